@@ -0,0 +1,28 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void child_work() {
+    printf("Child %d running\n", getpid());
+    fflush(stdout);
+    execl("/proc/self/exe", "fork", "exec", NULL);
+    perror("execl");
+    exit(1);
+}
+
+int main(int argc, char *argv[]) {
+    if (argc > 1 && strcmp(argv[1], "exec") == 0) {
+        printf("Process %d exec'd\n", getpid());
+        return 3;
+    }
+    pid_t pid = fork();
+    if (pid == 0) {
+        child_work();
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    printf("Parent %d saw child exit with %d\n", getpid(), WEXITSTATUS(status));
+    return 0;
+}
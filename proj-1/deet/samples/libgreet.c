@@ -0,0 +1,8 @@
+#include <stdio.h>
+
+int greetings = 0;
+
+void greet(const char *name) {
+    greetings++;
+    printf("Hello, %s! (greeting #%d)\n", name, greetings);
+}
@@ -0,0 +1,7 @@
+void greet(const char *name);
+
+int main() {
+    greet("world");
+    greet("deet");
+    return 0;
+}
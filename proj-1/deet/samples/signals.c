@@ -0,0 +1,20 @@
+#include <signal.h>
+#include <stdio.h>
+#include <unistd.h>
+
+volatile sig_atomic_t received = 0;
+
+void handler(int sig) {
+    received++;
+    printf("Caught signal %d\n", sig);
+}
+
+int main() {
+    signal(SIGUSR1, handler);
+    signal(SIGALRM, handler);
+    raise(SIGUSR1);
+    alarm(1);
+    pause();
+    printf("Received %d signals\n", received);
+    return 0;
+}
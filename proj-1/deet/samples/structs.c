@@ -0,0 +1,43 @@
+#include <stdio.h>
+#include <stdlib.h>
+
+enum color { RED, GREEN, BLUE };
+
+struct point {
+    int x;
+    int y;
+};
+
+typedef struct node {
+    int value;
+    struct point pos;
+    enum color color;
+    struct node *next;
+} node_t;
+
+union number {
+    int i;
+    float f;
+};
+
+const char greeting[] = "hello";
+int grid[2][3] = {{1, 2, 3}, {4, 5, 6}};
+
+int sum_list(node_t *head) {
+    int total = 0;
+    for (node_t *n = head; n != NULL; n = n->next) {
+        total += n->value;
+    }
+    return total;
+}
+
+int main() {
+    node_t second = {2, {3, 4}, BLUE, NULL};
+    node_t first = {1, {1, 2}, GREEN, &second};
+    int arr[5] = {10, 20, 30, 40, 50};
+    union number num;
+    num.f = 1.5;
+    printf("sum = %d\n", sum_list(&first));
+    printf("%s %d %d %f\n", greeting, arr[3], grid[1][2], num.f);
+    return 0;
+}
@@ -0,0 +1,14 @@
+#include <stdio.h>
+
+char letter = 'x';
+long big = -1234567890123;
+double ratio = 2.5;
+
+int main() {
+    char initial = 'd';
+    long offset = 9876543210;
+    double scale = -0.25;
+    long *where = &offset;
+    printf("%c %ld %f %p\n", initial, offset, scale, (void *)where);
+    return 0;
+}
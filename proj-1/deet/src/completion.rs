@@ -0,0 +1,131 @@
+//! Tab completion at the `(deet)` prompt: command names, `info` and `help` topics, and the
+//! functions and `file:line` locations in the debugging information.
+
+use crate::debugger_command::{lookup_command, COMMANDS};
+use crate::dwarf_data::DwarfData;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+const INFO_TOPICS: &[&str] = &[
+    "breakpoints",
+    "frame",
+    "inferiors",
+    "locals",
+    "record",
+    "registers",
+    "sharedlibrary",
+    "signals",
+    "threads",
+];
+
+pub struct DeetHelper {
+    functions: Vec<String>,
+    /// Base name of each source file, with the lines that have code.
+    source_lines: Vec<(String, Vec<usize>)>,
+}
+
+impl DeetHelper {
+    pub fn new() -> DeetHelper {
+        DeetHelper {
+            functions: Vec::new(),
+            source_lines: Vec::new(),
+        }
+    }
+
+    /// Takes the names to complete from `debug_data`, e.g. after a library was loaded.
+    pub fn update(&mut self, debug_data: &DwarfData) {
+        self.functions = debug_data.function_names();
+        self.source_lines = debug_data
+            .source_lines()
+            .into_iter()
+            .map(|(path, lines)| {
+                let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+                (name, lines)
+            })
+            .collect();
+    }
+
+    /// Returns where the word being completed starts, and the words it could be.
+    fn candidates(&self, line: &str) -> (usize, Vec<String>) {
+        let start = line.rfind(char::is_whitespace).map_or(0, |pos| pos + 1);
+        let word = &line[start..];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+        let candidates = match previous.first() {
+            None => COMMANDS
+                .iter()
+                .map(|cmd| cmd.name)
+                .filter(|name| name.starts_with(word))
+                .map(String::from)
+                .collect(),
+            Some(first) => match lookup_command(first).map(|cmd| cmd.name) {
+                Some("help") if previous.len() == 1 => COMMANDS
+                    .iter()
+                    .map(|cmd| cmd.name)
+                    .filter(|name| name.starts_with(word))
+                    .map(String::from)
+                    .collect(),
+                Some("info") if previous.len() == 1 => INFO_TOPICS
+                    .iter()
+                    .filter(|topic| topic.starts_with(word))
+                    .map(|topic| topic.to_string())
+                    .collect(),
+                Some("break") | Some("list") | Some("disassemble") if previous.len() == 1 => {
+                    self.locations(word)
+                }
+                _ => Vec::new(),
+            },
+        };
+        (start, candidates)
+    }
+
+    /// Completes a function name, a file name followed by ':', or a line in a file.
+    fn locations(&self, word: &str) -> Vec<String> {
+        if let Some(colon) = word.find(':') {
+            let (file, number) = (&word[..colon], &word[colon + 1..]);
+            return self
+                .source_lines
+                .iter()
+                .filter(|(name, _)| name == file)
+                .flat_map(|(_, lines)| lines.iter())
+                .map(|line| line.to_string())
+                .filter(|line| line.starts_with(number))
+                .map(|line| format!("{}:{}", file, line))
+                .collect();
+        }
+        let functions = self
+            .functions
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .cloned();
+        let files = self
+            .source_lines
+            .iter()
+            .filter(|(name, _)| name.starts_with(word))
+            .map(|(name, _)| format!("{}:", name));
+        functions.chain(files).collect()
+    }
+}
+
+impl Completer for DeetHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(&line[..pos]))
+    }
+}
+
+impl Hinter for DeetHelper {}
+
+impl Highlighter for DeetHelper {}
+
+impl Validator for DeetHelper {}
+
+impl Helper for DeetHelper {}
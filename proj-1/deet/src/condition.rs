@@ -0,0 +1,113 @@
+use std::fmt;
+
+/// The left-hand side of a breakpoint condition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    /// A local or global variable, looked up in the DWARF data when the breakpoint is hit.
+    Variable(String),
+    /// A general-purpose register such as `$rax` (stored without the leading `$`).
+    Register(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A condition attached to a breakpoint with `break <loc> if <operand> <op> <constant>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub op: Comparison,
+    pub rhs: i64,
+}
+
+impl Condition {
+    /// Parses expressions such as `i == 3`, `$rdi!=0x10` or `count >= -1`. Returns None if the
+    /// expression is not a comparison between a variable or register and an integer constant.
+    pub fn parse(expr: &str) -> Option<Condition> {
+        // Two-character operators must be tried first so that "<=" is not read as "<"
+        let operators = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        let (pos, len, op) = operators
+            .iter()
+            .find_map(|(text, op)| expr.find(text).map(|pos| (pos, text.len(), *op)))?;
+
+        let lhs = expr[..pos].trim();
+        let rhs = expr[pos + len..].trim();
+        let lhs = if lhs.starts_with('$') {
+            Operand::Register(lhs[1..].to_string())
+        } else {
+            Operand::Variable(lhs.to_string())
+        };
+        match &lhs {
+            Operand::Variable(name) | Operand::Register(name) => {
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return None;
+                }
+            }
+        }
+
+        Some(Condition {
+            lhs,
+            op,
+            rhs: parse_integer(rhs)?,
+        })
+    }
+
+    /// Returns true if the condition holds when its left-hand side has the given value.
+    pub fn holds(&self, value: i64) -> bool {
+        match self.op {
+            Comparison::Eq => value == self.rhs,
+            Comparison::Ne => value != self.rhs,
+            Comparison::Lt => value < self.rhs,
+            Comparison::Le => value <= self.rhs,
+            Comparison::Gt => value > self.rhs,
+            Comparison::Ge => value >= self.rhs,
+        }
+    }
+}
+
+/// Parses a decimal or 0x-prefixed hexadecimal integer, optionally negative.
+pub fn parse_integer(text: &str) -> Option<i64> {
+    let (negative, digits) = if text.starts_with('-') {
+        (true, &text[1..])
+    } else {
+        (false, text)
+    };
+    let value = if digits.to_lowercase().starts_with("0x") {
+        u64::from_str_radix(&digits[2..], 16).ok()? as i64
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.lhs {
+            Operand::Variable(name) => write!(f, "{}", name)?,
+            Operand::Register(name) => write!(f, "${}", name)?,
+        }
+        let op = match self.op {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, " {} {}", op, self.rhs)
+    }
+}
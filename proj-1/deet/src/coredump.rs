@@ -0,0 +1,523 @@
+//! ELF core files. `generate-core-file` writes the state of a stopped inferior to one, and
+//! `deet <program> --core <file>` reads one back so that it can be looked at without a live
+//! process. We handle the parts the kernel and gdb write for x86-64 Linux: a PT_NOTE segment
+//! with each thread's registers, the command line, the auxiliary vector and the mapped files,
+//! and a PT_LOAD segment for each mapping of memory.
+
+use crate::inferior::register_mut;
+use libc::user_regs_struct;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::io;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x4649_4c45;
+const PAGE_SIZE: usize = 4096;
+
+/// Size of `struct elf_prstatus`, and where the signal, thread id and registers are in it.
+const PRSTATUS_SIZE: usize = 336;
+const PRSTATUS_SIGNAL: usize = 12;
+const PRSTATUS_PID: usize = 32;
+const PRSTATUS_REGS: usize = 112;
+/// Size of `struct elf_prpsinfo`, and where the pid, program name and arguments are in it.
+const PRPSINFO_SIZE: usize = 136;
+const PRPSINFO_PID: usize = 24;
+const PRPSINFO_FNAME: usize = 40;
+const PRPSINFO_FNAME_SIZE: usize = 16;
+const PRPSINFO_PSARGS: usize = 56;
+const PRPSINFO_PSARGS_SIZE: usize = 80;
+
+/// The registers of `user_regs_struct`, in the order the kernel lays them out in NT_PRSTATUS.
+const PRSTATUS_REGISTERS: [&str; 27] = [
+    "r15", "r14", "r13", "r12", "rbp", "rbx", "r11", "r10", "r9", "r8", "rax", "rcx", "rdx", "rsi",
+    "rdi", "orig_rax", "rip", "cs", "eflags", "rsp", "ss", "fs_base", "gs_base", "ds", "es", "fs",
+    "gs",
+];
+
+fn prstatus_register<'a>(regs: &'a mut user_regs_struct, name: &str) -> &'a mut u64 {
+    match name {
+        "orig_rax" => &mut regs.orig_rax,
+        _ => register_mut(regs, name).unwrap(),
+    }
+}
+
+/// A mapping of the process's memory.
+pub struct Segment {
+    pub addr: usize,
+    pub size: usize,
+    /// PF_R, PF_W and PF_X.
+    pub flags: u32,
+    /// Contents of the mapping, or nothing if they could not be read.
+    pub data: Vec<u8>,
+}
+
+/// A file mapped into the process's memory, as listed in the NT_FILE note.
+pub struct MappedFile {
+    pub start: usize,
+    pub end: usize,
+    /// Offset in the file of the start of the mapping.
+    pub offset: usize,
+    pub path: String,
+}
+
+pub struct CoreFile {
+    pub pid: Pid,
+    /// Each thread's id and registers, starting with the thread that got `signal`.
+    pub threads: Vec<(Pid, user_regs_struct)>,
+    pub signal: Option<Signal>,
+    /// The command line the program was started with.
+    pub command: String,
+    /// The auxiliary vector, as in /proc/<pid>/auxv.
+    pub auxv: Vec<u8>,
+    pub files: Vec<MappedFile>,
+    pub segments: Vec<Segment>,
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+fn pad_to(out: &mut Vec<u8>, alignment: usize) {
+    while out.len() % alignment != 0 {
+        out.push(0);
+    }
+}
+
+/// Rounds `size` up to the 4-byte alignment of note names and descriptors.
+fn align4(size: usize) -> Option<usize> {
+    Some(size.checked_add(3)? / 4 * 4)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset.checked_add(8)?)?.try_into().ok()?) as usize)
+}
+
+/// Returns the NUL-terminated string in `bytes`.
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Copies `s` into `field`, leaving room for a NUL at the end.
+fn put_c_string(field: &mut [u8], s: &str) {
+    let len = s.len().min(field.len() - 1);
+    field[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
+
+/// Appends a note named "CORE", as the kernel names the notes it writes.
+fn put_note(out: &mut Vec<u8>, kind: u32, desc: &[u8]) {
+    put_u32(out, 5);
+    put_u32(out, desc.len() as u32);
+    put_u32(out, kind);
+    out.extend_from_slice(b"CORE\0");
+    pad_to(out, 4);
+    out.extend_from_slice(desc);
+    pad_to(out, 4);
+}
+
+impl CoreFile {
+    /// Reads the core file at `path`.
+    pub fn read(path: &str) -> io::Result<CoreFile> {
+        let bytes = fs::read(path)?;
+        CoreFile::parse(&bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an x86-64 core file"))
+    }
+
+    fn parse(bytes: &[u8]) -> Option<CoreFile> {
+        // 64-bit, little-endian ELF
+        if !bytes.starts_with(b"\x7fELF\x02\x01")
+            || u16_at(bytes, 16)? != ET_CORE
+            || u16_at(bytes, 18)? != EM_X86_64
+        {
+            return None;
+        }
+        let mut core = CoreFile {
+            pid: Pid::from_raw(0),
+            threads: Vec::new(),
+            signal: None,
+            command: String::new(),
+            auxv: Vec::new(),
+            files: Vec::new(),
+            segments: Vec::new(),
+        };
+        let header_offset = u64_at(bytes, 32)?;
+        let header_size = u16_at(bytes, 54)? as usize;
+        for index in 0..u16_at(bytes, 56)? as usize {
+            let header_start = index.checked_mul(header_size)?.checked_add(header_offset)?;
+            let header = bytes.get(header_start..)?;
+            let offset = u64_at(header, 8)?;
+            let file_size = u64_at(header, 32)?;
+            let contents = bytes.get(offset..offset.checked_add(file_size)?)?;
+            match u32_at(header, 0)? {
+                PT_NOTE => core.parse_notes(contents)?,
+                PT_LOAD => core.segments.push(Segment {
+                    addr: u64_at(header, 16)?,
+                    size: u64_at(header, 40)?,
+                    flags: u32_at(header, 4)?,
+                    data: contents.to_vec(),
+                }),
+                _ => {}
+            }
+        }
+        if core.threads.is_empty() {
+            return None;
+        }
+        if core.pid.as_raw() == 0 {
+            core.pid = core.threads[0].0;
+        }
+        Some(core)
+    }
+
+    fn parse_notes(&mut self, mut notes: &[u8]) -> Option<()> {
+        while !notes.is_empty() {
+            let name_size = u32_at(notes, 0)? as usize;
+            let desc_size = u32_at(notes, 4)? as usize;
+            let kind = u32_at(notes, 8)?;
+            let desc_offset = align4(name_size)?.checked_add(12)?;
+            let desc = notes.get(desc_offset..desc_offset.checked_add(desc_size)?)?;
+            match kind {
+                NT_PRSTATUS if desc.len() >= PRSTATUS_SIZE => {
+                    // The first thread is the one that got the signal
+                    if self.threads.is_empty() {
+                        let signal = u16_at(desc, PRSTATUS_SIGNAL)? as i32;
+                        self.signal = Signal::try_from(signal).ok();
+                    }
+                    let tid = Pid::from_raw(u32_at(desc, PRSTATUS_PID)? as i32);
+                    let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+                    for (index, name) in PRSTATUS_REGISTERS.iter().enumerate() {
+                        let value = u64_at(desc, PRSTATUS_REGS + index * 8)?;
+                        *prstatus_register(&mut regs, name) = value as u64;
+                    }
+                    self.threads.push((tid, regs));
+                }
+                NT_PRPSINFO if desc.len() >= PRPSINFO_SIZE => {
+                    self.pid = Pid::from_raw(u32_at(desc, PRPSINFO_PID)? as i32);
+                    self.command =
+                        c_string(&desc[PRPSINFO_PSARGS..PRPSINFO_PSARGS + PRPSINFO_PSARGS_SIZE]);
+                }
+                NT_AUXV => self.auxv = desc.to_vec(),
+                NT_FILE => {
+                    // A count and the page size, then where each file is mapped, then the names
+                    let count = u64_at(desc, 0)?;
+                    let page_size = u64_at(desc, 8)?;
+                    let names_start = count.checked_mul(24)?.checked_add(16)?;
+                    let mut names = desc.get(names_start..)?.split(|b| *b == 0);
+                    for index in 0..count {
+                        let entry = 16 + index * 24;
+                        self.files.push(MappedFile {
+                            start: u64_at(desc, entry)?,
+                            end: u64_at(desc, entry + 8)?,
+                            offset: u64_at(desc, entry + 16)?.checked_mul(page_size)?,
+                            path: String::from_utf8_lossy(names.next()?).into_owned(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+            notes = notes.get(desc_offset.checked_add(align4(desc_size)?)?..)?;
+        }
+        Some(())
+    }
+
+    /// Writes this core to `path`.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let notes = self.notes();
+        let num_headers = 1 + self.segments.len();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"\x7fELF\x02\x01\x01");
+        pad_to(&mut out, 16);
+        put_u16(&mut out, ET_CORE);
+        put_u16(&mut out, EM_X86_64);
+        put_u32(&mut out, 1);
+        // No entry point or section headers
+        put_u64(&mut out, 0);
+        put_u64(&mut out, ELF_HEADER_SIZE);
+        put_u64(&mut out, 0);
+        put_u32(&mut out, 0);
+        put_u16(&mut out, ELF_HEADER_SIZE as u16);
+        put_u16(&mut out, PROGRAM_HEADER_SIZE as u16);
+        put_u16(&mut out, num_headers as u16);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+
+        // The notes come right after the program headers, and each segment's contents start on
+        // a page of their own
+        let mut offset = ELF_HEADER_SIZE + num_headers * PROGRAM_HEADER_SIZE;
+        let mut put_header = |out: &mut Vec<u8>, kind, flags, addr, file_size, size, align| {
+            if align > 1 {
+                offset = (offset + align - 1) / align * align;
+            }
+            put_u32(out, kind);
+            put_u32(out, flags);
+            put_u64(out, offset);
+            put_u64(out, addr);
+            put_u64(out, 0);
+            put_u64(out, file_size);
+            put_u64(out, size);
+            put_u64(out, align);
+            offset += file_size;
+        };
+        put_header(&mut out, PT_NOTE, 0, 0, notes.len(), 0, 1);
+        for segment in &self.segments {
+            let data_len = segment.data.len();
+            put_header(
+                &mut out,
+                PT_LOAD,
+                segment.flags,
+                segment.addr,
+                data_len,
+                segment.size,
+                PAGE_SIZE,
+            );
+        }
+        out.extend_from_slice(&notes);
+        for segment in &self.segments {
+            pad_to(&mut out, PAGE_SIZE);
+            out.extend_from_slice(&segment.data);
+        }
+        fs::write(path, out)
+    }
+
+    fn notes(&self) -> Vec<u8> {
+        let mut notes = Vec::new();
+        for (index, (tid, regs)) in self.threads.iter().enumerate() {
+            put_note(&mut notes, NT_PRSTATUS, &self.prstatus(*tid, regs));
+            // Like the kernel, describe the process after its first thread
+            if index == 0 {
+                put_note(&mut notes, NT_PRPSINFO, &self.prpsinfo());
+                put_note(&mut notes, NT_AUXV, &self.auxv);
+                put_note(&mut notes, NT_FILE, &self.file_note());
+            }
+        }
+        notes
+    }
+
+    fn prstatus(&self, tid: Pid, regs: &user_regs_struct) -> Vec<u8> {
+        let mut desc = vec![0; PRSTATUS_SIZE];
+        let signal = self.signal.map_or(0, |signal| signal as i32);
+        desc[..4].copy_from_slice(&signal.to_le_bytes());
+        desc[PRSTATUS_SIGNAL..PRSTATUS_SIGNAL + 2].copy_from_slice(&(signal as u16).to_le_bytes());
+        desc[PRSTATUS_PID..PRSTATUS_PID + 4].copy_from_slice(&tid.as_raw().to_le_bytes());
+        let mut regs = *regs;
+        for (index, name) in PRSTATUS_REGISTERS.iter().enumerate() {
+            let offset = PRSTATUS_REGS + index * 8;
+            desc[offset..offset + 8]
+                .copy_from_slice(&prstatus_register(&mut regs, name).to_le_bytes());
+        }
+        desc
+    }
+
+    fn prpsinfo(&self) -> Vec<u8> {
+        let mut desc = vec![0; PRPSINFO_SIZE];
+        desc[PRPSINFO_PID..PRPSINFO_PID + 4].copy_from_slice(&self.pid.as_raw().to_le_bytes());
+        let program = self.command.split(' ').next().unwrap_or("");
+        let name = program.rsplit('/').next().unwrap_or(program);
+        put_c_string(
+            &mut desc[PRPSINFO_FNAME..PRPSINFO_FNAME + PRPSINFO_FNAME_SIZE],
+            name,
+        );
+        put_c_string(
+            &mut desc[PRPSINFO_PSARGS..PRPSINFO_PSARGS + PRPSINFO_PSARGS_SIZE],
+            &self.command,
+        );
+        desc
+    }
+
+    fn file_note(&self) -> Vec<u8> {
+        let mut desc = Vec::new();
+        put_u64(&mut desc, self.files.len());
+        put_u64(&mut desc, PAGE_SIZE);
+        for file in &self.files {
+            put_u64(&mut desc, file.start);
+            put_u64(&mut desc, file.end);
+            put_u64(&mut desc, file.offset / PAGE_SIZE);
+        }
+        for file in &self.files {
+            desc.extend_from_slice(file.path.as_bytes());
+            desc.push(0);
+        }
+        desc
+    }
+
+    /// Returns the registers of thread `tid`.
+    pub fn registers(&self, tid: Pid) -> Option<user_regs_struct> {
+        self.threads
+            .iter()
+            .find(|(t, _)| *t == tid)
+            .map(|(_, regs)| *regs)
+    }
+
+    /// Reads `len` bytes of memory at `addr`, if the core has them.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| addr >= segment.addr && addr - segment.addr < segment.size)?;
+        let start = addr - segment.addr;
+        segment
+            .data
+            .get(start..start.checked_add(len)?)
+            .map(<[u8]>::to_vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_core() -> CoreFile {
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rip = 0x401136;
+        regs.rsp = 0x7ffe_0000_0ff0;
+        regs.orig_rax = u64::MAX;
+        let mut other = regs;
+        other.rip = 0x401200;
+        CoreFile {
+            pid: Pid::from_raw(1234),
+            threads: vec![(Pid::from_raw(1235), regs), (Pid::from_raw(1234), other)],
+            signal: Some(Signal::SIGSEGV),
+            command: "/tmp/segfault --verbose".to_string(),
+            auxv: vec![9, 0, 0, 0, 0, 0, 0, 0, 0x30, 0x10, 0x40, 0, 0, 0, 0, 0],
+            files: vec![MappedFile {
+                start: 0x400000,
+                end: 0x401000,
+                offset: 0x1000,
+                path: "/tmp/segfault".to_string(),
+            }],
+            segments: vec![
+                Segment {
+                    addr: 0x400000,
+                    size: 0x1000,
+                    flags: PF_R | PF_X,
+                    data: (0..0x1000).map(|i| i as u8).collect(),
+                },
+                Segment {
+                    addr: 0x7fff_f7ff_0000,
+                    size: 0x2000,
+                    flags: PF_R,
+                    data: Vec::new(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn write_and_read() {
+        let path = std::env::temp_dir().join(format!("deet-core-test.{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        sample_core().write(path).unwrap();
+        let core = CoreFile::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(core.pid, Pid::from_raw(1234));
+        assert_eq!(core.signal, Some(Signal::SIGSEGV));
+        assert_eq!(core.command, "/tmp/segfault --verbose");
+        assert_eq!(core.threads.len(), 2);
+        let regs = core.registers(Pid::from_raw(1235)).unwrap();
+        assert_eq!(
+            (regs.rip, regs.rsp, regs.orig_rax),
+            (0x401136, 0x7ffe_0000_0ff0, u64::MAX)
+        );
+        assert_eq!(core.registers(Pid::from_raw(1234)).unwrap().rip, 0x401200);
+        assert_eq!(core.auxv, sample_core().auxv);
+        assert_eq!(core.files.len(), 1);
+        assert_eq!(
+            (core.files[0].start, core.files[0].end, core.files[0].offset),
+            (0x400000, 0x401000, 0x1000)
+        );
+        assert_eq!(core.files[0].path, "/tmp/segfault");
+        assert_eq!(core.segments.len(), 2);
+        assert_eq!(core.segments[0].flags, PF_R | PF_X);
+        assert_eq!(
+            core.read_memory(0x400010, 4),
+            Some(vec![0x10, 0x11, 0x12, 0x13])
+        );
+        assert_eq!(core.read_memory(0x400ffe, 4), None);
+        // Mapped, but its contents were not saved
+        assert_eq!(core.read_memory(0x7fff_f7ff_0000, 1), None);
+    }
+
+    #[test]
+    fn reject_other_files() {
+        assert!(CoreFile::parse(b"").is_none());
+        assert!(CoreFile::parse(b"#!/bin/sh\n").is_none());
+        let mut executable = vec![0; ELF_HEADER_SIZE];
+        executable[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+        executable[16] = 2;
+        executable[18] = EM_X86_64 as u8;
+        assert!(CoreFile::parse(&executable).is_none());
+    }
+
+    #[test]
+    fn reject_bad_program_headers() {
+        let path = std::env::temp_dir().join(format!("deet-core-bad.{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        sample_core().write(path).unwrap();
+        let mut bytes = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        // Program headers that would start past the end of the address space
+        bytes[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(CoreFile::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn reject_bad_notes() {
+        let mut core = sample_core();
+        // A descriptor that runs past the end of the notes
+        let mut note = Vec::new();
+        for value in &[4u32, u32::MAX, NT_AUXV] {
+            note.extend_from_slice(&value.to_le_bytes());
+        }
+        note.extend_from_slice(b"CORE");
+        assert!(core.parse_notes(&note).is_none());
+    }
+
+    #[test]
+    fn read_memory_at_the_top_of_memory() {
+        let mut core = sample_core();
+        core.segments = vec![Segment {
+            addr: usize::MAX - 0xfff,
+            size: 0x1000,
+            flags: PF_R,
+            data: vec![0xaa; 0x1000],
+        }];
+        assert_eq!(core.read_memory(usize::MAX, 1), Some(vec![0xaa]));
+        assert_eq!(core.read_memory(usize::MAX, 2), None);
+        assert_eq!(core.read_memory(0x1000, 1), None);
+    }
+}
@@ -0,0 +1,529 @@
+//! `deet --dap`: a Debug Adapter Protocol server on stdin and stdout, for editors that speak it.
+//! Requests are carried out by the same `Debugger` that serves the `(deet)` prompt, and what it
+//! reports comes back to us as `output::Event`s, which we turn into protocol messages. As with
+//! `--gdbserver`, the inferior can't be interrupted while it is running.
+
+use crate::debugger::Debugger;
+use crate::debugger_command::DebuggerCommand;
+use crate::json::Json;
+use crate::output::{self, Event, Frame};
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Sequence number of the next message we send.
+static NEXT_SEQ: AtomicUsize = AtomicUsize::new(1);
+
+const NOT_LAUNCHED: &str = "No program has been launched";
+
+/// Largest message body we accept. Requests are small, so anything bigger is a broken client.
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+/// Stack frame ids stand for a thread and a level within it, as `thread * FRAMES_PER_THREAD +
+/// level`, since the client may look at frames of several threads while the program is stopped.
+const FRAMES_PER_THREAD: usize = 1 << 16;
+
+/// Sends a message of type `message_type` ("response" or "event") to the client.
+fn send(message_type: &str, mut members: Vec<(&str, Json)>) {
+    members.insert(0, ("seq", NEXT_SEQ.fetch_add(1, Ordering::SeqCst).into()));
+    members.insert(1, ("type", message_type.into()));
+    let body = Json::object(members).to_string();
+    // Events come from the threads that forward the inferior's output too, so each message is
+    // written while holding the lock
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    // If the client has gone away there is nobody left to tell
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = stdout.flush();
+}
+
+fn send_event_message(event: &str, body: Json) {
+    send("event", vec![("event", event.into()), ("body", body)]);
+}
+
+/// Passes on an event from the debugger, if the protocol has a message for it. The rest are
+/// only of use to the requests that capture them.
+pub fn send_event(event: &Event) {
+    match event {
+        Event::Console(text) => send_event_message(
+            "output",
+            Json::object(vec![
+                ("category", "console".into()),
+                ("output", format!("{}\n", text).into()),
+            ]),
+        ),
+        Event::ProgramOutput { stream, text } => send_event_message(
+            "output",
+            Json::object(vec![
+                ("category", (*stream).into()),
+                ("output", text.as_str().into()),
+            ]),
+        ),
+        Event::Stopped {
+            reason,
+            signal,
+            thread,
+            breakpoint,
+            ..
+        } => {
+            let reason = match *reason {
+                "breakpoint-hit" => "breakpoint",
+                "watchpoint-trigger" => "data breakpoint",
+                "end-stepping-range" => "step",
+                _ => "exception",
+            };
+            let mut body = vec![
+                ("reason", reason.into()),
+                ("threadId", (*thread).into()),
+                ("allThreadsStopped", true.into()),
+            ];
+            if reason == "exception" {
+                body.push(("text", signal.as_str().into()));
+            }
+            if let Some(id) = breakpoint {
+                body.push(("hitBreakpointIds", Json::Array(vec![(*id).into()])));
+            }
+            send_event_message("stopped", Json::object(body));
+        }
+        Event::Exited { code, signal } => {
+            // Like a shell, report death by a signal as 128 plus the signal's number
+            let exit_code = code.unwrap_or_else(|| {
+                signal
+                    .as_ref()
+                    .and_then(|name| Signal::from_str(name).ok())
+                    .map_or(128, |signal| 128 + signal as i32)
+            });
+            send_event_message("exited", Json::object(vec![("exitCode", exit_code.into())]));
+            send_event_message("terminated", Json::object(vec![]));
+        }
+        _ => {}
+    }
+}
+
+/// Reads a message: headers, a blank line, then a body as long as the Content-Length header
+/// says. Returns None at the end of input, and an InvalidData error if the Content-Length header
+/// is missing, not a number or too large.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    let length = loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            match length {
+                Some(length) => break length,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "message has no Content-Length header",
+                    ))
+                }
+            }
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            match value.trim().parse::<usize>() {
+                Ok(value) => length = Some(value),
+                Err(err) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad Content-Length \"{}\": {}", value.trim(), err),
+                    ))
+                }
+            }
+        }
+    };
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Content-Length {} is over the limit of {}", length, MAX_MESSAGE_SIZE),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+/// What to do once the response to a request has been sent.
+enum Then {
+    Nothing,
+    /// Tell the client it can send its breakpoints.
+    Initialized,
+    /// Carry out a command that resumes the inferior. The client expects to hear that it is
+    /// running before hearing why it stopped.
+    Execute(DebuggerCommand),
+}
+
+struct Adapter {
+    /// Created by `launch`.
+    debugger: Option<Debugger>,
+    /// Arguments to run the program with, from `launch`.
+    args: Vec<String>,
+    /// Ids of the breakpoints set in each source file. The client sends all of a file's
+    /// breakpoints every time, so they replace the ones it sent before.
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    /// Set once the client disconnects.
+    done: bool,
+}
+
+/// Serves one client on stdin and stdout until it disconnects, closes stdin or sends something
+/// that isn't a message.
+pub fn serve() -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut adapter = Adapter {
+        debugger: None,
+        args: Vec::new(),
+        source_breakpoints: HashMap::new(),
+        done: false,
+    };
+    let mut result = Ok(());
+    while !adapter.done {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        };
+        match Json::parse(&message) {
+            Ok(request) => adapter.run_request(&request),
+            Err(err) => console!("Invalid message: {}", err),
+        }
+    }
+    // Don't leave the inferior behind if the client went away without disconnecting
+    if let Some(debugger) = adapter.debugger.as_mut() {
+        output::capture(|| debugger.execute(DebuggerCommand::Quit));
+    }
+    result
+}
+
+impl Adapter {
+    /// Carries out `request` and sends the response, followed by any events it leads to.
+    fn run_request(&mut self, request: &Json) {
+        let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+        let result = self.handle(command, &arguments);
+        let mut response = vec![
+            (
+                "request_seq",
+                request.get("seq").cloned().unwrap_or(Json::Null),
+            ),
+            ("success", result.is_ok().into()),
+            ("command", command.into()),
+        ];
+        let then = match result {
+            Ok((body, then)) => {
+                response.push(("body", body));
+                then
+            }
+            Err(message) => {
+                response.push(("message", message.into()));
+                Then::Nothing
+            }
+        };
+        send("response", response);
+        match then {
+            Then::Nothing => {}
+            Then::Initialized => send_event_message("initialized", Json::object(vec![])),
+            Then::Execute(cmd) => {
+                if let Some(debugger) = self.debugger.as_mut() {
+                    debugger.execute(cmd);
+                }
+            }
+        }
+    }
+
+    /// Carries out the request called `command`, and returns the body of the response and what
+    /// to do after sending it, or the reason it failed.
+    fn handle(&mut self, command: &str, arguments: &Json) -> Result<(Json, Then), String> {
+        match command {
+            "initialize" => Ok((
+                Json::object(vec![
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsConditionalBreakpoints", true.into()),
+                ]),
+                Then::Nothing,
+            )),
+            "launch" => {
+                self.launch(arguments)?;
+                Ok((Json::Null, Then::Initialized))
+            }
+            "setBreakpoints" => Ok((self.set_breakpoints(arguments)?, Then::Nothing)),
+            "configurationDone" => {
+                self.debugger()?;
+                Ok((
+                    Json::Null,
+                    Then::Execute(DebuggerCommand::Run(self.args.clone())),
+                ))
+            }
+            "threads" => Ok((self.threads()?, Then::Nothing)),
+            "stackTrace" => Ok((self.stack_trace(arguments)?, Then::Nothing)),
+            "scopes" => {
+                let frame = arguments
+                    .get("frameId")
+                    .and_then(Json::as_usize)
+                    .ok_or("Missing frameId")?;
+                // Variable references must not be 0, so the locals of frame id N are reference
+                // N + 1
+                let scope = Json::object(vec![
+                    ("name", "Locals".into()),
+                    ("variablesReference", (frame + 1).into()),
+                    ("expensive", false.into()),
+                ]);
+                Ok((
+                    Json::object(vec![("scopes", Json::Array(vec![scope]))]),
+                    Then::Nothing,
+                ))
+            }
+            "variables" => Ok((self.variables(arguments)?, Then::Nothing)),
+            "continue" => {
+                self.debugger()?;
+                Ok((
+                    Json::object(vec![("allThreadsContinued", true.into())]),
+                    Then::Execute(DebuggerCommand::Continue),
+                ))
+            }
+            "next" => {
+                self.debugger()?;
+                Ok((Json::Null, Then::Execute(DebuggerCommand::Next)))
+            }
+            "stepIn" => {
+                self.debugger()?;
+                Ok((Json::Null, Then::Execute(DebuggerCommand::Step)))
+            }
+            "stepOut" => {
+                self.debugger()?;
+                Ok((Json::Null, Then::Execute(DebuggerCommand::Finish)))
+            }
+            "disconnect" => {
+                self.done = true;
+                if let Some(debugger) = self.debugger.as_mut() {
+                    output::capture(|| debugger.execute(DebuggerCommand::Quit));
+                }
+                Ok((Json::Null, Then::Nothing))
+            }
+            _ => Err(format!("Unsupported request \"{}\"", command)),
+        }
+    }
+
+    fn debugger(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger
+            .as_mut()
+            .ok_or_else(|| NOT_LAUNCHED.to_string())
+    }
+
+    /// Loads the program to debug. It is run once the client is done setting breakpoints.
+    fn launch(&mut self, arguments: &Json) -> Result<(), String> {
+        let program = arguments
+            .get("program")
+            .and_then(Json::as_str)
+            .ok_or("Missing program to launch")?;
+        let args = match arguments.get("args") {
+            Some(args) => args
+                .as_array()
+                .and_then(|args| {
+                    args.iter()
+                        .map(|arg| arg.as_str().map(String::from))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .ok_or("Program arguments must be strings")?,
+            None => Vec::new(),
+        };
+        self.debugger = Some(Debugger::open(program)?);
+        self.args = args;
+        Ok(())
+    }
+
+    /// Replaces the breakpoints in a source file with those at the lines the client gives.
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let source = arguments.get("source").cloned().unwrap_or(Json::Null);
+        let path = source
+            .get("path")
+            .and_then(Json::as_str)
+            .ok_or("Missing source path")?;
+        let requested: Vec<(usize, Option<String>)> = arguments
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|bp| {
+                let condition = bp.get("condition").and_then(Json::as_str);
+                Some((bp.get("line")?.as_usize()?, condition.map(String::from)))
+            })
+            .collect();
+        let old_ids = self.source_breakpoints.remove(path).unwrap_or_default();
+        let debugger = self.debugger.as_mut().ok_or(NOT_LAUNCHED)?;
+        for id in old_ids {
+            output::capture(|| debugger.execute(DebuggerCommand::Delete(id)));
+        }
+
+        let mut ids = Vec::new();
+        let mut breakpoints = Vec::new();
+        for (line, condition) in requested {
+            let location = format!("{}:{}", path, line);
+            let (_, events) =
+                output::capture(|| debugger.execute(DebuggerCommand::Break(location, condition)));
+            let created = events.iter().find_map(|event| match event {
+                Event::BreakpointCreated { id, line, .. } => Some((*id, *line)),
+                _ => None,
+            });
+            breakpoints.push(match created {
+                Some((id, actual_line)) => {
+                    ids.push(id);
+                    Json::object(vec![
+                        ("id", id.into()),
+                        ("verified", true.into()),
+                        ("line", actual_line.unwrap_or(line).into()),
+                        ("source", source.clone()),
+                    ])
+                }
+                // Say why, using the last thing the debugger printed
+                None => {
+                    let message = events.iter().rev().find_map(|event| match event {
+                        Event::Console(text) => Some(text.as_str()),
+                        _ => None,
+                    });
+                    Json::object(vec![
+                        ("verified", false.into()),
+                        ("line", line.into()),
+                        ("message", message.into()),
+                    ])
+                }
+            });
+        }
+        self.source_breakpoints.insert(path.to_string(), ids);
+        Ok(Json::object(vec![(
+            "breakpoints",
+            Json::Array(breakpoints),
+        )]))
+    }
+
+    fn threads(&mut self) -> Result<Json, String> {
+        let debugger = self.debugger()?;
+        let (_, events) = output::capture(|| debugger.execute(DebuggerCommand::InfoThreads));
+        let threads = events
+            .into_iter()
+            .find_map(|event| match event {
+                Event::Threads { threads, .. } => Some(threads),
+                _ => None,
+            })
+            .unwrap_or_default();
+        Ok(Json::object(vec![(
+            "threads",
+            Json::Array(
+                threads
+                    .into_iter()
+                    .map(|(id, tid)| {
+                        Json::object(vec![
+                            ("id", id.into()),
+                            ("name", format!("Thread {} (LWP {})", id, tid).into()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        )]))
+    }
+
+    fn stack_trace(&mut self, arguments: &Json) -> Result<Json, String> {
+        let thread = arguments
+            .get("threadId")
+            .and_then(Json::as_usize)
+            .ok_or("Missing threadId")?;
+        let debugger = self.debugger()?;
+        let (_, events) = output::capture(|| {
+            debugger.execute(DebuggerCommand::Thread(Some(thread)));
+            debugger.execute(DebuggerCommand::Backtrace)
+        });
+        let frames = events
+            .into_iter()
+            .find_map(|event| match event {
+                Event::Backtrace(frames) => Some(frames),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let total = frames.len();
+        let start = arguments
+            .get("startFrame")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        // No number of levels, or 0, means all of them
+        let levels = match arguments.get("levels").and_then(Json::as_usize) {
+            Some(0) | None => total,
+            Some(levels) => levels,
+        };
+        Ok(Json::object(vec![
+            (
+                "stackFrames",
+                Json::Array(
+                    frames
+                        .into_iter()
+                        .skip(start)
+                        .take(levels)
+                        .map(|frame| stack_frame(thread, frame))
+                        .collect(),
+                ),
+            ),
+            ("totalFrames", total.into()),
+        ]))
+    }
+
+    /// Lists the locals of the frame that `variablesReference` came from.
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let frame_id = arguments
+            .get("variablesReference")
+            .and_then(Json::as_usize)
+            .and_then(|reference| reference.checked_sub(1))
+            .ok_or("Invalid variablesReference")?;
+        let (thread, level) = (frame_id / FRAMES_PER_THREAD, frame_id % FRAMES_PER_THREAD);
+        let debugger = self.debugger()?;
+        let (_, events) = output::capture(|| {
+            debugger.execute(DebuggerCommand::Thread(Some(thread)));
+            debugger.execute(DebuggerCommand::Frame(Some(level)));
+            debugger.execute(DebuggerCommand::InfoLocals);
+            // Stepping is always from the innermost frame, whatever the client looks at
+            debugger.execute(DebuggerCommand::Frame(Some(0)))
+        });
+        let variables = events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Value { expression, value } => Some(Json::object(vec![
+                    ("name", expression.into()),
+                    ("value", value.into()),
+                    ("variablesReference", 0usize.into()),
+                ])),
+                _ => None,
+            })
+            .collect();
+        Ok(Json::object(vec![("variables", Json::Array(variables))]))
+    }
+}
+
+/// Describes `frame` of thread number `thread` to the client.
+fn stack_frame(thread: usize, frame: Frame) -> Json {
+    let mut members = vec![
+        ("id", (thread * FRAMES_PER_THREAD + frame.level).into()),
+        (
+            "name",
+            frame.function.unwrap_or_else(|| "??".to_string()).into(),
+        ),
+        ("line", frame.line.unwrap_or(0).into()),
+        ("column", 0usize.into()),
+        (
+            "instructionPointerReference",
+            format!("{:#x}", frame.addr).into(),
+        ),
+    ];
+    if let Some(file) = frame.file {
+        let name = file.rsplit('/').next().unwrap_or(&file).to_string();
+        members.push((
+            "source",
+            Json::object(vec![("name", name.into()), ("path", file.into())]),
+        ));
+    }
+    Json::object(members)
+}
@@ -1,301 +1,2876 @@
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::{Inferior, Status};
+use crate::completion::DeetHelper;
+use crate::condition::{Condition, Operand};
+use crate::coredump::CoreFile;
+use crate::debugger_command::{self, DebuggerCommand, ExamineFormat, ForkMode};
+use crate::disassembler::{self, MAX_INSTRUCTION_LEN};
+use crate::expression;
+use crate::json::Json;
+use crate::output::{self, Event, Frame, Mode};
+use crate::record::{self, Recording};
+use crate::dwarf_data::{
+    DwarfData, Error as DwarfError, Function, Line, Location, Type, TypeKind, Variable,
+};
+use crate::inferior::{Inferior, StackFrame, Status, REGISTER_NAMES};
+use crate::signals::{self, SignalTable};
+use crate::source::{SourceCache, LIST_SIZE};
+use crate::unwind;
 use rustyline::error::ReadlineError;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use rustyline::Editor;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::fs;
+use std::mem::size_of;
 
 #[derive(Clone)]
 pub struct Breakpoint {
+    /// Number shown to the user. Stays the same for the lifetime of the breakpoint.
+    pub id: usize,
+    /// What the user asked to break at, e.g. "main", "12" or "*0x401126", so that the breakpoint
+    /// can be set again in a new program after an exec.
+    pub location: String,
     pub addr: usize,
     pub orig_byte: u8,
+    /// Disabled breakpoints are kept around, but their 0xcc is not planted in the inferior.
+    pub enabled: bool,
+    /// Only stop if this holds when the breakpoint is reached.
+    pub condition: Option<Condition>,
+    /// Number of times the breakpoint was reached with its condition holding.
+    pub hit_count: usize,
+    /// Number of upcoming hits to resume through without stopping.
+    pub ignore_count: usize,
+    /// Lines given with `commands`, run as if typed at the prompt whenever the breakpoint stops
+    /// the inferior.
+    pub commands: Vec<String>,
+}
+
+impl Breakpoint {
+    /// Creates an enabled, unnumbered breakpoint that is planted while the inferior runs to some
+    /// address (e.g. the return address for `finish`).
+    pub fn temporary(addr: usize, orig_byte: u8) -> Breakpoint {
+        Breakpoint {
+            id: usize::MAX,
+            location: String::new(),
+            addr,
+            orig_byte,
+            enabled: true,
+            condition: None,
+            hit_count: 0,
+            ignore_count: 0,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Creates a breakpoint that the debugger keeps planted for its own use, e.g. on the dynamic
+    /// linker's hook for library loads. Internal breakpoints are not numbered or listed.
+    pub fn internal(addr: usize, orig_byte: u8) -> Breakpoint {
+        Breakpoint::temporary(addr, orig_byte)
+    }
+
+    pub fn is_internal(&self) -> bool {
+        self.id == usize::MAX
+    }
+}
+
+/// Offsets within the dynamic linker's `struct r_debug` and `struct link_map` (see <link.h>).
+const R_DEBUG_MAP: usize = 8;
+const R_DEBUG_STATE: usize = 24;
+const LINK_MAP_ADDR: usize = 0;
+const LINK_MAP_NAME: usize = 8;
+const LINK_MAP_NEXT: usize = 24;
+/// `r_state` once the dynamic linker has finished adding or removing libraries.
+const RT_CONSISTENT: u32 = 0;
+
+/// A watchpoint on a variable or address, backed by one of the x86 debug registers while the
+/// inferior runs. Numbered alongside breakpoints.
+#[derive(Clone)]
+pub struct Watchpoint {
+    pub id: usize,
+    /// What the user asked to watch, e.g. "global" or "*0x404028".
+    pub expr: String,
+    pub addr: usize,
+    pub entity_type: Type,
+    pub enabled: bool,
+    /// Debug register slot holding this watchpoint in the current inferior.
+    pub slot: Option<usize>,
+    /// For a local variable, the frame it lives in. The watchpoint is deleted once that frame
+    /// returns.
+    pub scope: Option<WatchpointScope>,
+}
+
+/// The stack frame a watched local variable belongs to.
+#[derive(Clone)]
+pub struct WatchpointScope {
+    pub tid: Pid,
+    pub return_address: usize,
+    pub cfa: usize,
 }
 
 pub struct Debugger {
     target: String,
     history_path: String,
-    readline: Editor<()>,
+    readline: Editor<DeetHelper>,
     inferior: Option<Inferior>,
     debug_data: DwarfData,
     breakpoints: HashMap<usize, Breakpoint>,
+    /// Breakpoints whose location is not in the program or any library loaded so far. They are
+    /// set as libraries get loaded.
+    pending_breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_breakpoint_id: usize,
+    sources: SourceCache,
+    /// File and last line printed by the previous `list`, so that another `list` continues
+    /// from there.
+    last_listed: Option<(String, usize)>,
+    /// Level of the frame selected with `up`, `down` or `frame`, counting out from the innermost
+    /// frame. Variables are looked up in this frame.
+    selected_frame: usize,
+    signals: SignalTable,
+    /// Signal the inferior last stopped with, to be delivered when it is next continued if the
+    /// `handle` table says to pass it.
+    pending_signal: Option<Signal>,
+    /// Thread the inferior last stopped in, so that we can say when a different one stops.
+    stopped_thread: Option<Pid>,
+    /// Number of the current inferior, as shown by `info inferiors`.
+    inferior_id: usize,
+    next_inferior_id: usize,
+    /// Other processes we still trace, with their numbers. They were left stopped by a fork
+    /// while `detach-on-fork` was off, and stay stopped until selected with `inferior`.
+    suspended: Vec<(usize, Inferior)>,
+    follow_fork_mode: ForkMode,
+    detach_on_fork: bool,
+    /// Address of the dynamic linker's `_r_debug`, which lists the loaded shared libraries.
+    r_debug: Option<usize>,
+    /// Lines to run before reading any more from the terminal: sourced scripts, and the commands
+    /// of a breakpoint that was just hit.
+    pending_input: VecDeque<String>,
+    /// Quit once `pending_input` runs out rather than prompting, as with `--batch`.
+    batch: bool,
+    /// Id of the request from a front end being carried out, in JSON mode.
+    request: Option<Json>,
 }
 
 impl Debugger {
     /// Initializes the debugger.
     pub fn new(target: &str) -> Debugger {
+        match Debugger::open(target) {
+            Ok(debugger) => debugger,
+            Err(err) => {
+                console!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Initializes the debugger, or says why `target` could not be loaded.
+    pub fn open(target: &str) -> Result<Debugger, String> {
         // TODO (milestone 3): initialize the DwarfData
         let debug_data = match DwarfData::from_file(target) {
             Ok(val) => val,
             Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
+                return Err(format!("Could not open file {}", target));
             }
 
             Err(DwarfError::DwarfFormatError(err)) => {
-                println!("Could not debugging symbols from {}: {:?}", target, err);
-                std::process::exit(1);
+                return Err(format!("Could not debugging symbols from {}: {:?}", target, err));
             }
         };
 
-        debug_data.print();
+        // A front end has no use for this, and it would bury the replies it is waiting for
+        if output::mode() == Mode::Text {
+            debug_data.print();
+        }
 
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<()>::new();
+        let mut readline = Editor::<DeetHelper>::new();
+        let mut helper = DeetHelper::new();
+        helper.update(&debug_data);
+        readline.set_helper(Some(helper));
         // Attempt to load history from ~/.deet_history if it exists
         let _ = readline.load_history(&history_path);
 
-        Debugger {
+        Ok(Debugger {
             target: target.to_string(),
             history_path,
             readline,
             inferior: None,
             debug_data,
             breakpoints: HashMap::new(),
+            pending_breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_breakpoint_id: 0,
+            sources: SourceCache::new(),
+            last_listed: None,
+            selected_frame: 0,
+            signals: SignalTable::new(),
+            pending_signal: None,
+            stopped_thread: None,
+            inferior_id: 1,
+            next_inferior_id: 2,
+            suspended: Vec::new(),
+            follow_fork_mode: ForkMode::Parent,
+            detach_on_fork: true,
+            r_debug: None,
+            pending_input: VecDeque::new(),
+            batch: false,
+            request: None,
+        })
+    }
+
+    /// Queues the commands in the files at `paths` to run, in order, ahead of anything already
+    /// queued. Blank lines and lines starting with '#' are skipped.
+    pub fn source(&mut self, paths: &[String]) {
+        let mut lines = Vec::new();
+        for path in paths {
+            match fs::read_to_string(path) {
+                Ok(contents) => lines.extend(
+                    contents
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(String::from),
+                ),
+                Err(err) => console!("{}: {}.", path, err),
+            }
+        }
+        self.queue_input(lines);
+    }
+
+    /// In batch mode, the debugger quits after running the queued commands instead of prompting.
+    pub fn set_batch(&mut self, batch: bool) {
+        self.batch = batch;
+    }
+
+    fn queue_input(&mut self, lines: Vec<String>) {
+        for line in lines.into_iter().rev() {
+            self.pending_input.push_front(line);
         }
     }
 
     pub fn run(&mut self) {
         loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    match &mut self.inferior {
-                        Some(inf) => match inf.kill() {
+            let cmd = self.get_next_command();
+            let keep_going = self.execute(cmd);
+            self.finish_request();
+            if !keep_going {
+                return;
+            }
+        }
+    }
+
+    /// Carries out `cmd`, whether it was typed at the prompt, read from a script or sent by a
+    /// front end. Returns false once the debugger should exit.
+    pub fn execute(&mut self, cmd: DebuggerCommand) -> bool {
+        // A core dump can be looked at, but not run
+        if self.inferior.as_ref().map_or(false, Inferior::is_core) {
+            match cmd {
+                DebuggerCommand::Continue
+                | DebuggerCommand::StepInstruction
+                | DebuggerCommand::Step
+                | DebuggerCommand::Next
+                | DebuggerCommand::Finish
+                | DebuggerCommand::Record
+                | DebuggerCommand::ReverseStepInstruction
+                | DebuggerCommand::ReverseStep
+                | DebuggerCommand::ReverseContinue
+                | DebuggerCommand::Detach => {
+                    console!("The program is not being run.");
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        match cmd {
+            DebuggerCommand::Run(args) => {
+                match &mut self.inferior {
+                    Some(inf) if inf.is_core() => {}
+                    Some(inf) => match inf.kill() {
+                        Ok(_) => {
+                            console!("Killing running inferior (pid {})", inf.pid());
+                        }
+                        Err(_) => (),
+                    },
+                    None => {}
+                }
+                self.kill_suspended();
+
+                if let Some(inferior) = Inferior::new(&self.target, &args, output::mode() != Mode::Text) {
+                    // Create the inferior
+                    self.inferior = Some(inferior);
+                    self.locate_objects();
+                    self.install_watchpoints();
+                    self.pending_signal = None;
+                    // TODO (milestone 1): make the inferior run
+                    // You may use self.inferior.as_mut().unwrap() to get a mutable reference
+                    // to the Inferior object
+                    let res = self.resume(None);
+                    self.report_status(res);
+                } else {
+                    console!("Error starting subprocess");
+                }
+            }
+
+            DebuggerCommand::Continue => {
+                match self.inferior {
+                    Some(_) => {
+                        let res = self.resume(None);
+                        self.report_status(res);
+                    }
+                    None => {
+                        console!("No processes are running!");
+                    }
+                };
+            }
+
+            DebuggerCommand::StepInstruction => match self.inferior {
+                Some(_) => {
+                    self.pending_signal = None;
+                    let res = self.step_instruction();
+                    self.report_status(res);
+                }
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Step => match self.inferior {
+                Some(_) => {
+                    let res = self.step_line(false);
+                    self.report_status(res);
+                }
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Next => match self.inferior {
+                Some(_) => {
+                    let res = self.step_line(true);
+                    self.report_status(res);
+                }
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Finish => match self.inferior {
+                Some(_) => {
+                    let res = self.finish();
+                    self.report_status(res);
+                }
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Record => match self.inferior.as_mut() {
+                Some(inferior) if inferior.recording().is_some() => {
+                    console!("The process is already being recorded.  Use \"record stop\" to stop recording first.");
+                }
+                Some(inferior) if inferior.threads().len() > 1 => {
+                    console!("Process record does not support multi-threaded programs.");
+                }
+                Some(inferior) => {
+                    if let Err(err) = inferior.start_recording() {
+                        console!("Can not start recording: {}", err);
+                    }
+                }
+                None => console!("The program is not being run."),
+            },
+
+            DebuggerCommand::RecordStop => match self.inferior.as_mut() {
+                Some(inferior) if inferior.recording().is_some() => {
+                    inferior.stop_recording();
+                    console!("Process record is stopped and all execution logs are deleted.");
+                }
+                _ => console!("No recording is currently active."),
+            },
+
+            DebuggerCommand::InfoRecord => {
+                match self.inferior.as_ref().and_then(Inferior::recording) {
+                    Some(recording) => {
+                        console!("Active record target: record-full");
+                        console!("Log contains {} instructions.", recording.len());
+                        console!("Max logged instructions is {}.", record::MAX_INSTRUCTIONS);
+                    }
+                    None => console!("No recording is currently active."),
+                }
+            }
+
+            DebuggerCommand::ReverseStepInstruction => {
+                if self.check_recording() {
+                    let res = self.reverse_step_instruction();
+                    self.report_status(res);
+                }
+            }
+
+            DebuggerCommand::ReverseStep => {
+                if self.check_recording() {
+                    let res = self.reverse_step_line();
+                    self.report_status(res);
+                }
+            }
+
+            DebuggerCommand::ReverseContinue => {
+                if self.check_recording() {
+                    let res = self.reverse_continue();
+                    self.report_status(res);
+                }
+            }
+
+            DebuggerCommand::Quit => {
+                match &mut self.inferior {
+                    Some(inf) if inf.is_attached() => self.detach(),
+                    Some(inf) if inf.is_core() => {}
+                    Some(inf) => {
+                        // println!("Killing running inferior (pid {})", inf.pid());
+
+                        match inf.kill() {
                             Ok(_) => {
-                                println!("Killing running inferior (pid {})", inf.pid());
+                                console!("Killing running inferior (pid {})", inf.pid());
                             }
                             Err(_) => (),
-                        },
-                        None => {}
+                        }
                     }
+                    None => {}
+                }
+                self.kill_suspended();
+                return false;
+            }
+
+            DebuggerCommand::Attach(pid) => self.attach(pid),
 
-                    if let Some(inferior) =
-                        Inferior::new(&self.target, &args, &mut self.breakpoints)
+            DebuggerCommand::GenerateCoreFile(path) => match &self.inferior {
+                Some(inferior) if !inferior.is_core() => {
+                    let path = path.unwrap_or_else(|| format!("core.{}", inferior.pid()));
+                    // A breakpoint or a step leaves no signal pending, but the kernel would
+                    // record SIGTRAP
+                    let signal = self.pending_signal.unwrap_or(Signal::SIGTRAP);
+                    match inferior
+                        .dump_core(&self.breakpoints, signal)
+                        .and_then(|core| core.write(&path))
                     {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        // TODO (milestone 1): make the inferior run
-                        // You may use self.inferior.as_mut().unwrap() to get a mutable reference
-                        // to the Inferior object
-                        let inferior = self.inferior.as_mut().unwrap();
-                        let res = inferior.con(&self.breakpoints);
-                        match res {
-                            Ok(status) => match status {
-                                Status::Stopped(sign, _rip) => {
-                                    println!("Child stopped (signal {})", sign);
-                                    match self.debug_data.get_line_from_addr(_rip) {
-                                        Some(line_info) => {
-                                            println!(
-                                                "Stopped at {}: {}",
-                                                line_info.file, line_info.number
-                                            );
-                                        }
-                                        None => {
-                                            println!("Stopped at %rip register: {:#x}", _rip);
-                                        }
-                                    };
-                                }
-                                Status::Exited(code) => {
-                                    println!("The program exited with code {}", code);
-                                }
-                                Status::Signaled(sign) => {
-                                    println!("The program stop by sign {}", sign);
-                                }
-                            },
-                            Err(err) => {
-                                println!("{}", err);
-                            }
+                        Ok(_) => console!("Saved corefile {}", path),
+                        Err(err) => console!("Can't create a core file {}: {}", path, err),
+                    }
+                }
+                _ => console!("The program is not being run."),
+            },
+
+            DebuggerCommand::Detach => self.detach(),
+
+            DebuggerCommand::Break(arg, condition) => {
+                let condition = match condition {
+                    Some(expr) => match Condition::parse(&expr) {
+                        Some(condition) => Some(condition),
+                        None => {
+                            console!("Invalid condition \"{}\", expected <variable|$register> <op> <integer>", expr);
+                            return true;
+                        }
+                    },
+                    None => None,
+                };
+                match self.resolve_location(&arg) {
+                    Ok(addr) => {
+                        if self.breakpoints.contains_key(&addr) {
+                            console!("Already set breakpoint at {:#x}", addr);
+                        } else {
+                            self.set_breakpoint(&arg, addr, condition);
                         }
+                    }
+                    // Possibly a function in a library that is not loaded yet
+                    Err(_) if is_identifier(&arg) => self.set_pending_breakpoint(&arg, condition),
+                    Err(err) => console!("{}", err),
+                }
+            }
+
+            DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+
+            DebuggerCommand::Commands(id) => self.read_breakpoint_commands(id),
+
+            DebuggerCommand::Source(path) => self.source(&[path]),
+
+            DebuggerCommand::Help(topic) => debugger_command::print_help(topic.as_deref()),
+
+            DebuggerCommand::Ignore(id, count) => match self.find_breakpoint_mut(id) {
+                Some(bp) => {
+                    bp.ignore_count = count;
+                    if count == 0 {
+                        console!("Will stop next time breakpoint {} is reached.", id);
                     } else {
-                        println!("Error starting subprocess");
-                    }
-                }
-
-                DebuggerCommand::Continue => {
-                    match self.inferior {
-                        Some(_) => {
-                            let inferior = self.inferior.as_mut().unwrap();
-                            let res = inferior.con(&self.breakpoints);
-                            match res {
-                                Ok(status) => match status {
-                                    Status::Stopped(sign, _rip) => {
-                                        println!("Child stopped (signal {})", sign);
-                                        match self.debug_data.get_line_from_addr(_rip) {
-                                            Some(line_info) => {
-                                                println!(
-                                                    "Stopped at {}: {}",
-                                                    line_info.file, line_info.number
-                                                );
-                                            }
-                                            None => {
-                                                println!("Stopped at %rip register: {:#x}", _rip);
-                                            }
-                                        };
-                                    }
-                                    Status::Exited(code) => {
-                                        println!("The program exited with code {}", code);
-                                    }
-                                    Status::Signaled(sign) => {
-                                        println!("The program stop by sign {}", sign);
-                                    }
-                                },
+                        console!("Will ignore next {} crossings of breakpoint {}.", count, id);
+                    }
+                }
+                None => console!("No breakpoint number {}.", id),
+            },
+
+            DebuggerCommand::Watch(expr) => self.set_watchpoint(&expr),
+
+            DebuggerCommand::Delete(id) => match self.find_breakpoint(id) {
+                Some(addr) => {
+                    let bp = self.breakpoints.remove(&addr).unwrap();
+                    if bp.enabled {
+                        self.unplant_breakpoint(&bp);
+                    }
+                }
+                None => match self.watchpoints.iter().position(|wp| wp.id == id) {
+                    Some(index) => self.delete_watchpoint(index),
+                    None => match self.pending_breakpoints.iter().position(|bp| bp.id == id) {
+                        Some(index) => {
+                            self.pending_breakpoints.remove(index);
+                        }
+                        None => console!("No breakpoint number {}.", id),
+                    },
+                },
+            },
+
+            DebuggerCommand::Disable(id) => match self.find_breakpoint(id) {
+                Some(addr) => {
+                    let bp = self.breakpoints[&addr].clone();
+                    if bp.enabled {
+                        self.unplant_breakpoint(&bp);
+                        self.breakpoints.get_mut(&addr).unwrap().enabled = false;
+                    }
+                }
+                None => match self.find_breakpoint_mut(id) {
+                    Some(bp) => bp.enabled = false,
+                    None => match self.watchpoints.iter().position(|wp| wp.id == id) {
+                        Some(index) => self.disable_watchpoint(index),
+                        None => console!("No breakpoint number {}.", id),
+                    },
+                },
+            },
+
+            DebuggerCommand::Enable(id) => match self.find_breakpoint(id) {
+                Some(addr) => {
+                    if !self.breakpoints[&addr].enabled {
+                        let orig_byte = match self.inferior.as_mut() {
+                            Some(inferior) => match inferior.write_byte(addr, 0xcc) {
+                                Ok(orig_byte) => orig_byte,
                                 Err(err) => {
-                                    println!("{}", err);
+                                    console!("Can not enable breakpoint {}: {}", id, err);
+                                    return true;
                                 }
-                            }
-                        }
-                        None => {
-                            println!("No processes are running!");
-                        }
-                    };
+                            },
+                            None => 0,
+                        };
+                        let bp = self.breakpoints.get_mut(&addr).unwrap();
+                        bp.orig_byte = orig_byte;
+                        bp.enabled = true;
+                    }
                 }
+                None => match self.find_breakpoint_mut(id) {
+                    Some(bp) => bp.enabled = true,
+                    None => match self.watchpoints.iter().position(|wp| wp.id == id) {
+                        Some(index) => self.enable_watchpoint(index),
+                        None => console!("No breakpoint number {}.", id),
+                    },
+                },
+            },
 
-                DebuggerCommand::Quit => {
-                    match &mut self.inferior {
-                        Some(inf) => {
-                            // println!("Killing running inferior (pid {})", inf.pid());
+            DebuggerCommand::Backtrace => match &self.inferior {
+                Some(_) => {
+                    if let Err(err) = self.print_backtrace() {
+                        console!("Can not read the stack: {}", err);
+                    }
+                }
+                None => {
+                    console!("No processes are running!");
+                }
+            },
 
-                            match inf.kill() {
-                                Ok(_) => {
-                                    println!("Killing running inferior (pid {})", inf.pid());
-                                }
-                                Err(_) => (),
-                            }
-                        }
-                        None => {}
+            DebuggerCommand::Print(expr) => match self.inferior {
+                Some(_) => match expression::evaluate(&expr, self)
+                    .and_then(|value| expression::format_value(&value, self))
+                {
+                    Ok(value) => {
+                        console!("{} = {}", expr, value);
+                        output::emit(Event::Value { expression: expr, value });
                     }
-                    return;
+                    Err(err) => console!("{}", err),
+                },
+                None => {
+                    console!("No processes are running!");
                 }
+            },
 
-                DebuggerCommand::Break(arg) => {
-                    let breakpoint_addr: Option<usize>;
-                    if arg.starts_with("*") {
-                        if let Some(addr) = self.parse_address(&arg[..]).ok() {
-                            breakpoint_addr = Some(addr);
-                        } else {
-                            breakpoint_addr = None;
-                            println!("Invalid address");
-                        }
-                    } else if let Some(line) = usize::from_str_radix(&arg, 10).ok() {
-                        if let Some(addr) = self.debug_data.get_addr_for_line(None, line) {
-                            breakpoint_addr = Some(addr);
-                        } else {
-                            breakpoint_addr = None;
-                            println!("Invalid line number");
+            DebuggerCommand::InfoRegisters(names) => match self.inferior {
+                Some(_) => self.print_registers(&names),
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::SetRegister(name, value) => match &self.inferior {
+                Some(inf) => match inf.set_register(&name, value) {
+                    Ok(true) => {}
+                    Ok(false) => console!("Invalid register \"{}\"", name),
+                    Err(err) => console!("Can not set ${}: {}", name, err),
+                },
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::SetVariable(lhs, rhs) => match self.inferior {
+                Some(_) => {
+                    if let Err(err) = self.set_variable(&lhs, &rhs) {
+                        console!("{}", err);
+                    }
+                }
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Handle(name, keywords) => self.handle_signal(&name, &keywords),
+
+            DebuggerCommand::InfoSignals(name) => match name {
+                Some(name) => match signals::parse_signal(&name) {
+                    Some(signal) => self.signals.print(&[signal]),
+                    None => console!("Unrecognized signal \"{}\".", name),
+                },
+                None => self.signals.print(&Signal::iterator().collect::<Vec<_>>()),
+            },
+
+            DebuggerCommand::SetFollowForkMode(mode) => self.follow_fork_mode = mode,
+
+            DebuggerCommand::SetDetachOnFork(detach) => self.detach_on_fork = detach,
+
+            DebuggerCommand::InfoInferiors => self.print_inferiors(),
+
+            DebuggerCommand::InfoSharedLibrary => self.print_libraries(),
+
+            DebuggerCommand::InfoThreads => match self.inferior {
+                Some(_) => self.print_threads(),
+                None => console!("No threads."),
+            },
+
+            DebuggerCommand::Thread(id) => match (&self.inferior, id) {
+                (Some(_), Some(id)) => self.select_thread(id),
+                (Some(inferior), None) => console!(
+                    "[Current thread is {} (LWP {})]",
+                    inferior.current_thread(),
+                    inferior.tid()
+                ),
+                (None, _) => console!("No processes are running!"),
+            },
+
+            DebuggerCommand::Inferior(id) => self.select_inferior(id),
+
+            DebuggerCommand::Examine(examine, expr) => match self.inferior {
+                Some(_) => match self.resolve_address(&expr) {
+                    Ok(addr) => {
+                        if let Err(err) = self.examine_memory(&examine, addr) {
+                            console!("Can not access memory at address {:#x}: {}", addr, err);
                         }
-                    } else if let Some(addr) = self.debug_data.get_addr_for_function(None, &arg) {
-                        breakpoint_addr = Some(addr);
+                    }
+                    Err(err) => console!("{}", err),
+                },
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Disassemble(target) => match self.inferior {
+                Some(_) => self.disassemble(target),
+                None => {
+                    console!("No processes are running!");
+                }
+            },
+
+            DebuggerCommand::Up(count) => match self.inferior {
+                Some(_) => self.select_frame(|current, len| {
+                    if current + 1 >= len {
+                        Err("Initial frame selected; you cannot go up.".to_string())
+                    } else {
+                        Ok((current + count).min(len - 1))
+                    }
+                }),
+                None => console!("No stack."),
+            },
+
+            DebuggerCommand::Down(count) => match self.inferior {
+                Some(_) => self.select_frame(|current, _| {
+                    if current == 0 {
+                        Err("Bottom (innermost) frame selected; you cannot go down.".to_string())
                     } else {
-                        breakpoint_addr = None;
-                        println!("Usage: b|break|breakpoint *address|line|func");
-                    }
-
-                    match breakpoint_addr {
-                        Some(addr) => {
-                            if self.breakpoints.contains_key(&addr) {
-                                println!("Already set breakpoint at {:#x}", addr);
-                            } else {
-                                if self.inferior.is_some() {
-                                    if let Some(orig_instr) =
-                                        self.inferior.as_mut().unwrap().write_byte(addr, 0xcc).ok()
-                                    {
-                                        println!(
-                                            "Set breakpoint {} at {:#x}",
-                                            self.breakpoints.len(),
-                                            addr
-                                        );
-                                        self.breakpoints.insert(
-                                            addr,
-                                            Breakpoint {
-                                                addr: addr,
-                                                orig_byte: orig_instr,
-                                            },
-                                        );
-                                    } else {
-                                        println!("Invalid breakpoint address at {:#x}", addr);
-                                    }
-                                } else {
-                                    println!(
-                                        "Set breakpoint {} at {:#x}",
-                                        self.breakpoints.len(),
-                                        addr
-                                    );
-                                    self.breakpoints.insert(
-                                        addr,
-                                        Breakpoint {
-                                            addr: addr,
-                                            orig_byte: 0,
-                                        },
-                                    );
+                        Ok(current.saturating_sub(count))
+                    }
+                }),
+                None => console!("No stack."),
+            },
+
+            DebuggerCommand::Frame(level) => match self.inferior {
+                Some(_) => self.select_frame(|current, len| match level {
+                    Some(level) if level >= len => Err(format!("No frame at level {}.", level)),
+                    Some(level) => Ok(level),
+                    None => Ok(current),
+                }),
+                None => console!("No stack."),
+            },
+
+            DebuggerCommand::InfoFrame => match self.inferior {
+                Some(_) => {
+                    if let Err(err) = self.print_frame_info() {
+                        console!("Can not read the stack: {}", err);
+                    }
+                }
+                None => console!("No stack."),
+            },
+
+            DebuggerCommand::List(target) => self.list(target),
+
+            DebuggerCommand::Directory(dir) => {
+                if let Some(dir) = dir {
+                    self.sources.add_directory(&dir);
+                }
+                console!("Source directories searched: {}", self.sources.directories().join(":"));
+            }
+
+            DebuggerCommand::InfoLocals => match self.inferior {
+                Some(_) => match self.current_function() {
+                    Some(func) => {
+                        if func.variables.is_empty() {
+                            console!("No locals.");
+                        }
+                        for var in &func.variables {
+                            match self.read_variable(var) {
+                                Ok(value) => {
+                                    console!("{} = {}", var.name, value);
+                                    output::emit(Event::Value {
+                                        expression: var.name.clone(),
+                                        value,
+                                    });
                                 }
+                                Err(err) => console!("{} = <error: {}>", var.name, err),
                             }
                         }
-                        None => (),
                     }
+                    None => console!("No symbol table info available."),
+                },
+                None => {
+                    console!("No processes are running!");
                 }
+            },
+        }
+        true
+    }
 
-                DebuggerCommand::Backtrace => match &self.inferior {
-                    Some(inf) => {
-                        inf.print_backtrace(&self.debug_data).ok();
-                    }
-                    None => {
-                        println!("No processes are running!");
-                    }
-                },
+    /// Starts debugging the already running process `pid`, planting our breakpoints and
+    /// watchpoints in it.
+    pub fn attach(&mut self, pid: i32) {
+        if self.inferior.is_some() {
+            console!("A program is already being debugged. Kill or detach it first.");
+            return;
+        }
+        match Inferior::attach(Pid::from_raw(pid)) {
+            Ok(inferior) => {
+                console!("Attaching to process {}", pid);
+                self.inferior = Some(inferior);
+                self.locate_objects();
+                self.install_watchpoints();
+                let res = self
+                    .inferior
+                    .as_ref()
+                    .unwrap()
+                    .getregs()
+                    .map(|regs| Status::Stopped(Signal::SIGSTOP, regs.rip as usize, None));
+                self.report_status(res);
             }
+            Err(err) => console!("Can not attach to process {}: {}", pid, err),
         }
     }
 
-    fn parse_address(&self, addr: &str) -> Result<usize, String> {
-        let addr_without_0x = if addr.to_lowercase().starts_with("*0x") {
-            &addr[3..]
-        } else {
-            return Err("address illegal".to_string());
+    /// Opens the core dump at `path` in place of a live process, and shows where the program
+    /// was when it was dumped.
+    pub fn load_core(&mut self, path: &str) {
+        let core = match CoreFile::read(path) {
+            Ok(core) => core,
+            Err(err) => {
+                console!("{}: {}.", path, err);
+                return;
+            }
         };
-
-        match usize::from_str_radix(addr_without_0x, 16) {
-            Ok(v) => Ok(v),
-            Err(_) => Err(format!("Can not parse address 0x{}", addr_without_0x)),
+        console!("Core was generated by `{}'.", core.command);
+        if let Some(signal) = core.signal {
+            console!("Program terminated with signal {}, {}.", signal, signals::describe(signal));
         }
+        self.inferior = Some(Inferior::from_core(core));
+        self.locate_objects();
+        self.select_frame(|_, _| Ok(0));
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
-        loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
+    /// Removes our breakpoints and watchpoints from the inferior and lets it continue untraced.
+    fn detach(&mut self) {
+        match self.inferior.take() {
+            Some(inferior) => {
+                let pid = inferior.pid();
+                match inferior.detach(&self.breakpoints) {
+                    Ok(_) => console!("Detaching from process {}", pid),
+                    Err(err) => console!("Can not detach from process {}: {}", pid, err),
+                }
+                for wp in self.watchpoints.iter_mut() {
+                    wp.slot = None;
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+            }
+            None => console!("The program is not being run."),
+        }
+    }
+
+    /// Returns the address of a breakpoint location: `*address`, a line number or a function.
+    fn resolve_location(&self, location: &str) -> Result<usize, String> {
+        if location.starts_with("*") {
+            self.parse_address(location).or(Err("Invalid address".to_string()))
+        } else if let Ok(line) = usize::from_str_radix(location, 10) {
+            self.debug_data
+                .get_addr_for_line(None, line)
+                .ok_or_else(|| "Invalid line number".to_string())
+        } else if let Some((file, line)) = parse_file_line(location) {
+            self.debug_data
+                .get_addr_for_line(Some(file), line)
+                .ok_or_else(|| format!("No line {} in file \"{}\".", line, file))
+        } else {
+            // Stop once the function has set up its frame, so that its arguments can be read
+            self.debug_data
+                .get_addr_for_function(None, location)
+                .map(|addr| self.debug_data.skip_prologue(addr))
+                .ok_or_else(|| "Usage: b|break|breakpoint *address|line|func".to_string())
+        }
+    }
+
+    /// Creates a new breakpoint at `addr`, planting it right away if the inferior is running.
+    fn set_breakpoint(&mut self, location: &str, addr: usize, condition: Option<Condition>) {
+        let orig_byte = match self.inferior.as_mut() {
+            Some(inferior) => match inferior.write_byte(addr, 0xcc) {
+                Ok(orig_byte) => orig_byte,
+                Err(_) => {
+                    console!("Invalid breakpoint address at {:#x}", addr);
+                    return;
                 }
+            },
+            None => 0,
+        };
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        console!("Set breakpoint {} at {:#x}", id, addr);
+        let line = self.debug_data.get_line_from_addr(addr);
+        output::emit(Event::BreakpointCreated {
+            id,
+            addr: Some(addr),
+            location: location.to_string(),
+            file: line.as_ref().map(|line| line.file.clone()),
+            line: line.map(|line| line.number),
+        });
+        self.breakpoints.insert(
+            addr,
+            Breakpoint {
+                id,
+                location: location.to_string(),
+                addr,
+                orig_byte,
+                enabled: true,
+                condition,
+                hit_count: 0,
+                ignore_count: 0,
+                commands: Vec::new(),
+            },
+        );
+    }
+
+    /// Creates a breakpoint on a function we don't know about yet, to be set once a library that
+    /// defines it is loaded.
+    fn set_pending_breakpoint(&mut self, location: &str, condition: Option<Condition>) {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        console!("Function \"{}\" not defined.", location);
+        console!("Breakpoint {} ({}) pending.", id, location);
+        output::emit(Event::BreakpointCreated {
+            id,
+            addr: None,
+            location: location.to_string(),
+            file: None,
+            line: None,
+        });
+        self.pending_breakpoints.push(Breakpoint {
+            id,
+            location: location.to_string(),
+            addr: 0,
+            orig_byte: 0,
+            enabled: true,
+            condition,
+            hit_count: 0,
+            ignore_count: 0,
+            commands: Vec::new(),
+        });
+    }
+
+    /// Creates a watchpoint on a variable or on the word at `*address`.
+    fn set_watchpoint(&mut self, expr: &str) {
+        let (addr, entity_type, scope) = if expr.starts_with('*') {
+            match self.parse_address(expr) {
+                Ok(addr) => (addr, Type::new("long".to_string(), size_of::<usize>()), None),
                 Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+                    console!("{}", err);
+                    return;
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
+            }
+        } else {
+            let var = match self.lookup_variable(expr) {
+                Some(var) => var,
+                None => {
+                    console!("No symbol \"{}\" in current context.", expr);
+                    return;
+                }
+            };
+            match var.location {
+                Location::Address(addr) => (addr, var.entity_type, None),
+                Location::FramePointerOffset(_) => match self.variable_address(&var) {
+                    Ok(addr) => {
+                        // Remember the frame, so the watchpoint can go once the frame returns
+                        let tid = self.inferior.as_ref().unwrap().tid();
+                        let scope = match self.selected_stack_frame() {
+                            Ok(Some((_, frame))) => frame.return_address.map(|return_address| {
+                                WatchpointScope { tid, return_address, cfa: frame.cfa }
+                            }),
+                            _ => None,
+                        };
+                        (addr, var.entity_type, scope)
                     }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                    Err(err) => {
+                        console!("Can not locate {}: {}", expr, err);
+                        return;
                     }
-                }
+                },
+            }
+        };
+
+        let len = entity_type.size;
+        if ![1, 2, 4, 8].contains(&len) || addr % len != 0 {
+            console!("Can not watch {} byte(s) at {:#x} with a debug register", len, addr);
+            return;
+        }
+
+        let id = self.next_breakpoint_id;
+        let mut wp = Watchpoint {
+            id,
+            expr: expr.to_string(),
+            addr,
+            entity_type,
+            enabled: true,
+            slot: None,
+            scope,
+        };
+        if self.inferior.is_some() && !self.install_watchpoint(&mut wp) {
+            return;
+        }
+        self.next_breakpoint_id += 1;
+        console!("Hardware watchpoint {}: {}", id, expr);
+        self.watchpoints.push(wp);
+    }
+
+    /// Programs a debug register of the running inferior for `wp`. Returns false on failure.
+    fn install_watchpoint(&mut self, wp: &mut Watchpoint) -> bool {
+        let inferior = self.inferior.as_mut().unwrap();
+        match inferior.set_watchpoint(wp.addr, wp.entity_type.size) {
+            Ok(Some(slot)) => {
+                wp.slot = Some(slot);
+                true
+            }
+            Ok(None) => {
+                console!("Can not watch {}: all hardware debug registers are in use", wp.expr);
+                false
+            }
+            Err(err) => {
+                console!("Can not watch {}: {}", wp.expr, err);
+                false
+            }
+        }
+    }
+
+    /// Removes the watchpoint at `index` in `self.watchpoints`, freeing its debug register.
+    fn delete_watchpoint(&mut self, index: usize) {
+        let wp = self.watchpoints.remove(index);
+        if let (Some(inferior), Some(slot)) = (self.inferior.as_mut(), wp.slot) {
+            if let Err(err) = inferior.remove_watchpoint(slot) {
+                console!("Can not clear debug register: {}", err);
+            }
+        }
+    }
+
+    /// Deletes the watchpoints on local variables whose frame has returned, which is all of them
+    /// once the inferior is gone. A frame has returned when %rsp of its thread is back at or
+    /// above its CFA.
+    fn delete_watchpoints_out_of_scope(&mut self) {
+        let current = match &self.inferior {
+            Some(inferior) => match inferior.getregs() {
+                Ok(regs) => Some((inferior.tid(), regs.rsp as usize)),
+                Err(_) => return,
+            },
+            None => None,
+        };
+        let mut index = 0;
+        while index < self.watchpoints.len() {
+            let gone = match (&self.watchpoints[index].scope, current) {
+                (None, _) => false,
+                (Some(scope), Some((tid, rsp))) => scope.tid == tid && rsp >= scope.cfa,
+                (Some(_), None) => true,
+            };
+            if gone {
+                console!(
+                    "Watchpoint {} deleted because the program has left the block in which its \
+                     expression is valid.",
+                    self.watchpoints[index].id
+                );
+                self.delete_watchpoint(index);
+            } else {
+                index += 1;
             }
         }
     }
+
+    /// Frees the debug register of the watchpoint at `index` in `self.watchpoints`, keeping the
+    /// watchpoint so that it can be enabled again.
+    fn disable_watchpoint(&mut self, index: usize) {
+        let wp = &mut self.watchpoints[index];
+        wp.enabled = false;
+        if let (Some(inferior), Some(slot)) = (self.inferior.as_mut(), wp.slot.take()) {
+            if let Err(err) = inferior.remove_watchpoint(slot) {
+                console!("Can not clear debug register: {}", err);
+            }
+        }
+    }
+
+    /// Programs a debug register again for the watchpoint at `index` in `self.watchpoints`.
+    fn enable_watchpoint(&mut self, index: usize) {
+        if self.watchpoints[index].enabled {
+            return;
+        }
+        let mut wp = self.watchpoints[index].clone();
+        if self.inferior.is_some() && !self.install_watchpoint(&mut wp) {
+            return;
+        }
+        wp.enabled = true;
+        self.watchpoints[index] = wp;
+    }
+
+    /// Programs the debug registers of a freshly started inferior for all watchpoints.
+    fn install_watchpoints(&mut self) {
+        let mut watchpoints = std::mem::replace(&mut self.watchpoints, Vec::new());
+        for wp in watchpoints.iter_mut() {
+            wp.slot = None;
+            if wp.enabled {
+                self.install_watchpoint(wp);
+            }
+        }
+        self.watchpoints = watchpoints;
+    }
+
+    /// Returns the address of the breakpoint numbered `id`.
+    fn find_breakpoint(&self, id: usize) -> Option<usize> {
+        self.breakpoints
+            .values()
+            .find(|bp| bp.id == id)
+            .map(|bp| bp.addr)
+    }
+
+    /// Returns the breakpoint numbered `id`, whether it is set or pending.
+    fn find_breakpoint_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints
+            .values_mut()
+            .chain(self.pending_breakpoints.iter_mut())
+            .find(|bp| bp.id == id)
+    }
+
+    /// Puts the original instruction byte back in place of the breakpoint's 0xcc.
+    fn unplant_breakpoint(&mut self, bp: &Breakpoint) {
+        if let Some(inferior) = self.inferior.as_mut() {
+            if let Err(err) = inferior.write_byte(bp.addr, bp.orig_byte) {
+                console!("Can not restore instruction at {:#x}: {}", bp.addr, err);
+            }
+        }
+    }
+
+    fn print_breakpoints(&self) {
+        let mut bps: Vec<&Breakpoint> = self
+            .breakpoints
+            .values()
+            .filter(|bp| !bp.is_internal())
+            .chain(self.pending_breakpoints.iter())
+            .collect();
+        if bps.is_empty() && self.watchpoints.is_empty() {
+            console!("No breakpoints.");
+            return;
+        }
+        bps.sort_by_key(|bp| bp.id);
+        console!("{:<4} {:<4} {:<18} {}", "Num", "Enb", "Address", "What");
+        for bp in bps {
+            let pending = self.pending_breakpoints.iter().any(|pending| pending.id == bp.id);
+            let what = match (
+                self.debug_data.get_function_from_addr(bp.addr),
+                self.debug_data.get_line_from_addr(bp.addr),
+            ) {
+                _ if pending => bp.location.clone(),
+                (Some(func), Some(line)) => format!("in {} at {}", func, line),
+                (Some(func), None) => format!("in {}", func),
+                (None, Some(line)) => format!("at {}", line),
+                (None, None) => String::new(),
+            };
+            let addr = if pending {
+                "<PENDING>".to_string()
+            } else {
+                format!("{:#018x}", bp.addr)
+            };
+            console!(
+                "{:<4} {:<4} {:<18} {}",
+                bp.id,
+                if bp.enabled { "y" } else { "n" },
+                addr,
+                what
+            );
+            if let Some(condition) = &bp.condition {
+                console!("\tstop only if {}", condition);
+            }
+            if bp.hit_count > 0 {
+                console!("\tbreakpoint already hit {} time(s)", bp.hit_count);
+            }
+            if bp.ignore_count > 0 {
+                console!("\twill ignore next {} crossings of breakpoint", bp.ignore_count);
+            }
+            for command in &bp.commands {
+                console!("        {}", command);
+            }
+        }
+        for wp in &self.watchpoints {
+            console!(
+                "{:<4} {:<4} {:#018x} hw watchpoint {}",
+                wp.id,
+                if wp.enabled { "y" } else { "n" },
+                wp.addr,
+                wp.expr
+            );
+        }
+    }
+
+    /// Continues the inferior until it stops at a breakpoint whose condition holds, receives a
+    /// signal or exits. With `until` set to an address and a canonical frame address, also stops
+    /// once the inferior reaches that address with %rsp at or above the CFA, using a temporary
+    /// breakpoint. The %rsp check keeps recursive calls from ending the run early. Stops the same
+    /// way when the frame of a watched local variable returns.
+    ///
+    /// Signals are handled according to the `handle` table: the pending signal is delivered as the
+    /// inferior resumes, and signals that don't stop it are passed on right away.
+    fn resume(&mut self, until: Option<(usize, usize)>) -> Result<Status, nix::Error> {
+        let mut temporary: Vec<Breakpoint> = Vec::new();
+        let scope_addrs = self
+            .watchpoints
+            .iter()
+            .filter_map(|wp| wp.scope.as_ref().map(|scope| scope.return_address));
+        for addr in until.map(|(addr, _)| addr).into_iter().chain(scope_addrs) {
+            if self.breakpoints.get(&addr).map_or(false, |bp| bp.enabled)
+                || temporary.iter().any(|bp| bp.addr == addr)
+            {
+                continue;
+            }
+            let orig_byte = self.inferior.as_mut().unwrap().write_byte(addr, 0xcc)?;
+            temporary.push(Breakpoint::temporary(addr, orig_byte));
+        }
+        let with_temporary = |breakpoints: &HashMap<usize, Breakpoint>| {
+            let mut bps = breakpoints.clone();
+            for bp in &temporary {
+                bps.insert(bp.addr, bp.clone());
+            }
+            bps
+        };
+        let mut bps = with_temporary(&self.breakpoints);
+
+        let mut signal = self.pending_signal.take();
+        let status = loop {
+            let status = self.inferior.as_mut().unwrap().con(&bps, signal.take())?;
+            if self.follow_event(&status)? {
+                // An exec moves our breakpoints
+                bps = with_temporary(&self.breakpoints);
+                continue;
+            }
+            if let Status::Stopped(sig, _, _) = status {
+                if sig != Signal::SIGTRAP {
+                    let policy = self.signals.get(sig);
+                    if policy.pass {
+                        signal = Some(sig);
+                    }
+                    if policy.stop {
+                        self.pending_signal = signal;
+                        break status;
+                    }
+                    if policy.print {
+                        console!("Child received signal {}, {}", sig, signals::describe(sig));
+                    }
+                    continue;
+                }
+            }
+            if let Status::Stopped(Signal::SIGTRAP, _, Some(hit)) = &status {
+                // The hardware fires on any write, but we only report actual changes
+                if hit.old_value == hit.new_value {
+                    continue;
+                }
+            }
+            if let Status::Stopped(Signal::SIGTRAP, rip, None) = status {
+                if let Some((addr, cfa)) = until {
+                    let rsp = self.inferior.as_ref().unwrap().getregs()?.rsp as usize;
+                    if rip == addr && rsp >= cfa {
+                        break status;
+                    }
+                }
+                if self.left_watchpoint_scope(rip)? {
+                    break status;
+                }
+                if self.breakpoints.get(&rip).map_or(false, |bp| bp.is_internal()) {
+                    // The dynamic linker has loaded or unloaded libraries, which may have set or
+                    // cleared breakpoints
+                    self.update_libraries();
+                    bps = with_temporary(&self.breakpoints);
+                    continue;
+                }
+                if self.breakpoints.get(&rip).map_or(false, |bp| bp.enabled) {
+                    if self.breakpoint_should_stop(rip) {
+                        let commands = self.breakpoints[&rip].commands.clone();
+                        self.queue_input(commands);
+                        break status;
+                    }
+                    continue;
+                }
+                if temporary.iter().any(|bp| bp.addr == rip) {
+                    continue;
+                }
+            }
+            break status;
+        };
+
+        if let Status::Stopped(_, _, _) = status {
+            for bp in temporary {
+                self.inferior.as_mut().unwrap().write_byte(bp.addr, bp.orig_byte)?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Returns whether the current thread, stopped at `rip`, has just returned from the frame of
+    /// a watched local variable.
+    fn left_watchpoint_scope(&self, rip: usize) -> Result<bool, nix::Error> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let mut scopes = self
+            .watchpoints
+            .iter()
+            .filter_map(|wp| wp.scope.as_ref())
+            .filter(|scope| scope.return_address == rip && scope.tid == inferior.tid())
+            .peekable();
+        if scopes.peek().is_none() {
+            return Ok(false);
+        }
+        let rsp = inferior.getregs()?.rsp as usize;
+        Ok(scopes.any(|scope| rsp >= scope.cfa))
+    }
+
+    /// Executes a single machine instruction, dealing with any fork, exec or thread creation that
+    /// happens along the way.
+    fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        loop {
+            let status = self.inferior.as_mut().unwrap().step_instruction(&self.breakpoints)?;
+            if !self.follow_event(&status)? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Handles the inferior forking, exec'ing or creating a thread. Returns false if `status` is
+    /// not one of those events.
+    fn follow_event(&mut self, status: &Status) -> Result<bool, nix::Error> {
+        match status {
+            Status::Forked(pid) => self.follow_fork(*pid)?,
+            Status::Execed => {
+                self.stop_recording("the program called exec");
+                self.follow_exec();
+            }
+            Status::Cloned(tid) => {
+                let inferior = self.inferior.as_ref().unwrap();
+                if let Some(thread) = inferior.threads().iter().find(|thread| thread.tid == *tid) {
+                    console!("[New Thread {} (LWP {})]", thread.id, tid);
+                }
+                self.stop_recording("multi-threaded programs are not supported");
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Stops recording, if we were, because of something we can't record.
+    fn stop_recording(&mut self, reason: &str) {
+        let inferior = self.inferior.as_mut().unwrap();
+        if inferior.recording().is_some() {
+            inferior.stop_recording();
+            console!("Process record stopped: {}.", reason);
+        }
+    }
+
+    /// Decides which side of a fork to keep debugging, according to `follow-fork-mode`. The
+    /// other side is detached, or kept stopped if `detach-on-fork` is off.
+    fn follow_fork(&mut self, child_pid: Pid) -> Result<(), nix::Error> {
+        let child = self.inferior.as_mut().unwrap().fork_child(child_pid)?;
+        let child_id = self.next_inferior_id;
+        self.next_inferior_id += 1;
+        let parent_pid = self.inferior.as_ref().unwrap().pid();
+
+        let (other, other_id) = match self.follow_fork_mode {
+            ForkMode::Parent => (child, child_id),
+            ForkMode::Child => {
+                console!(
+                    "[Attaching after process {} fork to child process {}]",
+                    parent_pid, child_pid
+                );
+                let parent = self.inferior.replace(child).unwrap();
+                let parent_id = std::mem::replace(&mut self.inferior_id, child_id);
+                self.install_watchpoints();
+                (parent, parent_id)
+            }
+        };
+
+        let (side, pid) = match self.follow_fork_mode {
+            ForkMode::Parent => ("child", child_pid),
+            ForkMode::Child => ("parent", parent_pid),
+        };
+        if self.detach_on_fork {
+            console!("[Detaching after fork from {} process {}]", side, pid);
+            other.detach(&self.breakpoints)?;
+        } else {
+            console!("[New inferior {} (process {})]", other_id, pid);
+            self.suspended.push((other_id, other));
+        }
+        Ok(())
+    }
+
+    /// Loads the debugging information of the program the inferior has exec'd, and sets our
+    /// breakpoints again in it.
+    fn follow_exec(&mut self) {
+        let pid = self.inferior.as_ref().unwrap().pid();
+        let path = match fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(err) => {
+                console!("Can not find the program process {} is executing: {}", pid, err);
+                return;
+            }
+        };
+        console!("process {} is executing new program: {}", pid, path);
+        self.load_program(&path);
+        // The 0xcc bytes went away with the old program's memory
+        self.locate_objects();
+    }
+
+    /// Works out where the program and the dynamic linker were loaded in a new inferior, picks
+    /// up any shared libraries that are already loaded, and sets our breakpoints.
+    fn locate_objects(&mut self) {
+        // A position-independent executable is linked at 0 and loaded elsewhere
+        if let Some(entry) = self.inferior.as_ref().unwrap().auxv_entry(libc::AT_ENTRY) {
+            let bias = entry.wrapping_sub(self.debug_data.entry_point());
+            self.debug_data.set_load_bias(bias);
+        }
+        self.relocate_watchpoints();
+        let inferior = self.inferior.as_ref().unwrap();
+        self.debug_data.retain_libraries(|_, _| false);
+        self.r_debug = None;
+        let interpreter = inferior
+            .auxv_entry(libc::AT_BASE)
+            .filter(|base| *base != 0)
+            .and_then(|base| Some((inferior.mapped_file(base)?, base)));
+        let mut hook = None;
+        if let Some((path, base)) = interpreter {
+            // The dynamic linker keeps the list of libraries in _r_debug, and calls
+            // _dl_debug_state whenever it changes, so that debuggers can put a breakpoint there
+            if self.debug_data.add_library(&path, base).is_ok() {
+                self.r_debug = self.debug_data.get_symbol("_r_debug");
+                hook = self.debug_data.get_symbol("_dl_debug_state");
+            }
+        }
+
+        // Nothing can be planted in a core dump, but its libraries are listed in its memory
+        if inferior.is_core() {
+            self.update_libraries();
+            return;
+        }
+
+        let mut bps: Vec<Breakpoint> = self
+            .breakpoints
+            .drain()
+            .map(|(_, bp)| bp)
+            .filter(|bp| !bp.is_internal())
+            .collect();
+        bps.append(&mut self.pending_breakpoints);
+        self.plant_breakpoints(bps);
+        if let Some(addr) = hook {
+            match self.inferior.as_mut().unwrap().write_byte(addr, 0xcc) {
+                Ok(orig_byte) => {
+                    self.breakpoints.insert(addr, Breakpoint::internal(addr, orig_byte));
+                }
+                Err(err) => console!("Can not watch for shared library loads: {}", err),
+            }
+        }
+        // Libraries are already loaded in a process we attach to
+        self.update_libraries();
+    }
+
+    /// Looks up the global variables that watchpoints are set on again, since a watchpoint set
+    /// before `run` has the address the variable was linked at rather than loaded at.
+    fn relocate_watchpoints(&mut self) {
+        for wp in self.watchpoints.iter_mut().filter(|wp| wp.scope.is_none()) {
+            if let Some(var) = self.debug_data.get_global_variable(&wp.expr) {
+                if let Location::Address(addr) = var.location {
+                    wp.addr = addr;
+                }
+            }
+        }
+    }
+
+    /// Sets each of `bps` at the address its location now resolves to, planting it if it is
+    /// enabled. Breakpoints that don't resolve are kept pending.
+    fn plant_breakpoints(&mut self, mut bps: Vec<Breakpoint>) {
+        bps.sort_by_key(|bp| bp.id);
+        for mut bp in bps {
+            let addr = match self.resolve_location(&bp.location) {
+                Ok(addr) if !self.breakpoints.contains_key(&addr) => addr,
+                _ => {
+                    self.pending_breakpoints.push(bp);
+                    continue;
+                }
+            };
+            bp.addr = addr;
+            if bp.enabled {
+                match self.inferior.as_mut().unwrap().write_byte(addr, 0xcc) {
+                    Ok(orig_byte) => bp.orig_byte = orig_byte,
+                    Err(_) => {
+                        self.pending_breakpoints.push(bp);
+                        continue;
+                    }
+                }
+            }
+            self.breakpoints.insert(addr, bp);
+        }
+    }
+
+    /// Brings our list of shared libraries in line with the dynamic linker's. Breakpoints in
+    /// libraries that went away become pending, and pending breakpoints are set in the libraries
+    /// that arrived.
+    fn update_libraries(&mut self) {
+        let r_debug = match self.r_debug {
+            Some(r_debug) => r_debug,
+            None => return,
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        match inferior.read_bytes(r_debug + R_DEBUG_STATE, 4, &self.breakpoints) {
+            Ok(bytes) if u32::from_le_bytes(bytes[..].try_into().unwrap()) == RT_CONSISTENT => {}
+            // The list is being changed, and we will be told again once it is done
+            _ => return,
+        }
+
+        let mut link_map = self.read_word(r_debug + R_DEBUG_MAP).unwrap_or(0);
+        if link_map == 0 {
+            // The dynamic linker hasn't started yet
+            return;
+        }
+        let mut loaded: Vec<(String, usize)> = Vec::new();
+        while link_map != 0 {
+            let bias = self.read_word(link_map + LINK_MAP_ADDR).unwrap_or(0);
+            let name = self
+                .read_word(link_map + LINK_MAP_NAME)
+                .and_then(|addr| self.read_c_string(addr))
+                .unwrap_or_default();
+            // The program itself has no name, and the vDSO is not a file
+            if let Ok(path) = fs::canonicalize(&name) {
+                loaded.push((path.to_string_lossy().to_string(), bias));
+            }
+            link_map = self.read_word(link_map + LINK_MAP_NEXT).unwrap_or(0);
+        }
+
+        // Breakpoints went away with the memory of unloaded libraries, so there is nothing to
+        // remove from the inferior
+        let is_loaded =
+            |path: &str, bias: usize| loaded.iter().any(|lib| lib.0 == path && lib.1 == bias);
+        let unloaded: Vec<(usize, usize)> = self
+            .debug_data
+            .libraries()
+            .iter()
+            .filter(|lib| !is_loaded(lib.path, lib.bias))
+            .map(|lib| (lib.start, lib.end))
+            .collect();
+        self.debug_data.retain_libraries(is_loaded);
+        let orphaned: Vec<usize> = self
+            .breakpoints
+            .values()
+            .filter(|bp| unloaded.iter().any(|(start, end)| bp.addr >= *start && bp.addr < *end))
+            .map(|bp| bp.addr)
+            .collect();
+        for addr in orphaned {
+            let bp = self.breakpoints.remove(&addr).unwrap();
+            self.pending_breakpoints.push(bp);
+        }
+
+        for (path, bias) in &loaded {
+            let libraries = self.debug_data.libraries();
+            if libraries.iter().any(|lib| lib.path == path && lib.bias == *bias) {
+                continue;
+            }
+            if let Err(DwarfError::DwarfFormatError(err)) = self.debug_data.add_library(path, *bias) {
+                console!("Could not read debugging symbols from {}: {:?}", path, err);
+            }
+        }
+        let pending = std::mem::replace(&mut self.pending_breakpoints, Vec::new());
+        self.plant_breakpoints(pending);
+        self.update_completions();
+    }
+
+    /// Lets tab completion offer the functions and files in the debugging information we have now.
+    fn update_completions(&mut self) {
+        if let Some(helper) = self.readline.helper_mut() {
+            helper.update(&self.debug_data);
+        }
+    }
+
+    /// Lists the shared libraries loaded in the inferior, as `info sharedlibrary` does in gdb.
+    fn print_libraries(&self) {
+        let libraries = self.debug_data.libraries();
+        if libraries.is_empty() {
+            console!("No shared libraries loaded at this time.");
+            return;
+        }
+        console!("{:<19} {:<19} {:<11} {}", "From", "To", "Syms Read", "Shared Object Library");
+        for lib in &libraries {
+            let syms = if lib.has_debug_info { "Yes" } else { "Yes (*)" };
+            console!("{:#018x}  {:#018x}  {:<11} {}", lib.start, lib.end, syms, lib.path);
+        }
+        if libraries.iter().any(|lib| !lib.has_debug_info) {
+            console!("(*): Shared library is missing debugging information.");
+        }
+    }
+
+    /// Reads the word at `addr` in the inferior.
+    fn read_word(&self, addr: usize) -> Option<usize> {
+        let inferior = self.inferior.as_ref()?;
+        let bytes = inferior.read_bytes(addr, size_of::<usize>(), &self.breakpoints).ok()?;
+        Some(usize::from_le_bytes(bytes[..].try_into().unwrap()))
+    }
+
+    /// Reads the NUL-terminated string at `addr` in the inferior.
+    fn read_c_string(&self, addr: usize) -> Option<String> {
+        let inferior = self.inferior.as_ref()?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = inferior.read_bytes(addr + bytes.len(), 1, &self.breakpoints).ok()?[0];
+            if byte == 0 {
+                return Some(String::from_utf8_lossy(&bytes).to_string());
+            }
+            bytes.push(byte);
+        }
+    }
+
+    /// Switches to the debugging information of the program at `path`, keeping the current one if
+    /// it can't be loaded.
+    fn load_program(&mut self, path: &str) {
+        match DwarfData::from_file(path) {
+            Ok(debug_data) => {
+                self.debug_data = debug_data;
+                self.target = path.to_string();
+                self.update_completions();
+            }
+            Err(DwarfError::ErrorOpeningFile) => console!("Could not open file {}", path),
+            Err(DwarfError::DwarfFormatError(err)) => {
+                console!("Could not debugging symbols from {}: {:?}", path, err)
+            }
+        }
+    }
+
+    /// Lists the threads of the inferior, with the innermost frame of each.
+    fn print_threads(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let current = inferior.current_thread();
+        let threads: Vec<(usize, Pid)> =
+            inferior.threads().iter().map(|thread| (thread.id, thread.tid)).collect();
+        console!("  {:<4} {:<17} {}", "Id", "Target Id", "Frame");
+        for &(id, tid) in &threads {
+            self.inferior.as_mut().unwrap().select_thread(id);
+            let frame = match self.inferior.as_ref().unwrap().backtrace(&self.debug_data) {
+                Ok(frames) if !frames.is_empty() => self.frame_summary(0, &frames[0]),
+                Ok(_) => "No stack.".to_string(),
+                Err(err) => format!("Can not read the stack: {}", err),
+            };
+            let marker = if id == current { '*' } else { ' ' };
+            console!("{} {:<4} {:<17} {}", marker, id, format!("LWP {}", tid), frame);
+        }
+        self.inferior.as_mut().unwrap().select_thread(current);
+        output::emit(Event::Threads {
+            threads: threads.iter().map(|(id, tid)| (*id, tid.as_raw())).collect(),
+            current,
+        });
+    }
+
+    /// Makes thread `id` the current one, whose registers and stack we look at, and shows where
+    /// it is.
+    fn select_thread(&mut self, id: usize) {
+        let tid = match self.inferior.as_mut().unwrap().select_thread(id) {
+            Some(tid) => tid,
+            None => {
+                console!("Invalid thread ID: {}", id);
+                return;
+            }
+        };
+        self.selected_frame = 0;
+        self.last_listed = None;
+        console!("[Switching to thread {} (LWP {})]", id, tid);
+        self.select_frame(|_, _| Ok(0));
+    }
+
+    /// Lists the current inferior and those left stopped after a fork.
+    fn print_inferiors(&self) {
+        let current = self.inferior.as_ref().map(|inferior| (self.inferior_id, inferior));
+        let mut inferiors: Vec<(usize, &Inferior)> = current.into_iter().collect();
+        inferiors.extend(self.suspended.iter().map(|(id, inferior)| (*id, inferior)));
+        if inferiors.is_empty() {
+            console!("No inferiors.");
+            return;
+        }
+        inferiors.sort_by_key(|(id, _)| *id);
+        console!("  {:<4} {:<17} {}", "Num", "Description", "Executable");
+        for (id, inferior) in inferiors {
+            let marker = if id == self.inferior_id { '*' } else { ' ' };
+            let exe = fs::read_link(format!("/proc/{}/exe", inferior.pid()))
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let description = format!("process {}", inferior.pid());
+            console!("{} {:<4} {:<17} {}", marker, id, description, exe);
+        }
+    }
+
+    /// Makes the suspended inferior numbered `id` the current one. The previous one stays
+    /// stopped until it is selected again.
+    fn select_inferior(&mut self, id: usize) {
+        if let Some(inferior) = self.inferior.as_ref().filter(|_| id == self.inferior_id) {
+            console!("[Current inferior is {} (process {})]", id, inferior.pid());
+            return;
+        }
+        let index = match self.suspended.iter().position(|(other, _)| *other == id) {
+            Some(index) => index,
+            None => {
+                console!("Inferior ID {} not known.", id);
+                return;
+            }
+        };
+        let (_, inferior) = self.suspended.remove(index);
+        let pid = inferior.pid();
+        if let Some(previous) = self.inferior.replace(inferior) {
+            self.suspended.push((self.inferior_id, previous));
+        }
+        self.inferior_id = id;
+        self.pending_signal = None;
+        console!("[Switching to inferior {} (process {})]", id, pid);
+        if let Ok(path) = fs::read_link(format!("/proc/{}/exe", pid)) {
+            let path = path.to_string_lossy().to_string();
+            if path != self.target {
+                self.load_program(&path);
+            }
+        }
+        let res = self
+            .inferior
+            .as_ref()
+            .unwrap()
+            .getregs()
+            .map(|regs| Status::Stopped(Signal::SIGSTOP, regs.rip as usize, None));
+        self.report_status(res);
+    }
+
+    /// Once the current inferior is gone, carries on with the most recent one left stopped after
+    /// a fork, if any.
+    fn switch_to_suspended(&mut self) {
+        if let Some((id, inferior)) = self.suspended.pop() {
+            console!("[Switching to inferior {} (process {})]", id, inferior.pid());
+            self.inferior = Some(inferior);
+            self.inferior_id = id;
+            self.pending_signal = None;
+        }
+    }
+
+    /// Kills the inferiors left stopped after forks, or detaches from them if they came from a
+    /// process we attached to.
+    fn kill_suspended(&mut self) {
+        for (_, mut inferior) in self.suspended.drain(..) {
+            let pid = inferior.pid();
+            let res = if inferior.is_attached() {
+                inferior.detach(&self.breakpoints)
+            } else {
+                inferior.kill()
+            };
+            if let Err(err) = res {
+                console!("Can not stop process {}: {}", pid, err);
+            }
+        }
+    }
+
+    /// Called when the inferior reaches the breakpoint at `addr`. Evaluates the breakpoint's
+    /// condition and ignore count, updating its hit count, and returns whether we should stop.
+    fn breakpoint_should_stop(&mut self, addr: usize) -> bool {
+        if !self.breakpoint_condition_holds(addr) {
+            return false;
+        }
+
+        let bp = self.breakpoints.get_mut(&addr).unwrap();
+        bp.hit_count += 1;
+        if bp.ignore_count > 0 {
+            bp.ignore_count -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// Returns whether the breakpoint at `addr` has no condition or its condition holds. A
+    /// condition that can't be evaluated counts as holding, after saying why.
+    fn breakpoint_condition_holds(&self, addr: usize) -> bool {
+        let condition = match &self.breakpoints[&addr].condition {
+            Some(condition) => condition,
+            None => return true,
+        };
+        match self.evaluate_operand(&condition.lhs) {
+            Ok(value) => condition.holds(value),
+            Err(err) => {
+                console!("Error in breakpoint condition \"{}\": {}", condition, err);
+                true
+            }
+        }
+    }
+
+    /// Returns the current value of the left-hand side of a breakpoint condition.
+    fn evaluate_operand(&self, operand: &Operand) -> Result<i64, String> {
+        match operand {
+            Operand::Register(name) => {
+                match self.inferior.as_ref().unwrap().get_register(name) {
+                    Ok(Some(value)) => Ok(value as i64),
+                    Ok(None) => Err(format!("Invalid register \"{}\"", name)),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            Operand::Variable(name) => match self.lookup_variable(name) {
+                Some(var) => match self.read_variable_bytes(&var) {
+                    Ok(bytes) => Ok(var.entity_type.int_value(&bytes)),
+                    Err(err) => Err(err.to_string()),
+                },
+                None => Err(format!("No symbol \"{}\" in current context.", name)),
+            },
+        }
+    }
+
+    /// Prints the outcome of resuming the inferior. Forgets about the inferior once it is gone.
+    fn report_status(&mut self, res: Result<Status, nix::Error>) {
+        // The next plain `list` should show the code around wherever we stopped
+        self.last_listed = None;
+        self.selected_frame = 0;
+        match res {
+            Ok(status) => match status {
+                Status::Stopped(sign, _rip, hit) => {
+                    let inferior = self.inferior.as_ref().unwrap();
+                    let tid = inferior.tid();
+                    if inferior.threads().len() > 1 && self.stopped_thread != Some(tid) {
+                        console!("[Switching to thread {} (LWP {})]", inferior.current_thread(), tid);
+                    }
+                    self.stopped_thread = Some(tid);
+                    console!("Child stopped (signal {})", sign);
+                    match sign {
+                        Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGFPE | Signal::SIGILL => {
+                            self.print_fault(sign)
+                        }
+                        _ => {}
+                    }
+                    self.delete_watchpoints_out_of_scope();
+                    if let Some(hit) = &hit {
+                        if let Some(wp) = self.watchpoints.iter().find(|wp| wp.slot == Some(hit.slot)) {
+                            let size = wp.entity_type.size;
+                            console!("Hardware watchpoint {}: {}", wp.id, wp.expr);
+                            console!();
+                            console!("Old value = {}", wp.entity_type.format_value(&hit.old_value.to_le_bytes()[..size]));
+                            console!("New value = {}", wp.entity_type.format_value(&hit.new_value.to_le_bytes()[..size]));
+                        }
+                    }
+                    match self.debug_data.get_line_from_addr(_rip) {
+                        Some(line_info) => {
+                            console!("Stopped at {}: {}", line_info.file, line_info.number);
+                            self.sources.print_lines(
+                                &line_info.file,
+                                line_info.number.saturating_sub(2),
+                                line_info.number + 2,
+                                Some(line_info.number),
+                            );
+                        }
+                        None => {
+                            console!("Stopped at %rip register: {:#x}", _rip);
+                            // Without source to show, at least show the instruction
+                            self.print_instructions(_rip, 1, None);
+                        }
+                    };
+                    self.report_stop(sign, _rip, hit.is_some());
+                }
+                Status::Exited(code) => {
+                    if let Some(inferior) = self.inferior.as_mut() {
+                        inferior.flush_output();
+                    }
+                    console!("The program exited with code {}", code);
+                    output::emit(Event::Exited { code: Some(code), signal: None });
+                    self.inferior = None;
+                    self.delete_watchpoints_out_of_scope();
+                    self.switch_to_suspended();
+                }
+                Status::Signaled(sign) => {
+                    if let Some(inferior) = self.inferior.as_mut() {
+                        inferior.flush_output();
+                    }
+                    console!("The program stop by sign {}", sign);
+                    output::emit(Event::Exited {
+                        code: None,
+                        signal: Some(sign.as_str().to_string()),
+                    });
+                    self.inferior = None;
+                    self.delete_watchpoints_out_of_scope();
+                    self.switch_to_suspended();
+                }
+                // These are dealt with by follow_event as soon as they happen
+                Status::Forked(_) | Status::Cloned(_) | Status::Execed => {}
+            },
+            Err(err) => {
+                console!("{}", err);
+            }
+        }
+    }
+
+    /// Tells a front end why and where the inferior stopped.
+    fn report_stop(&self, signal: Signal, rip: usize, watchpoint: bool) {
+        let breakpoint = self
+            .breakpoints
+            .get(&rip)
+            .filter(|bp| signal == Signal::SIGTRAP && !bp.is_internal())
+            .map(|bp| bp.id);
+        let reason = if watchpoint {
+            "watchpoint-trigger"
+        } else if breakpoint.is_some() {
+            "breakpoint-hit"
+        } else if signal == Signal::SIGTRAP {
+            "end-stepping-range"
+        } else {
+            "signal-received"
+        };
+        let line = self.debug_data.get_line_from_addr(rip);
+        output::emit(Event::Stopped {
+            reason,
+            signal: signal.as_str().to_string(),
+            thread: self.inferior.as_ref().unwrap().current_thread(),
+            rip,
+            breakpoint,
+            function: self.debug_data.get_function_from_addr(rip),
+            file: line.as_ref().map(|line| line.file.clone()),
+            line: line.map(|line| line.number),
+        });
+    }
+
+    /// Updates the `handle` table for the signal called `name`, or for every signal but SIGTRAP
+    /// and SIGINT if it is "all", then shows the new settings.
+    fn handle_signal(&mut self, name: &str, keywords: &[String]) {
+        let targets: Vec<Signal> = if name == "all" {
+            Signal::iterator()
+                .filter(|sig| *sig != Signal::SIGTRAP && *sig != Signal::SIGINT)
+                .collect()
+        } else {
+            match signals::parse_signal(name) {
+                Some(signal) => vec![signal],
+                None => {
+                    console!("Unrecognized signal \"{}\".", name);
+                    return;
+                }
+            }
+        };
+        for signal in &targets {
+            if let Err(err) = self.signals.update(*signal, keywords) {
+                console!("{}", err);
+                return;
+            }
+        }
+        self.signals.print(&targets);
+    }
+
+    /// Prints the address that caused a fault, taken from the siginfo of the signal.
+    fn print_fault(&self, sign: Signal) {
+        match self.inferior.as_ref().unwrap().fault_info() {
+            Ok((addr, code)) => match signals::describe_fault(sign, code) {
+                Some(reason) => console!("Fault address: {:#x} ({})", addr, reason),
+                None => console!("Fault address: {:#x}", addr),
+            },
+            Err(err) => console!("Can not read siginfo: {}", err),
+        }
+    }
+
+    /// Single-steps the inferior until it reaches the start of a different source line. Calls
+    /// into functions without line information are always run to completion; with `over_calls`
+    /// set, every call is.
+    fn step_line(&mut self, over_calls: bool) -> Result<Status, nix::Error> {
+        // Like stepi, stepping discards a signal the inferior stopped with
+        self.pending_signal = None;
+        let start_rip = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+        let start_line = match self.debug_data.get_line_from_addr(start_rip) {
+            Some(line) => line,
+            None => {
+                console!("No line information for {:#x}, use stepi instead", start_rip);
+                return Ok(Status::Stopped(Signal::SIGTRAP, start_rip, None));
+            }
+        };
+
+        loop {
+            let prev_regs = self.inferior.as_ref().unwrap().getregs()?;
+            let mut rip = match self.step_instruction()? {
+                Status::Stopped(Signal::SIGTRAP, rip, None) => rip,
+                status => return Ok(status),
+            };
+
+            // A call pushes the address of the instruction following it
+            let inferior = self.inferior.as_mut().unwrap();
+            let regs = inferior.getregs()?;
+            if regs.rsp == prev_regs.rsp - 8 {
+                let pushed = inferior.read_bytes(regs.rsp as usize, 8, &self.breakpoints)?;
+                let ret_addr = u64::from_le_bytes(pushed[..].try_into().unwrap());
+                let is_call = ret_addr > prev_regs.rip && ret_addr < prev_regs.rip + 16;
+                if is_call && (over_calls || self.debug_data.get_line_from_addr(rip).is_none()) {
+                    let ret_addr = ret_addr as usize;
+                    match self.resume(Some((ret_addr, prev_regs.rsp as usize)))? {
+                        Status::Stopped(Signal::SIGTRAP, stop, None) if stop == ret_addr => rip = stop,
+                        status => return Ok(status),
+                    }
+                } else if is_call {
+                    // Stepped into a function: run its prologue, so that the frame is set up and
+                    // the arguments can be read when we stop
+                    let body = self.debug_data.skip_prologue(rip);
+                    while rip != body {
+                        match self.step_instruction()? {
+                            Status::Stopped(Signal::SIGTRAP, next, None) => rip = next,
+                            status => return Ok(status),
+                        }
+                    }
+                    return Ok(Status::Stopped(Signal::SIGTRAP, rip, None));
+                }
+            }
+
+            match self.debug_data.get_line_from_addr(rip) {
+                // We left the code we have debugging information for (e.g. main returned), so
+                // there is no next line to stop at
+                None => return self.resume(None),
+                Some(line) => {
+                    let new_line = line.file != start_line.file || line.number != start_line.number;
+                    if new_line && self.debug_data.is_line_start(rip) {
+                        return Ok(Status::Stopped(Signal::SIGTRAP, rip, None));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if the inferior is being recorded, and can therefore run backwards. Otherwise
+    /// says why not.
+    fn check_recording(&self) -> bool {
+        match &self.inferior {
+            Some(inferior) if inferior.recording().is_some() => true,
+            Some(_) => {
+                console!("No recording is currently active.");
+                console!("Use the \"record\" command first.");
+                false
+            }
+            None => {
+                console!("No processes are running!");
+                false
+            }
+        }
+    }
+
+    /// Undoes the last instruction recorded. Returns false, after saying so, if the log has run
+    /// out.
+    fn undo_instruction(&mut self) -> Result<bool, nix::Error> {
+        let undone = self.inferior.as_mut().unwrap().reverse_step_instruction()?;
+        if !undone {
+            console!("No more reverse-execution history.");
+        }
+        Ok(undone)
+    }
+
+    /// Returns the status to report after running backwards: stopped wherever %rip now is, as
+    /// after a step.
+    fn reverse_stop(&self) -> Result<Status, nix::Error> {
+        let rip = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+        Ok(Status::Stopped(Signal::SIGTRAP, rip, None))
+    }
+
+    /// Runs the inferior backwards by one machine instruction.
+    fn reverse_step_instruction(&mut self) -> Result<Status, nix::Error> {
+        // Like stepi, this discards a signal the inferior stopped with
+        self.pending_signal = None;
+        self.undo_instruction()?;
+        self.reverse_stop()
+    }
+
+    /// Runs the inferior backwards to the start of the line that ran before the current one. If
+    /// that was the end of a call to a function with line information, we stop at the start of
+    /// the last line it ran; other calls are gone back through in one go.
+    fn reverse_step_line(&mut self) -> Result<Status, nix::Error> {
+        self.pending_signal = None;
+        let line_at = |debugger: &Debugger, rip: u64| {
+            let rip = rip as usize;
+            let func = debugger.debug_data.get_function_from_addr(rip)?;
+            let start = debugger.debug_data.get_addr_for_function(None, &func)?;
+            // A prologue counts as part of the call into its function, as when stepping forward
+            if rip < debugger.debug_data.skip_prologue(start) {
+                return None;
+            }
+            let line = debugger.debug_data.get_line_from_addr(rip)?;
+            Some((line.file, line.number))
+        };
+        let start_line = line_at(self, self.inferior.as_ref().unwrap().getregs()?.rip);
+
+        // Back out of the current line, and of any code without line information before it
+        let (line, frame_rsp) = loop {
+            if !self.undo_instruction()? {
+                return self.reverse_stop();
+            }
+            let regs = self.inferior.as_ref().unwrap().getregs()?;
+            let line = line_at(self, regs.rip);
+            if line.is_some() && line != start_line {
+                break (line, regs.rsp);
+            }
+        };
+
+        // Then go back to where that line started, through any calls it made to code without
+        // line information, which run further down the stack
+        let mut rip = self.inferior.as_ref().unwrap().getregs()?.rip;
+        loop {
+            let prev = match self.inferior.as_ref().unwrap().recording().and_then(Recording::last_regs) {
+                Some(prev) => *prev,
+                None => {
+                    console!("No more reverse-execution history.");
+                    break;
+                }
+            };
+            let prev_line = line_at(self, prev.rip);
+            let in_call = prev_line.is_none() && prev.rsp < frame_rsp;
+            // The previous line may have ended with a call too
+            let at_start = self.debug_data.is_line_start(rip as usize) && prev_line != line;
+            if (prev_line != line && !in_call) || at_start {
+                break;
+            }
+            self.undo_instruction()?;
+            rip = prev.rip;
+        }
+        self.reverse_stop()
+    }
+
+    /// Runs the inferior backwards until it reaches an enabled breakpoint whose condition holds,
+    /// or the start of the log.
+    fn reverse_continue(&mut self) -> Result<Status, nix::Error> {
+        self.pending_signal = None;
+        while self.undo_instruction()? {
+            let rip = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+            let is_breakpoint = self
+                .breakpoints
+                .get(&rip)
+                .map_or(false, |bp| bp.enabled && !bp.is_internal());
+            // Only running forward counts as hitting the breakpoint
+            if is_breakpoint && self.breakpoint_condition_holds(rip) {
+                let commands = self.breakpoints[&rip].commands.clone();
+                self.queue_input(commands);
+                break;
+            }
+        }
+        self.reverse_stop()
+    }
+
+    /// Runs the inferior until the selected frame returns to its caller.
+    fn finish(&mut self) -> Result<Status, nix::Error> {
+        let rip = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+        let (index, frame) = match self.selected_stack_frame()? {
+            Some(selected) => selected,
+            None => {
+                console!("No stack.");
+                return Ok(Status::Stopped(Signal::SIGTRAP, rip, None));
+            }
+        };
+        let func = self.debug_data.get_function_from_addr(frame_code_address(index, &frame));
+        let return_address = match frame.return_address {
+            Some(addr) if func.as_deref() != Some("main") => addr,
+            _ => {
+                console!("\"finish\" not meaningful in the outermost frame.");
+                return Ok(Status::Stopped(Signal::SIGTRAP, rip, None));
+            }
+        };
+        console!("Run till exit from {}", func.unwrap_or_else(|| "??".to_string()));
+        self.resume(Some((return_address, frame.cfa)))
+    }
+
+    /// Returns the function of the selected frame, if we have debugging information for it.
+    fn current_function(&self) -> Option<Function> {
+        self.inferior.as_ref()?;
+        let (index, frame) = self.selected_stack_frame().ok()??;
+        let func_name = self.debug_data.get_function_from_addr(frame_code_address(index, &frame))?;
+        self.debug_data.get_function(&func_name).cloned()
+    }
+
+    /// Resolves `name` to a variable of the current function, falling back to global variables.
+    fn lookup_variable(&self, name: &str) -> Option<Variable> {
+        if let Some(func) = self.current_function() {
+            if let Some(var) = func.variables.iter().find(|var| var.name == name) {
+                return Some(var.clone());
+            }
+        }
+        self.debug_data.get_global_variable(name).cloned()
+    }
+
+    /// Reads the value of `var` from the inferior's current frame and formats it according to
+    /// its type.
+    fn read_variable(&self, var: &Variable) -> Result<String, nix::Error> {
+        let bytes = self.read_variable_bytes(var)?;
+        Ok(var.entity_type.format_value(&bytes))
+    }
+
+    /// Reads the raw bytes of `var` from the inferior's current frame.
+    fn read_variable_bytes(&self, var: &Variable) -> Result<Vec<u8>, nix::Error> {
+        let addr = self.variable_address(var)?;
+        let inferior = self.inferior.as_ref().unwrap();
+        inferior.read_bytes(addr, var.entity_type.size, &self.breakpoints)
+    }
+
+    /// Returns the address of `var` in the selected frame.
+    fn variable_address(&self, var: &Variable) -> Result<usize, nix::Error> {
+        let cfa = match self.selected_stack_frame()? {
+            Some((_, frame)) => frame.cfa,
+            // With a standard %rbp frame the CFA sits 16 bytes above %rbp, past the saved %rbp
+            // and the return address
+            None => self.inferior.as_ref().unwrap().getregs()?.rbp as usize + 16,
+        };
+        Ok(frame_variable_address(var, cfa))
+    }
+
+    /// Returns the selected frame and its level, or the outermost frame if the stack has become
+    /// shallower since it was selected.
+    fn selected_stack_frame(&self) -> Result<Option<(usize, StackFrame)>, nix::Error> {
+        let mut frames = self.inferior.as_ref().unwrap().backtrace(&self.debug_data)?;
+        if frames.is_empty() {
+            return Ok(None);
+        }
+        let index = self.selected_frame.min(frames.len() - 1);
+        Ok(Some((index, frames.swap_remove(index))))
+    }
+
+    /// Prints the call stack, gdb style, with the arguments each function was called with.
+    fn print_backtrace(&self) -> Result<(), nix::Error> {
+        let frames = self.inferior.as_ref().unwrap().backtrace(&self.debug_data)?;
+        for (index, frame) in frames.iter().enumerate() {
+            console!("{}", self.describe_frame(index, frame));
+        }
+        output::emit(Event::Backtrace(
+            frames
+                .iter()
+                .enumerate()
+                .map(|(index, frame)| {
+                    let addr = frame_code_address(index, frame);
+                    let line = self.debug_data.get_line_from_addr(addr);
+                    Frame {
+                        level: index,
+                        addr: frame.rip,
+                        function: self.debug_data.get_function_from_addr(addr),
+                        file: line.as_ref().map(|line| line.file.clone()),
+                        line: line.map(|line| line.number),
+                    }
+                })
+                .collect(),
+        ));
+        Ok(())
+    }
+
+    /// Formats a backtrace line for frame number `index`, e.g.
+    /// "#1  0x00000000004011b5 in func2 (a=42, b=5) at function_calls.c:13".
+    fn describe_frame(&self, index: usize, frame: &StackFrame) -> String {
+        format!("#{:<3}{}", index, self.frame_summary(index, frame))
+    }
+
+    /// Formats where frame number `index` is, e.g. "func2 (a=42, b=5) at function_calls.c:13".
+    /// Frames other than the innermost one start with their return address.
+    fn frame_summary(&self, index: usize, frame: &StackFrame) -> String {
+        let inferior = self.inferior.as_ref().unwrap();
+        let addr = frame_code_address(index, frame);
+        let name = self.debug_data.get_function_from_addr(addr);
+        let args = match name.as_ref().and_then(|name| self.debug_data.get_function(name)) {
+            Some(func) => func
+                .variables
+                .iter()
+                .filter(|var| var.is_parameter)
+                .map(|var| {
+                    let addr = frame_variable_address(var, frame.cfa);
+                    match inferior.read_bytes(addr, var.entity_type.size, &self.breakpoints) {
+                        Ok(bytes) => format!("{}={}", var.name, var.entity_type.format_value(&bytes)),
+                        Err(_) => format!("{}=<unreadable>", var.name),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", "),
+            None => String::new(),
+        };
+        let location = match self.debug_data.get_line_from_addr(addr) {
+            Some(line) => format!(" at {}", line),
+            None => String::new(),
+        };
+        let name = name.unwrap_or_else(|| "??".to_string());
+        if index == 0 {
+            format!("{} ({}){}", name, args, location)
+        } else {
+            format!("{:#018x} in {} ({}){}", frame.rip, name, args, location)
+        }
+    }
+
+    /// Implements `up`, `down` and `frame N`. `pick` chooses the frame to select given the
+    /// currently selected level and the number of frames, or returns the error to print.
+    fn select_frame<F>(&mut self, pick: F)
+    where
+        F: FnOnce(usize, usize) -> Result<usize, String>,
+    {
+        let frames = match self.inferior.as_ref().unwrap().backtrace(&self.debug_data) {
+            Ok(frames) if !frames.is_empty() => frames,
+            Ok(_) => {
+                console!("No stack.");
+                return;
+            }
+            Err(err) => {
+                console!("Can not read the stack: {}", err);
+                return;
+            }
+        };
+        let current = self.selected_frame.min(frames.len() - 1);
+        let index = match pick(current, frames.len()) {
+            Ok(index) => index,
+            Err(message) => {
+                console!("{}", message);
+                return;
+            }
+        };
+        self.selected_frame = index;
+        self.last_listed = None;
+
+        let frame = &frames[index];
+        console!("{}", self.describe_frame(index, frame));
+        if let Some(line) = self.debug_data.get_line_from_addr(frame_code_address(index, frame)) {
+            self.sources.print_lines(&line.file, line.number, line.number, None);
+        }
+    }
+
+    /// Implements `info frame`: describes the selected frame and where it saved its caller's
+    /// registers.
+    fn print_frame_info(&self) -> Result<(), nix::Error> {
+        let frames = self.inferior.as_ref().unwrap().backtrace(&self.debug_data)?;
+        if frames.is_empty() {
+            console!("No stack.");
+            return Ok(());
+        }
+        let index = self.selected_frame.min(frames.len() - 1);
+        let frame = &frames[index];
+        let addr = frame_code_address(index, frame);
+
+        console!("Stack level {}, frame at {:#x}:", index, frame.cfa);
+        let mut rip_line = format!(" rip = {:#x}", frame.rip);
+        if let Some(name) = self.debug_data.get_function_from_addr(addr) {
+            rip_line.push_str(&format!(" in {}", name));
+        }
+        if let Some(line) = self.debug_data.get_line_from_addr(addr) {
+            rip_line.push_str(&format!(" ({})", line));
+        }
+        if let Some(return_address) = frame.return_address {
+            rip_line.push_str(&format!("; saved rip = {:#x}", return_address));
+        }
+        console!("{}", rip_line);
+        if let Some(caller) = frames.get(index + 1) {
+            console!(" called by frame at {:#x}", caller.cfa);
+        }
+        if index > 0 {
+            console!(" caller of frame at {:#x}", frames[index - 1].cfa);
+        }
+        console!(" rbp = {:#x}, previous frame's sp is {:#x}", frame.rbp, frame.cfa);
+        if !frame.saved_registers.is_empty() {
+            console!(" Saved registers:");
+            let saved: Vec<String> = frame
+                .saved_registers
+                .iter()
+                .map(|(reg, addr)| format!("{} at {:#x}", unwind::REGISTER_NAMES[*reg], addr))
+                .collect();
+            console!("  {}", saved.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Prints the registers called `names`, or all of them if `names` is empty.
+    fn print_registers(&self, names: &[String]) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let names: Vec<&str> = if names.is_empty() {
+            REGISTER_NAMES.to_vec()
+        } else {
+            names.iter().map(|name| name.as_str()).collect()
+        };
+        for name in names {
+            let value = match inferior.get_register(name) {
+                Ok(Some(value)) => value,
+                Ok(None) => {
+                    console!("Invalid register \"{}\"", name);
+                    continue;
+                }
+                Err(err) => {
+                    console!("Can not read registers: {}", err);
+                    return;
+                }
+            };
+            let natural = match name {
+                "eflags" => format_eflags(value),
+                "rip" => match self.symbolize(value as usize) {
+                    Some(symbol) => format!("{:#x} {}", value, symbol),
+                    None => format!("{:#x}", value),
+                },
+                "rbp" | "rsp" | "fs_base" | "gs_base" => format!("{:#x}", value),
+                _ => format!("{}", value as i64),
+            };
+            console!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural);
+        }
+    }
+
+    /// Returns "<func+offset>" for an address inside a function we have debugging information
+    /// for.
+    fn symbolize(&self, addr: usize) -> Option<String> {
+        let func = self.debug_data.get_function_from_addr(addr)?;
+        let start = self.debug_data.get_addr_for_function(None, &func)?;
+        if addr == start {
+            Some(format!("<{}>", func))
+        } else {
+            Some(format!("<{}+{}>", func, addr - start))
+        }
+    }
+
+    /// Stores the value of `rhs` in the object named by `lhs`, converted to its type.
+    fn set_variable(&mut self, lhs: &str, rhs: &str) -> Result<(), String> {
+        let (addr, bytes) = expression::assign(lhs, rhs, self)?;
+        self.inferior
+            .as_mut()
+            .unwrap()
+            .write_memory(addr, &bytes, &mut self.breakpoints)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+    }
+
+    /// Evaluates the address operand of `x`, which can be any expression, e.g. a number, a
+    /// `$register`, `&variable` or a pointer.
+    fn resolve_address(&self, expr: &str) -> Result<usize, String> {
+        let expr = expr.trim();
+        if expr.is_empty() {
+            return Err("Argument required (starting display address).".to_string());
+        }
+        let value = expression::evaluate(expr, self)?;
+        match value.dtype.strip_aliases().kind {
+            // Like C, use the address of an array rather than its contents
+            TypeKind::Array { .. } => value.address().ok_or_else(|| "Invalid address.".to_string()),
+            _ => {
+                let bytes = value.bytes(self)?;
+                Ok(value.dtype.int_value(&bytes) as usize)
+            }
+        }
+    }
+
+    /// Implements `x/Nfu`: dumps `examine.count` units of memory starting at `addr`.
+    fn examine_memory(&self, examine: &ExamineFormat, addr: usize) -> Result<(), nix::Error> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let label = |addr: usize| match self.symbolize(addr) {
+            Some(symbol) => format!("{:#x} {}:", addr, symbol),
+            None => format!("{:#x}:", addr),
+        };
+
+        if examine.format == 'i' {
+            self.print_instructions(addr, examine.count, None);
+            return Ok(());
+        }
+
+        if examine.format == 's' {
+            let mut addr = addr;
+            for _ in 0..examine.count {
+                let mut bytes = Vec::new();
+                loop {
+                    let byte = inferior.read_bytes(addr + bytes.len(), 1, &self.breakpoints)?[0];
+                    if byte == 0 {
+                        break;
+                    }
+                    bytes.push(byte);
+                }
+                console!("{}\t\"{}\"", label(addr), String::from_utf8_lossy(&bytes).escape_debug());
+                addr += bytes.len() + 1;
+            }
+            return Ok(());
+        }
+
+        let size = examine.unit_size;
+        let per_line = match (examine.format, size) {
+            ('c', _) | (_, 1) | (_, 2) => 8,
+            (_, 4) => 4,
+            _ => 2,
+        };
+        let bytes = inferior.read_bytes(addr, examine.count * size, &self.breakpoints)?;
+        for (i, units) in bytes.chunks(size * per_line).enumerate() {
+            let mut line = label(addr + i * size * per_line);
+            for unit in units.chunks(size) {
+                let mut buf = [0u8; 8];
+                buf[..size].copy_from_slice(unit);
+                let value = u64::from_le_bytes(buf);
+                line += &match examine.format {
+                    'c' => format!("\t{} {}", unit[0] as i8, format_char(unit[0])),
+                    'd' => {
+                        let shift = 64 - 8 * size as u32;
+                        format!("\t{}", ((value << shift) as i64) >> shift)
+                    }
+                    _ => format!("\t{:#0width$x}", value, width = 2 + 2 * size),
+                };
+            }
+            console!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// Implements `list [line|func]`: prints source lines around the given line of the current
+    /// file or the start of the given function. Without an argument, continues after the previous
+    /// listing, or lists around the current line.
+    fn list(&mut self, target: Option<String>) {
+        let (file, center) = match target {
+            None => {
+                if let Some((file, last)) = self.last_listed.clone() {
+                    if let Some(last) =
+                        self.sources.print_lines(&file, last + 1, last + LIST_SIZE, None)
+                    {
+                        self.last_listed = Some((file, last));
+                    }
+                    return;
+                }
+                match self.current_line() {
+                    Some(line) => (line.file, line.number),
+                    None => {
+                        console!("No symbol table is loaded.");
+                        return;
+                    }
+                }
+            }
+            Some(target) => match target.parse::<usize>() {
+                Ok(number) => match self.current_line() {
+                    Some(line) => (line.file, number),
+                    None => {
+                        console!("No symbol table is loaded.");
+                        return;
+                    }
+                },
+                Err(_) if target.contains(':') => match parse_file_line(&target).and_then(
+                    |(file, number)| {
+                        let addr = self.debug_data.get_addr_for_line(Some(file), number)?;
+                        Some((self.debug_data.get_line_from_addr(addr)?.file, number))
+                    },
+                ) {
+                    Some(location) => location,
+                    None => {
+                        console!("No source file named {}.", target);
+                        return;
+                    }
+                },
+                Err(_) => match self
+                    .debug_data
+                    .get_addr_for_function(None, &target)
+                    .and_then(|addr| self.debug_data.get_line_from_addr(addr))
+                {
+                    Some(line) => (line.file, line.number),
+                    None => {
+                        console!("Function \"{}\" not defined.", target);
+                        return;
+                    }
+                },
+            },
+        };
+
+        let first = center.saturating_sub(LIST_SIZE / 2).max(1);
+        if let Some(last) = self.sources.print_lines(&file, first, first + LIST_SIZE - 1, None) {
+            self.last_listed = Some((file, last));
+        }
+    }
+
+    /// Returns the source line of the selected frame, or the start of main if there is no
+    /// inferior.
+    fn current_line(&self) -> Option<Line> {
+        let addr = match self.inferior {
+            Some(_) => self
+                .selected_stack_frame()
+                .ok()
+                .flatten()
+                .map(|(index, frame)| frame_code_address(index, &frame)),
+            None => None,
+        };
+        match addr.and_then(|addr| self.debug_data.get_line_from_addr(addr)) {
+            Some(line) => Some(line),
+            None => self
+                .debug_data
+                .get_line_from_addr(self.debug_data.get_addr_for_function(None, "main")?),
+        }
+    }
+
+    /// Implements `disassemble [func|addr]`: prints the instructions of the given function, or of
+    /// the function containing the given address or the current %rip.
+    fn disassemble(&self, target: Option<String>) {
+        let addr = match &target {
+            None => match self.inferior.as_ref().unwrap().getregs() {
+                Ok(regs) => regs.rip as usize,
+                Err(err) => {
+                    console!("Can not read registers: {}", err);
+                    return;
+                }
+            },
+            Some(target) => match self.debug_data.get_function(target) {
+                Some(func) => func.address,
+                None => match self.resolve_address(target.trim_start_matches('*')) {
+                    Ok(addr) => addr,
+                    Err(err) => {
+                        console!("{}", err);
+                        return;
+                    }
+                },
+            },
+        };
+
+        let func = self
+            .debug_data
+            .get_function_from_addr(addr)
+            .and_then(|name| self.debug_data.get_function(&name).cloned());
+        match func {
+            Some(func) => {
+                console!("Dump of assembler code for function {}:", func.name);
+                self.print_instructions(func.address, usize::MAX, Some(func.address + func.text_length));
+            }
+            None => {
+                console!("No function contains specified address, dumping 10 instructions.");
+                console!("Dump of assembler code from {:#x}:", addr);
+                self.print_instructions(addr, 10, None);
+            }
+        }
+        console!("End of assembler dump.");
+    }
+
+    /// Disassembles up to `count` instructions starting at `addr`, stopping at `end` if given.
+    /// Each instruction is labelled with the function it belongs to, the current instruction is
+    /// marked with "=>", and a file:line header is printed whenever the source line changes.
+    fn print_instructions(&self, addr: usize, count: usize, end: Option<usize>) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let rip = inferior.getregs().map(|regs| regs.rip as usize).unwrap_or(0);
+        let len = match end {
+            Some(end) => end - addr,
+            None => count.saturating_mul(MAX_INSTRUCTION_LEN),
+        };
+
+        // Reading may fail near the end of a mapping, so retry with fewer bytes
+        let mut len = len;
+        let bytes = loop {
+            match inferior.read_bytes(addr, len, &self.breakpoints) {
+                Ok(bytes) => break bytes,
+                Err(err) if len <= 1 => {
+                    console!("Can not access memory at address {:#x}: {}", addr, err);
+                    return;
+                }
+                Err(_) => len /= 2,
+            }
+        };
+
+        let mut last_line = None;
+        for instr in disassembler::decode(&bytes, addr, count) {
+            if let Some(line) = self.debug_data.get_line_from_addr(instr.addr) {
+                if last_line.as_ref() != Some(&(line.file.clone(), line.number)) {
+                    console!("{}:", line);
+                    last_line = Some((line.file.clone(), line.number));
+                }
+            }
+            let marker = if instr.addr == rip { "=> " } else { "   " };
+            let label = match self.symbolize(instr.addr) {
+                Some(symbol) => format!("{:#018x} {}", instr.addr, symbol),
+                None => format!("{:#018x}", instr.addr),
+            };
+            let target = match instr.branch_target.and_then(|target| self.symbolize(target)) {
+                Some(symbol) => format!(" {}", symbol),
+                None => String::new(),
+            };
+            console!("{}{}:\t{}{}", marker, label, instr.text, target);
+        }
+    }
+
+    fn parse_address(&self, addr: &str) -> Result<usize, String> {
+        let addr_without_0x = if addr.to_lowercase().starts_with("*0x") {
+            &addr[3..]
+        } else {
+            return Err("address illegal".to_string());
+        };
+
+        match usize::from_str_radix(addr_without_0x, 16) {
+            Ok(v) => Ok(v),
+            Err(_) => Err(format!("Can not parse address 0x{}", addr_without_0x)),
+        }
+    }
+
+    /// Returns the next line of input: a queued one if there is any, otherwise one typed at
+    /// `prompt`. Returns None at the end of input, or once the queue runs out in batch mode.
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        if let Some(line) = self.pending_input.pop_front() {
+            return Some(line);
+        }
+        if self.batch {
+            return None;
+        }
+        if output::mode() == Mode::Json {
+            return self.read_request();
+        }
+        loop {
+            // Print prompt and get next line of user input
+            match self.readline.readline(prompt) {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    console!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => return None,
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if line.trim().len() > 0 {
+                        self.readline.add_history_entry(line.as_str());
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            console!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
+                    }
+                    return Some(line);
+                }
+            }
+        }
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    ///
+    /// You don't need to read, understand, or modify this function.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // User pressed ctrl+d (or the script ran out), which is the equivalent of "quit" for
+            // our purposes
+            let line = match self.read_line("(deet) ") {
+                Some(line) => line,
+                None => return DebuggerCommand::Quit,
+            };
+            if line.trim().len() == 0 {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match DebuggerCommand::parse(&tokens) {
+                Ok(cmd) => return cmd,
+                Err(message) => match self.request.take() {
+                    Some(id) => output::emit(Event::Error { id, message }),
+                    None => console!("{}", message),
+                },
+            }
+        }
+    }
+
+    /// Reads the next request from a front end, a line holding an object such as
+    /// {"id": 1, "command": "break", "arguments": ["main"]} or {"id": 2, "command": "next"}, and
+    /// returns the command line it stands for. Returns None once there are no more requests.
+    fn read_request(&mut self) -> Option<String> {
+        loop {
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => {}
+            }
+            let request = match Json::parse(&line) {
+                Ok(request) => request,
+                Err(err) => {
+                    let message = format!("Invalid request: {}", err);
+                    output::emit(Event::Error { id: Json::Null, message });
+                    continue;
+                }
+            };
+            let id = request.get("id").cloned().unwrap_or(Json::Null);
+            let mut words = match request.get("command").and_then(Json::as_str) {
+                Some(command) => vec![command.to_string()],
+                None => {
+                    let message = "Request has no \"command\"".to_string();
+                    output::emit(Event::Error { id, message });
+                    continue;
+                }
+            };
+            match request.get("arguments").map(|args| args.as_array()) {
+                None => {}
+                Some(Some(args)) => words.extend(args.iter().map(|arg| match arg {
+                    Json::String(s) => s.clone(),
+                    other => other.to_string(),
+                })),
+                Some(None) => {
+                    let message = "\"arguments\" must be an array".to_string();
+                    output::emit(Event::Error { id, message });
+                    continue;
+                }
+            }
+            self.request = Some(id);
+            return Some(words.join(" "));
+        }
+    }
+
+    /// Tells the front end that the request it sent has been carried out.
+    fn finish_request(&mut self) {
+        if let Some(id) = self.request.take() {
+            output::emit(Event::Done { id });
+        }
+    }
+
+    /// Reads the lines following `commands`, up to one saying just "end", and attaches them to
+    /// breakpoint `id`, or to the last breakpoint set if no number was given.
+    fn read_breakpoint_commands(&mut self, id: Option<usize>) {
+        let id = match id {
+            Some(id) => id,
+            None => match self.next_breakpoint_id.checked_sub(1) {
+                Some(id) => id,
+                None => {
+                    console!("No breakpoints specified.");
+                    return;
+                }
+            },
+        };
+        if self.pending_input.is_empty() && !self.batch {
+            console!("Type commands for breakpoint(s) {}, one per line.", id);
+            console!("End with a line saying just \"end\".");
+        }
+        // Read the whole block first, so that it isn't run as top-level commands if the
+        // breakpoint doesn't exist
+        let mut commands = Vec::new();
+        while let Some(line) = self.read_line(">") {
+            let line = line.trim();
+            if line == "end" {
+                break;
+            }
+            if !line.is_empty() {
+                commands.push(line.to_string());
+            }
+        }
+        match self.find_breakpoint_mut(id) {
+            Some(bp) => bp.commands = commands,
+            None => console!("No breakpoint number {}.", id),
+        }
+    }
+}
+
+impl expression::Context for Debugger {
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        let inferior = self.inferior.as_ref().ok_or("No processes are running!")?;
+        inferior
+            .read_bytes(addr, len, &self.breakpoints)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+    }
+
+    fn read_register(&self, name: &str) -> Option<u64> {
+        self.inferior.as_ref()?.get_register(name).ok()?
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<Option<(usize, Type)>, String> {
+        match Debugger::lookup_variable(self, name) {
+            Some(var) => {
+                let addr = self.variable_address(&var).map_err(|err| err.to_string())?;
+                Ok(Some((addr, var.entity_type)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn lookup_type(&self, name: &str) -> Option<Type> {
+        self.debug_data.get_type_by_name(name).cloned()
+    }
+
+    fn type_at(&self, offset: usize) -> Option<Type> {
+        self.debug_data.get_type(offset).cloned()
+    }
+}
+
+/// Returns true if `name` could be the name of a C function.
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a "file:line" location, e.g. "count.c:5".
+fn parse_file_line(location: &str) -> Option<(&str, usize)> {
+    let colon = location.rfind(':')?;
+    Some((&location[..colon], location[colon + 1..].parse().ok()?))
+}
+
+/// Returns the address whose function and line describe `frame`. Outer frames are stopped at a
+/// return address, which may already belong to the next line or even the next function, so
+/// they use the address of the call instruction instead.
+fn frame_code_address(index: usize, frame: &StackFrame) -> usize {
+    if index == 0 {
+        frame.rip
+    } else {
+        frame.rip - 1
+    }
+}
+
+/// Returns the address of `var` in the frame whose canonical frame address is `cfa`. gcc uses the
+/// CFA as the frame base, so locals and parameters are at fixed offsets from it.
+fn frame_variable_address(var: &Variable, cfa: usize) -> usize {
+    match var.location {
+        Location::Address(addr) => addr,
+        Location::FramePointerOffset(offset) => (cfa as isize + offset) as usize,
+    }
+}
+
+/// Formats a byte as a C character constant, the way gdb shows chars, e.g. 'a', '\n' or '\211'.
+fn format_char(byte: u8) -> String {
+    let escaped = match byte {
+        b'\'' => "\\'".to_string(),
+        b'\\' => "\\\\".to_string(),
+        7 => "\\a".to_string(),
+        8 => "\\b".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\n' => "\\n".to_string(),
+        11 => "\\v".to_string(),
+        12 => "\\f".to_string(),
+        b'\r' => "\\r".to_string(),
+        b' '..=b'~' => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte),
+    };
+    format!("'{}'", escaped)
+}
+
+/// Formats eflags the way gdb does, listing the names of the status and control flags that are
+/// set, e.g. "[ IF ZF PF ]".
+fn format_eflags(eflags: u64) -> String {
+    let flags = [
+        (11, "OF"),
+        (10, "DF"),
+        (9, "IF"),
+        (8, "TF"),
+        (7, "SF"),
+        (6, "ZF"),
+        (4, "AF"),
+        (2, "PF"),
+        (0, "CF"),
+    ];
+    let set: Vec<&str> = flags
+        .iter()
+        .filter(|(bit, _)| eflags & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", set.join(" "))
 }
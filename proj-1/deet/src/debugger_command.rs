@@ -1,13 +1,307 @@
+use crate::condition::parse_integer;
+
+/// The `/Nfu` suffix of the `x` command: how many units to show, in which format, and how many
+/// bytes each unit has.
+pub struct ExamineFormat {
+    pub count: usize,
+    /// 'x' (hex), 'd' (decimal), 'c' (char), 's' (string) or 'i' (instruction)
+    pub format: char,
+    pub unit_size: usize,
+}
+
+/// Which process to keep debugging when the inferior forks.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ForkMode {
+    Parent,
+    Child,
+}
+
+impl ExamineFormat {
+    /// Parses the part after the slash, e.g. "4xw" or "s". Missing parts default to one
+    /// hexadecimal 4-byte word, like gdb.
+    fn parse(spec: &str) -> Option<ExamineFormat> {
+        let digits: String = spec.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let count = if digits.is_empty() { 1 } else { digits.parse().ok()? };
+        let mut examine = ExamineFormat {
+            count,
+            format: 'x',
+            unit_size: 4,
+        };
+        for c in spec[digits.len()..].chars() {
+            match c {
+                'x' | 'd' | 'c' | 's' | 'i' => examine.format = c,
+                'b' => examine.unit_size = 1,
+                'h' => examine.unit_size = 2,
+                'w' => examine.unit_size = 4,
+                'g' => examine.unit_size = 8,
+                _ => return None,
+            }
+        }
+        if examine.format == 'c' {
+            examine.unit_size = 1;
+        }
+        Some(examine)
+    }
+}
+
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
     Continue,
-    Break(String),
+    Attach(i32),
+    Detach,
+    StepInstruction,
+    Step,
+    Next,
+    Finish,
+    /// Start logging each instruction the program runs, so that it can be run backwards.
+    Record,
+    /// Stop recording and throw away the log.
+    RecordStop,
+    InfoRecord,
+    ReverseStepInstruction,
+    ReverseStep,
+    ReverseContinue,
+    /// Write a core file of the stopped program, by default to "core.<pid>".
+    GenerateCoreFile(Option<String>),
+    Break(String, Option<String>),
+    InfoBreakpoints,
+    Watch(String),
+    Ignore(usize, usize),
+    /// Attach the lines that follow, up to "end", to a breakpoint (the last one set if None).
+    Commands(Option<usize>),
+    Source(String),
+    Delete(usize),
+    Disable(usize),
+    Enable(usize),
     Backtrace,
+    Print(String),
+    InfoLocals,
+    InfoRegisters(Vec<String>),
+    SetRegister(String, u64),
+    SetVariable(String, String),
+    Handle(String, Vec<String>),
+    InfoSignals(Option<String>),
+    SetFollowForkMode(ForkMode),
+    SetDetachOnFork(bool),
+    InfoInferiors,
+    InfoThreads,
+    InfoSharedLibrary,
+    Thread(Option<usize>),
+    Inferior(usize),
+    Examine(ExamineFormat, String),
+    Disassemble(Option<String>),
+    Up(usize),
+    Down(usize),
+    Frame(Option<usize>),
+    InfoFrame,
+    List(Option<String>),
+    Directory(Option<String>),
+    Help(Option<String>),
+}
+
+/// An entry in the command table, which drives `help`, argument checking and completion.
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Fewest and most arguments the command takes, or None if there is no upper limit.
+    pub min_args: usize,
+    pub max_args: Option<usize>,
+    pub usage: &'static str,
+    pub summary: &'static str,
+}
+
+const fn command(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    min_args: usize,
+    max_args: Option<usize>,
+    usage: &'static str,
+    summary: &'static str,
+) -> CommandInfo {
+    CommandInfo { name, aliases, min_args, max_args, usage, summary }
+}
+
+pub const COMMANDS: &[CommandInfo] = &[
+    command("attach", &[], 1, Some(1), "attach <pid>", "Attach to a running process."),
+    command("backtrace", &["bt"], 0, Some(0), "backtrace", "Print the stack of the selected thread."),
+    command(
+        "break",
+        &["b"],
+        1,
+        None,
+        "break <function | line | file:line | *address> [if <condition>]",
+        "Set a breakpoint, optionally stopping only when a condition holds.",
+    ),
+    command(
+        "commands",
+        &[],
+        0,
+        Some(1),
+        "commands [breakpoint]",
+        "Give commands to run when a breakpoint is hit, one per line, ending with \"end\".",
+    ),
+    command("continue", &["c"], 0, Some(0), "continue", "Resume the program."),
+    command("delete", &["d"], 1, Some(1), "delete <breakpoint>", "Delete a breakpoint or watchpoint."),
+    command("detach", &[], 0, Some(0), "detach", "Let the program run on without the debugger."),
+    command(
+        "directory",
+        &["dir"],
+        0,
+        Some(1),
+        "directory [dir]",
+        "Add a directory to search for source files, or show the ones searched.",
+    ),
+    command("disable", &[], 1, Some(1), "disable <breakpoint>", "Disable a breakpoint."),
+    command(
+        "disassemble",
+        &["disas"],
+        0,
+        Some(1),
+        "disassemble [function | *address]",
+        "Disassemble a function, by default the one in the selected frame.",
+    ),
+    command("down", &[], 0, Some(1), "down [count]", "Select the frame called by the selected one."),
+    command("enable", &[], 1, Some(1), "enable <breakpoint>", "Enable a breakpoint."),
+    command("finish", &["fin"], 0, Some(0), "finish", "Run until the selected function returns."),
+    command("frame", &["f"], 0, Some(1), "frame [level]", "Select a frame, or describe the selected one."),
+    command(
+        "generate-core-file",
+        &["gcore"],
+        0,
+        Some(1),
+        "generate-core-file [file]",
+        "Save the state of the program to a core file.",
+    ),
+    command(
+        "handle",
+        &[],
+        1,
+        None,
+        "handle <signal> [stop | nostop | print | noprint | pass | nopass]...",
+        "Choose what happens when the program receives a signal.",
+    ),
+    command("help", &["h"], 0, Some(1), "help [command]", "Describe a command, or list them all."),
+    command("ignore", &[], 2, Some(2), "ignore <breakpoint> <count>", "Skip the next hits of a breakpoint."),
+    command("inferior", &[], 1, Some(1), "inferior <id>", "Switch to another traced process."),
+    command(
+        "info",
+        &[],
+        1,
+        None,
+        "info breakpoints | frame | inferiors | locals | record | registers [reg]... | sharedlibrary | signals [signal] | threads",
+        "Show information about the program being debugged.",
+    ),
+    command(
+        "list",
+        &["l"],
+        0,
+        Some(1),
+        "list [line | function | file:line]",
+        "Print source lines, continuing from the last listing.",
+    ),
+    command("next", &["n"], 0, Some(0), "next", "Run to the next line, stepping over calls."),
+    command("print", &["p"], 1, None, "print <expression>", "Print the value of an expression."),
+    command("quit", &["q"], 0, Some(0), "quit", "Kill or detach from the program and exit."),
+    command(
+        "record",
+        &["rec"],
+        0,
+        Some(1),
+        "record [full | stop]",
+        "Start recording the program so that it can run backwards, or stop and delete the log.",
+    ),
+    command(
+        "reverse-continue",
+        &["rc"],
+        0,
+        Some(0),
+        "reverse-continue",
+        "Run the program backwards to the previous breakpoint.",
+    ),
+    command(
+        "reverse-step",
+        &["rs"],
+        0,
+        Some(0),
+        "reverse-step",
+        "Run backwards to the start of the previous line, stepping into calls.",
+    ),
+    command(
+        "reverse-stepi",
+        &["rsi"],
+        0,
+        Some(0),
+        "reverse-stepi",
+        "Run one machine instruction backwards.",
+    ),
+    command("run", &["r"], 0, None, "run [args]...", "Start the program, killing it first if it is running."),
+    command(
+        "set",
+        &[],
+        1,
+        None,
+        "set var <variable> = <value> | set $<register> = <value> | set follow-fork-mode parent | child | set detach-on-fork on | off",
+        "Change a variable, a register or a setting.",
+    ),
+    command("source", &[], 1, Some(1), "source <file>", "Run the commands in a file."),
+    command("step", &["s"], 0, Some(0), "step", "Run to the next line, stepping into calls."),
+    command("stepi", &["si"], 0, Some(0), "stepi", "Run one machine instruction."),
+    command("thread", &[], 0, Some(1), "thread [id]", "Switch to another thread, or show the current one."),
+    command("up", &[], 0, Some(1), "up [count]", "Select the frame that called the selected one."),
+    command("watch", &[], 1, None, "watch <variable | *address>", "Stop when a value changes."),
+    command("x", &[], 1, None, "x[/Nfu] <address>", "Examine memory, e.g. x/4xw &var or x/3i $rip."),
+];
+
+/// Looks up a command by its name or one of its aliases. The `x` command may carry a format,
+/// as in "x/4xw".
+pub fn lookup_command(name: &str) -> Option<&'static CommandInfo> {
+    let name = if name.starts_with("x/") { "x" } else { name };
+    COMMANDS
+        .iter()
+        .find(|cmd| cmd.name == name || cmd.aliases.contains(&name))
+}
+
+/// Prints the list of commands, or the usage of one command.
+pub fn print_help(topic: Option<&str>) {
+    match topic {
+        None => {
+            console!("List of commands:");
+            console!();
+            for cmd in COMMANDS {
+                console!("{} -- {}", cmd.name, cmd.summary);
+            }
+            console!();
+            console!("Type \"help\" followed by command name for full documentation.");
+        }
+        Some(topic) => match lookup_command(topic) {
+            Some(cmd) => {
+                console!("{}", cmd.summary);
+                console!("Usage: {}", cmd.usage);
+                if !cmd.aliases.is_empty() {
+                    console!("Aliases: {}", cmd.aliases.join(", "));
+                }
+            }
+            None => console!("Undefined command: \"{}\".  Try \"help\".", topic),
+        },
+    }
 }
 
 impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
+    /// Parses a line of input, or returns the message to show if it is not a valid command.
+    pub fn parse(tokens: &[&str]) -> Result<DebuggerCommand, String> {
+        let cmd = match lookup_command(tokens[0]) {
+            Some(cmd) => cmd,
+            None => return Err(format!("Undefined command: \"{}\".  Try \"help\".", tokens[0])),
+        };
+        let args = tokens.len() - 1;
+        if args < cmd.min_args || cmd.max_args.map_or(false, |max| args > max) {
+            return Err(format!("Usage: {}", cmd.usage));
+        }
+        DebuggerCommand::from_tokens(tokens).ok_or_else(|| format!("Usage: {}", cmd.usage))
+    }
+
+    fn from_tokens(tokens: &[&str]) -> Option<DebuggerCommand> {
         match tokens[0] {
             "q" | "quit" => Some(DebuggerCommand::Quit),
             "r" | "run" => {
@@ -19,14 +313,181 @@ impl DebuggerCommand {
             "c" | "continue" => {
                 Some(DebuggerCommand::Continue)
             },
+            "attach" => {
+                Some(DebuggerCommand::Attach(tokens.get(1)?.parse().ok()?))
+            },
+            "detach" => {
+                Some(DebuggerCommand::Detach)
+            },
+            "si" | "stepi" => {
+                Some(DebuggerCommand::StepInstruction)
+            },
+            "s" | "step" => {
+                Some(DebuggerCommand::Step)
+            },
+            "n" | "next" => {
+                Some(DebuggerCommand::Next)
+            },
+            "fin" | "finish" => {
+                Some(DebuggerCommand::Finish)
+            },
+            "rec" | "record" => match tokens.get(1) {
+                None | Some(&"full") => Some(DebuggerCommand::Record),
+                Some(&"stop") => Some(DebuggerCommand::RecordStop),
+                _ => None,
+            },
+            "rsi" | "reverse-stepi" => {
+                Some(DebuggerCommand::ReverseStepInstruction)
+            },
+            "rs" | "reverse-step" => {
+                Some(DebuggerCommand::ReverseStep)
+            },
+            "rc" | "reverse-continue" => {
+                Some(DebuggerCommand::ReverseContinue)
+            },
             "b" | "break" => {
-                Some(DebuggerCommand::Break(tokens[1].to_string()))
+                let condition = match tokens.get(2) {
+                    Some(&"if") if tokens.len() > 3 => Some(tokens[3..].join(" ")),
+                    Some(_) => return None,
+                    None => None,
+                };
+                Some(DebuggerCommand::Break(tokens.get(1)?.to_string(), condition))
+            },
+            "d" | "delete" => {
+                Some(DebuggerCommand::Delete(tokens.get(1)?.parse().ok()?))
+            },
+            "disable" => {
+                Some(DebuggerCommand::Disable(tokens.get(1)?.parse().ok()?))
+            },
+            "enable" => {
+                Some(DebuggerCommand::Enable(tokens.get(1)?.parse().ok()?))
+            },
+            "watch" if tokens.len() > 1 => {
+                Some(DebuggerCommand::Watch(tokens[1..].join(" ")))
+            },
+            "ignore" => {
+                let id = tokens.get(1)?.parse().ok()?;
+                let count = tokens.get(2)?.parse().ok()?;
+                Some(DebuggerCommand::Ignore(id, count))
+            },
+            "commands" => match tokens.get(1) {
+                Some(id) => Some(DebuggerCommand::Commands(Some(id.parse().ok()?))),
+                None => Some(DebuggerCommand::Commands(None)),
+            },
+            "source" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Source(tokens[1].to_string()))
             },
             "bt" | "backtrace" => {
                 Some(DebuggerCommand::Backtrace)
             },
+            "p" | "print" if tokens.len() > 1 => {
+                Some(DebuggerCommand::Print(tokens[1..].join(" ")))
+            },
+            "info" => match tokens.get(1) {
+                Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                Some(&"f") | Some(&"frame") => Some(DebuggerCommand::InfoFrame),
+                Some(&"r") | Some(&"registers") => Some(DebuggerCommand::InfoRegisters(
+                    tokens[2..].iter().map(|s| s.trim_start_matches('$').to_string()).collect(),
+                )),
+                Some(&"b") | Some(&"break") | Some(&"breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                Some(&"inferiors") => Some(DebuggerCommand::InfoInferiors),
+                Some(&"threads") => Some(DebuggerCommand::InfoThreads),
+                Some(&"record") | Some(&"rec") => Some(DebuggerCommand::InfoRecord),
+                Some(&"shared") | Some(&"sharedlibrary") => Some(DebuggerCommand::InfoSharedLibrary),
+                Some(&"signals") | Some(&"handle") => {
+                    Some(DebuggerCommand::InfoSignals(tokens.get(2).map(|s| s.to_string())))
+                }
+                _ => None,
+            },
+            "handle" if tokens.len() > 1 => Some(DebuggerCommand::Handle(
+                tokens[1].to_string(),
+                tokens[2..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "thread" => match tokens.get(1) {
+                Some(id) => Some(DebuggerCommand::Thread(Some(id.parse().ok()?))),
+                None => Some(DebuggerCommand::Thread(None)),
+            },
+            "inferior" => Some(DebuggerCommand::Inferior(tokens.get(1)?.parse().ok()?)),
+            "set" if tokens.get(1) == Some(&"follow-fork-mode") => match tokens.get(2) {
+                Some(&"parent") => Some(DebuggerCommand::SetFollowForkMode(ForkMode::Parent)),
+                Some(&"child") => Some(DebuggerCommand::SetFollowForkMode(ForkMode::Child)),
+                _ => None,
+            },
+            "set" if tokens.get(1) == Some(&"detach-on-fork") => match tokens.get(2) {
+                Some(&"on") => Some(DebuggerCommand::SetDetachOnFork(true)),
+                Some(&"off") => Some(DebuggerCommand::SetDetachOnFork(false)),
+                _ => None,
+            },
+            "set" if tokens.len() > 1 && tokens[1].starts_with('$') => {
+                // Accept both "set $rax = 1" and "set $rax=1"
+                let assignment = tokens[1..].join("");
+                let mut parts = assignment[1..].splitn(2, '=');
+                let name = parts.next()?.to_string();
+                let value = parse_integer(parts.next()?)? as u64;
+                Some(DebuggerCommand::SetRegister(name, value))
+            },
+            "set" if tokens.len() > 1 => {
+                // Accept both "set var x = 5" and "set *(int *)0x601040 = 7"
+                let assignment = match tokens[1] {
+                    "var" | "variable" => tokens[2..].join(" "),
+                    _ => tokens[1..].join(" "),
+                };
+                let (lhs, rhs) = split_assignment(&assignment)?;
+                Some(DebuggerCommand::SetVariable(lhs, rhs))
+            },
+            cmd if cmd == "x" || cmd.starts_with("x/") => {
+                let examine = match cmd.find('/') {
+                    Some(slash) => ExamineFormat::parse(&cmd[slash + 1..])?,
+                    None => ExamineFormat::parse("")?,
+                };
+                Some(DebuggerCommand::Examine(examine, tokens.get(1..)?.join(" ")))
+            },
+            "up" => match tokens.get(1) {
+                Some(count) => Some(DebuggerCommand::Up(count.parse().ok()?)),
+                None => Some(DebuggerCommand::Up(1)),
+            },
+            "down" => match tokens.get(1) {
+                Some(count) => Some(DebuggerCommand::Down(count.parse().ok()?)),
+                None => Some(DebuggerCommand::Down(1)),
+            },
+            "f" | "frame" => match tokens.get(1) {
+                Some(level) => Some(DebuggerCommand::Frame(Some(level.parse().ok()?))),
+                None => Some(DebuggerCommand::Frame(None)),
+            },
+            "l" | "list" => {
+                Some(DebuggerCommand::List(tokens.get(1).map(|s| s.to_string())))
+            },
+            "h" | "help" => {
+                Some(DebuggerCommand::Help(tokens.get(1).map(|s| s.to_string())))
+            },
+            "gcore" | "generate-core-file" => {
+                Some(DebuggerCommand::GenerateCoreFile(tokens.get(1).map(|s| s.to_string())))
+            },
+            "dir" | "directory" => {
+                Some(DebuggerCommand::Directory(tokens.get(1).map(|s| s.to_string())))
+            },
+            "disas" | "disassemble" => {
+                Some(DebuggerCommand::Disassemble(tokens.get(1).map(|s| s.to_string())))
+            },
             // Default case:
             _ => None,
         }
     }
 }
+
+/// Splits "lhs = rhs" at the assignment operator, skipping the `=` in `==`, `!=`, `<=` and `>=`.
+fn split_assignment(assignment: &str) -> Option<(String, String)> {
+    let bytes = assignment.as_bytes();
+    let pos = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && (i == 0 || !b"=!<>".contains(&bytes[i - 1]))
+    })?;
+    let (lhs, rhs) = (assignment[..pos].trim(), assignment[pos + 1..].trim());
+    if lhs.is_empty() || rhs.is_empty() {
+        return None;
+    }
+    Some((lhs.to_string(), rhs.to_string()))
+}
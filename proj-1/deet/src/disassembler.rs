@@ -0,0 +1,48 @@
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction};
+
+/// The longest possible x86-64 instruction, in bytes.
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// A machine instruction decoded from inferior memory.
+pub struct DecodedInstruction {
+    pub addr: usize,
+    /// AT&T syntax, like gdb prints by default, e.g. "mov    %rsp,%rbp".
+    pub text: String,
+    /// Destination of a direct call or jump, so that callers can name the target function.
+    pub branch_target: Option<usize>,
+}
+
+/// Decodes up to `max` x86-64 instructions from `bytes`, which were read from inferior memory
+/// starting at `addr`. Breakpoint 0xcc bytes should already have been replaced with the original
+/// instruction bytes. Stops early at a truncated or invalid instruction.
+pub fn decode(bytes: &[u8], addr: usize, max: usize) -> Vec<DecodedInstruction> {
+    let mut decoder = Decoder::new(64, bytes, DecoderOptions::NONE);
+    decoder.set_ip(addr as u64);
+
+    let mut formatter = GasFormatter::new();
+    formatter.options_mut().set_first_operand_char_index(7);
+    formatter.options_mut().set_uppercase_hex(false);
+    formatter.options_mut().set_branch_leading_zeros(false);
+
+    let mut instructions = Vec::new();
+    let mut instruction = Instruction::default();
+    while decoder.can_decode() && instructions.len() < max {
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() {
+            break;
+        }
+        let mut text = String::new();
+        formatter.format(&instruction, &mut text);
+        let target = instruction.near_branch_target();
+        instructions.push(DecodedInstruction {
+            addr: instruction.ip() as usize,
+            text,
+            branch_target: if target != 0 {
+                Some(target as usize)
+            } else {
+                None
+            },
+        });
+    }
+    instructions
+}
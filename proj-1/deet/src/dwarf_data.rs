@@ -1,6 +1,8 @@
 use crate::gimli_wrapper;
+use crate::unwind::{CallFrameInfo, Registers, Unwound};
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSegment};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{fmt, fs};
 
@@ -10,14 +12,139 @@ pub enum Error {
     DwarfFormatError(gimli_wrapper::Error),
 }
 
-pub struct DwarfData {
+/// Debugging information for one object file mapped into the inferior: the program itself or a
+/// shared library.
+struct Module {
+    path: String,
+    /// How far the object was loaded from the addresses it was linked at. The addresses in
+    /// `files` have already been adjusted by it, but `addr2line`, `frame_info` and `symbols` work
+    /// with link-time addresses.
+    bias: usize,
+    /// Link-time address range of the object's loadable segments.
+    start: usize,
+    end: usize,
+    entry: usize,
     files: Vec<File>,
+    /// Every type in the object, by .debug_info offset, so that pointers can be followed.
+    types: HashMap<usize, Type>,
+    /// ELF symbols by name, for code without debugging information, like the dynamic linker.
+    symbols: HashMap<String, usize>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    frame_info: CallFrameInfo,
+}
+
+impl Module {
+    fn load(path: &str) -> Result<Module, Error> {
+        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
+        let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
+        let object = object::File::parse(&*mmap)
+            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+        let endian = if object.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        let (files, types) = gimli_wrapper::load_file(&object, endian)?;
+
+        let segments = object.segments().filter(|segment| segment.size() > 0);
+        let (start, end) = segments.fold((usize::MAX, 0), |(start, end), segment| {
+            let addr = segment.address() as usize;
+            (start.min(addr), end.max(addr + segment.size() as usize))
+        });
+        let mut symbols = HashMap::new();
+        for (_, symbol) in object.symbols().chain(object.dynamic_symbols()) {
+            if let Some(name) = symbol.name() {
+                if !name.is_empty() && symbol.address() != 0 {
+                    symbols.entry(name.to_string()).or_insert(symbol.address() as usize);
+                }
+            }
+        }
+
+        Ok(Module {
+            path: path.to_string(),
+            bias: 0,
+            start,
+            end,
+            entry: object.entry() as usize,
+            files,
+            types,
+            symbols,
+            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            frame_info: CallFrameInfo::load(&object, endian),
+        })
+    }
+
+    /// Returns true if `addr` lies within the object as it is currently loaded.
+    fn contains(&self, addr: usize) -> bool {
+        let addr = addr.wrapping_sub(self.bias);
+        addr >= self.start && addr < self.end
+    }
+
+    /// Moves the object to a new load bias, adjusting the addresses in `files`.
+    fn set_bias(&mut self, bias: usize) {
+        let delta = bias.wrapping_sub(self.bias);
+        let relocate_variables = |variables: &mut Vec<Variable>| {
+            for var in variables {
+                if let Location::Address(addr) = &mut var.location {
+                    *addr = addr.wrapping_add(delta);
+                }
+            }
+        };
+        for file in &mut self.files {
+            relocate_variables(&mut file.global_variables);
+            // Declarations of functions defined elsewhere have no address to move
+            for func in file.functions.iter_mut().filter(|func| func.text_length > 0) {
+                func.address = func.address.wrapping_add(delta);
+                relocate_variables(&mut func.variables);
+            }
+            for line in &mut file.lines {
+                line.address = line.address.wrapping_add(delta);
+            }
+        }
+        self.bias = bias;
+    }
+
+    /// Adds `base` to the .debug_info offsets the object's types are known by, so that they don't
+    /// clash with those of other objects.
+    fn rebase_types(&mut self, base: usize) {
+        self.types = std::mem::replace(&mut self.types, HashMap::new())
+            .into_iter()
+            .map(|(offset, mut dtype)| {
+                dtype.rebase(base);
+                (offset + base, dtype)
+            })
+            .collect();
+        for file in &mut self.files {
+            let functions = file.functions.iter_mut();
+            let variables = functions.flat_map(|func| func.variables.iter_mut());
+            for var in file.global_variables.iter_mut().chain(variables) {
+                var.entity_type.rebase(base);
+            }
+        }
+    }
+}
+
+/// A shared library loaded in the inferior, as listed by `info sharedlibrary`.
+pub struct Library<'a> {
+    pub path: &'a str,
+    /// How far the library was loaded from the addresses it was linked at.
+    pub bias: usize,
+    /// Runtime address range of the library's loadable segments.
+    pub start: usize,
+    pub end: usize,
+    pub has_debug_info: bool,
+}
+
+pub struct DwarfData {
+    /// The program comes first, followed by the shared libraries in the order they were loaded.
+    modules: Vec<Module>,
+    /// Offset to add to the type offsets of the next shared library we load.
+    next_type_base: usize,
 }
 
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DwarfData {{files: {:?}}}", self.files)
+        write!(f, "DwarfData {{files: {:?}}}", self.modules[0].files)
     }
 }
 
@@ -27,26 +154,108 @@ impl From<gimli_wrapper::Error> for Error {
     }
 }
 
+/// Type offsets of each shared library are moved up by this much, which is more than any
+/// .debug_info section is long.
+const TYPE_BASE_STRIDE: usize = 1 << 40;
+
 impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
-        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
-        let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
-        let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
-        let endian = if object.is_little_endian() {
-            gimli::RunTimeEndian::Little
-        } else {
-            gimli::RunTimeEndian::Big
-        };
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            modules: vec![Module::load(path)?],
+            next_type_base: TYPE_BASE_STRIDE,
         })
     }
 
+    /// Returns the link-time address of the program's entry point.
+    pub fn entry_point(&self) -> usize {
+        self.modules[0].entry
+    }
+
+    /// Moves the program's addresses by `bias`, the distance between where a position-independent
+    /// executable was loaded and the addresses it was linked at. Zero for other executables.
+    pub fn set_load_bias(&mut self, bias: usize) {
+        self.modules[0].set_bias(bias);
+    }
+
+    /// Loads the debugging information of the shared library at `path`, which was loaded `bias`
+    /// bytes from its link-time addresses.
+    pub fn add_library(&mut self, path: &str, bias: usize) -> Result<(), Error> {
+        let mut module = Module::load(path)?;
+        module.set_bias(bias);
+        module.rebase_types(self.next_type_base);
+        self.next_type_base += TYPE_BASE_STRIDE;
+        self.modules.push(module);
+        Ok(())
+    }
+
+    /// Forgets the shared libraries for which `keep` returns false, given their path and bias.
+    pub fn retain_libraries<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str, usize) -> bool,
+    {
+        let program = self.modules.remove(0);
+        self.modules.retain(|module| keep(&module.path, module.bias));
+        self.modules.insert(0, program);
+    }
+
+    /// Returns the shared libraries we know about, in load order.
+    pub fn libraries(&self) -> Vec<Library<'_>> {
+        self.modules[1..]
+            .iter()
+            .map(|module| Library {
+                path: &module.path,
+                bias: module.bias,
+                start: module.start.wrapping_add(module.bias),
+                end: module.end.wrapping_add(module.bias),
+                has_debug_info: !module.files.is_empty(),
+            })
+            .collect()
+    }
+
+    /// Returns the runtime address of the ELF symbol `name` in the program or a shared library.
+    pub fn get_symbol(&self, name: &str) -> Option<usize> {
+        self.modules
+            .iter()
+            .find_map(|module| Some(module.symbols.get(name)?.wrapping_add(module.bias)))
+    }
+
+    /// Returns the names of the functions defined in the program and its libraries, sorted.
+    pub fn function_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .all_files()
+            .flat_map(|file| file.functions.iter())
+            .filter(|func| func.text_length > 0)
+            .map(|func| func.name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Returns the path of each source file, with the numbers of the lines that have code.
+    pub fn source_lines(&self) -> Vec<(String, Vec<usize>)> {
+        self.all_files()
+            .map(|file| {
+                let mut numbers: Vec<usize> = file.lines.iter().map(|line| line.number).collect();
+                numbers.sort();
+                numbers.dedup();
+                (file.name.clone(), numbers)
+            })
+            .collect()
+    }
+
+    /// Returns the object that `addr` belongs to.
+    fn module_for(&self, addr: usize) -> Option<&Module> {
+        self.modules.iter().find(|module| module.contains(addr))
+    }
+
+    fn all_files(&self) -> impl Iterator<Item = &File> {
+        self.modules.iter().flat_map(|module| module.files.iter())
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
+        self.all_files().find(|f| {
             f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
         })
     }
@@ -55,7 +264,7 @@ impl DwarfData {
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self.modules[0].files.get(0)?,
         };
         Some(
             target_file
@@ -73,25 +282,51 @@ impl DwarfData {
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(|func| func.name == func_name && func.text_length > 0)?
                     .address,
             ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
-                    }
-                }
-                None
-            }
+            // Declarations of functions defined in a library have no address
+            None => Some(self.get_function(func_name)?.address),
         }
     }
 
+    /// Returns the definition of the function named `func_name`, skipping declarations of
+    /// functions that live in other compilation units or libraries.
+    #[allow(dead_code)]
+    pub fn get_function(&self, func_name: &str) -> Option<&Function> {
+        self.all_files()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.name == func_name && func.text_length > 0)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_global_variable(&self, var_name: &str) -> Option<&Variable> {
+        self.all_files()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == var_name)
+    }
+
+    /// Returns the type at the given .debug_info offset, e.g. the pointee of a `TypeKind::Pointer`.
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        self.modules.iter().find_map(|module| module.types.get(&offset))
+    }
+
+    /// Returns the type called `name`, e.g. "struct node" or "long unsigned int". Complete
+    /// definitions are preferred over declarations.
+    pub fn get_type_by_name(&self, name: &str) -> Option<&Type> {
+        self.modules
+            .iter()
+            .flat_map(|module| module.types.values())
+            .filter(|dtype| dtype.name == name)
+            .max_by_key(|dtype| dtype.size)
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
-        let location = self
+        let module = self.module_for(curr_addr)?;
+        let location = module
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(curr_addr.wrapping_sub(module.bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
@@ -100,11 +335,51 @@ impl DwarfData {
         })
     }
 
+    /// Returns true if `addr` is the first instruction of a row in the line number table.
+    #[allow(dead_code)]
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        self.all_files()
+            .any(|file| file.lines.iter().any(|line| line.address == addr))
+    }
+
+    /// Returns where the body of the function starting at `func_addr` begins, past the prologue
+    /// that sets up its frame: like gdb, the address of the function's second row in the line
+    /// number table. Arguments and locals can only be read from there on. Returns `func_addr` if
+    /// no function starts there or there is no second row.
+    pub fn skip_prologue(&self, func_addr: usize) -> usize {
+        let func = self
+            .all_files()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.address == func_addr && func.text_length > 0);
+        let end = match func {
+            Some(func) => func_addr + func.text_length,
+            None => return func_addr,
+        };
+        self.all_files()
+            .flat_map(|file| file.lines.iter())
+            .map(|line| line.address)
+            .filter(|addr| *addr > func_addr && *addr < end)
+            .min()
+            .unwrap_or(func_addr)
+    }
+
+    /// Unwinds one frame using the call frame information. See `CallFrameInfo::unwind`.
+    pub fn unwind_frame(
+        &self,
+        regs: &Registers,
+        lookup_addr: usize,
+        read_word: &mut dyn FnMut(usize) -> Option<u64>,
+    ) -> Option<Unwound> {
+        let module = self.module_for(lookup_addr)?;
+        module.frame_info.unwind(regs, lookup_addr.wrapping_sub(module.bias), read_word)
+    }
+
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
+        let module = self.module_for(curr_addr)?;
+        let frame = module
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(curr_addr.wrapping_sub(module.bias).try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
@@ -113,45 +388,89 @@ impl DwarfData {
 
     #[allow(dead_code)]
     pub fn print(&self) {
-        for file in &self.files {
-            println!("------");
-            println!("{}", file.name);
-            println!("------");
+        for file in &self.modules[0].files {
+            console!("------");
+            console!("{}", file.name);
+            console!("------");
 
-            println!("Global variables:");
+            console!("Global variables:");
             for var in &file.global_variables {
-                println!(
+                console!(
                     "  * {} ({}, located at {}, declared at line {})",
                     var.name, var.entity_type.name, var.location, var.line_number
                 );
             }
 
-            println!("Functions:");
+            console!("Functions:");
             for func in &file.functions {
-                println!(
+                console!(
                     "  * {} (declared on line {}, located at {:#x}, {} bytes long)",
                     func.name, func.line_number, func.address, func.text_length
                 );
                 for var in &func.variables {
-                    println!(
+                    console!(
                         "    * Variable: {} ({}, located at {}, declared at line {})",
                         var.name, var.entity_type.name, var.location, var.line_number
                     );
                 }
             }
 
-            println!("Line numbers:");
+            console!("Line numbers:");
             for line in &file.lines {
-                println!("  * {} (at {:#x})", line.number, line.address);
+                console!("  * {} (at {:#x})", line.number, line.address);
             }
         }
     }
 }
 
+/// Arrays longer than this are cut short when printed, like gdb's `print elements` limit.
+const MAX_PRINTED_ELEMENTS: usize = 200;
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    /// int, char, double and the like, which are told apart by name.
+    Base,
+    Pointer(Pointee),
+    /// A struct or union.
+    Struct(Vec<Member>),
+    /// An array. `count` is None for arrays of unknown length, such as flexible array members.
+    Array { element: Box<Type>, count: Option<usize> },
+    /// A typedef or a const/volatile qualified type, which behaves like the type it wraps.
+    Alias(Box<Type>),
+    /// An enum and its named values.
+    Enum(Vec<(String, i64)>),
+}
+
+impl Default for TypeKind {
+    fn default() -> Self {
+        TypeKind::Base
+    }
+}
+
+/// What a pointer points to.
+#[derive(Debug, Clone)]
+pub enum Pointee {
+    Void,
+    /// The .debug_info offset of the pointee (see `DwarfData::get_type`). Pointer types from the
+    /// DWARF data refer to their pointees this way, because structs can point to themselves.
+    Offset(usize),
+    /// A pointee held directly, for pointer types the debugger makes up, e.g. for `&x`.
+    Type(Box<Type>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    /// Offset of the member from the start of the struct, in bytes.
+    pub offset: usize,
+    pub entity_type: Type,
 }
 
 impl Type {
@@ -159,6 +478,149 @@ impl Type {
         Type {
             name: name,
             size: size,
+            kind: TypeKind::Base,
+        }
+    }
+
+    /// Adds `base` to the .debug_info offsets of the pointees within this type.
+    fn rebase(&mut self, base: usize) {
+        match &mut self.kind {
+            TypeKind::Pointer(Pointee::Offset(offset)) => *offset += base,
+            TypeKind::Pointer(Pointee::Type(dtype)) => dtype.rebase(base),
+            TypeKind::Struct(members) => {
+                for member in members {
+                    member.entity_type.rebase(base);
+                }
+            }
+            TypeKind::Array { element, .. } => element.rebase(base),
+            TypeKind::Alias(target) => target.rebase(base),
+            TypeKind::Pointer(Pointee::Void) | TypeKind::Base | TypeKind::Enum(_) => {}
+        }
+    }
+
+    /// Returns the type underneath any typedefs and const/volatile qualifiers.
+    pub fn strip_aliases(&self) -> &Type {
+        let mut dtype = self;
+        while let TypeKind::Alias(target) = &dtype.kind {
+            dtype = target;
+        }
+        dtype
+    }
+
+    /// Returns the member called `name` if this is a struct or union.
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        match &self.strip_aliases().kind {
+            TypeKind::Struct(members) => members.iter().find(|member| member.name == name),
+            _ => None,
+        }
+    }
+
+    /// Returns true for pointers and unsigned integer types.
+    pub fn is_unsigned(&self) -> bool {
+        let dtype = self.strip_aliases();
+        match dtype.kind {
+            TypeKind::Pointer(_) => true,
+            TypeKind::Base => dtype.name.contains("unsigned") || dtype.name == "_Bool",
+            _ => false,
+        }
+    }
+
+    /// Interprets the raw little-endian bytes of a value of this type as an integer, sign-extending
+    /// signed types.
+    pub fn int_value(&self, bytes: &[u8]) -> i64 {
+        let dtype = self.strip_aliases();
+        let mut buf = [0u8; 8];
+        let len = bytes.len().min(8);
+        buf[..len].copy_from_slice(&bytes[..len]);
+        let raw = u64::from_le_bytes(buf);
+        if len == 0 || len == 8 || dtype.is_unsigned() {
+            return raw as i64;
+        }
+        let shift = 64 - 8 * len as u32;
+        ((raw << shift) as i64) >> shift
+    }
+
+    /// Formats a value of this type from its raw little-endian bytes, the way gdb would print it.
+    /// Structs and arrays are printed in braces, e.g. `{x = 1, y = {2, 3}}`.
+    pub fn format_value(&self, bytes: &[u8]) -> String {
+        let dtype = self.strip_aliases();
+        match &dtype.kind {
+            TypeKind::Base => dtype.format_base(bytes),
+            TypeKind::Alias(_) => unreachable!(),
+            TypeKind::Pointer(_) => format!("{:#x}", self.int_value(bytes) as u64),
+            TypeKind::Enum(values) => {
+                let value = self.int_value(bytes);
+                match values.iter().find(|(_, v)| *v == value) {
+                    Some((name, _)) => name.clone(),
+                    None => format!("{}", value),
+                }
+            }
+            TypeKind::Struct(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let end = (member.offset + member.entity_type.size).min(bytes.len());
+                        let start = member.offset.min(end);
+                        format!("{} = {}", member.name, member.entity_type.format_value(&bytes[start..end]))
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array { element, count } => {
+                let size = element.size.max(1);
+                let count = count.unwrap_or(0).min(bytes.len() / size);
+                if element.is_char() {
+                    let text: Vec<u8> =
+                        bytes[..count].iter().copied().take_while(|byte| *byte != 0).collect();
+                    return format!("\"{}\"", String::from_utf8_lossy(&text).escape_debug());
+                }
+                let mut items: Vec<String> = bytes
+                    .chunks(size)
+                    .take(count.min(MAX_PRINTED_ELEMENTS))
+                    .map(|chunk| element.format_value(chunk))
+                    .collect();
+                if count > MAX_PRINTED_ELEMENTS {
+                    items.push("...".to_string());
+                }
+                format!("{{{}}}", items.join(", "))
+            }
+        }
+    }
+
+    /// Returns true for the one-byte character types.
+    pub fn is_char(&self) -> bool {
+        let dtype = self.strip_aliases();
+        match dtype.kind {
+            TypeKind::Base => dtype.name.contains("char") && dtype.size == 1,
+            _ => false,
+        }
+    }
+
+    fn format_base(&self, bytes: &[u8]) -> String {
+        let mut buf = [0u8; 8];
+        let len = bytes.len().min(8);
+        buf[..len].copy_from_slice(&bytes[..len]);
+        let raw = u64::from_le_bytes(buf);
+
+        if self.name == "float" && bytes.len() == 4 {
+            format!("{}", f32::from_bits(raw as u32))
+        } else if self.name == "double" && bytes.len() == 8 {
+            format!("{}", f64::from_bits(raw))
+        } else if self.name == "_Bool" {
+            format!("{}", raw != 0)
+        } else if self.name.contains("char") && bytes.len() == 1 {
+            let value = self.int_value(bytes);
+            if (raw as u8).is_ascii_graphic() || raw as u8 == b' ' {
+                format!("{} '{}'", value, raw as u8 as char)
+            } else {
+                format!("{} '\\x{:02x}'", value, raw as u8)
+            }
+        } else if len == 0 {
+            "<unknown>".to_string()
+        } else if self.name.contains("unsigned") {
+            format!("{}", raw)
+        } else {
+            format!("{}", self.int_value(bytes))
         }
     }
 }
@@ -191,6 +653,7 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
 }
 
 #[derive(Debug, Default, Clone)]
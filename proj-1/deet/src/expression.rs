@@ -0,0 +1,1106 @@
+//! Evaluates the C-like expressions accepted by `print`, such as `p->next->value + 1`,
+//! `*(int *)$rsp` or `arr[i] == 3`.
+//!
+//! The evaluator does not talk to the inferior or the DWARF data directly. Everything it needs
+//! goes through the `Context` trait, which `Debugger` implements, so that it can be tested without
+//! a live process.
+
+use crate::condition::parse_integer;
+use crate::dwarf_data::{Pointee, Type, TypeKind};
+use std::mem::size_of;
+
+/// Longest string printed after a `char *` value.
+const MAX_STRING_LEN: usize = 200;
+
+/// C's built-in types, by the names gcc gives them in the DWARF data, with their size and the
+/// other ways of spelling them.
+const BUILTIN_TYPES: [(&str, usize, &[&str]); 14] = [
+    ("char", 1, &[]),
+    ("signed char", 1, &[]),
+    ("unsigned char", 1, &[]),
+    ("short int", 2, &["short", "signed short", "signed short int"]),
+    ("short unsigned int", 2, &["unsigned short", "unsigned short int"]),
+    ("int", 4, &["signed", "signed int"]),
+    ("unsigned int", 4, &["unsigned"]),
+    ("long int", 8, &["long", "signed long", "signed long int"]),
+    ("long unsigned int", 8, &["unsigned long", "unsigned long int"]),
+    ("long long int", 8, &["long long", "signed long long", "signed long long int"]),
+    ("long long unsigned int", 8, &["unsigned long long", "unsigned long long int"]),
+    ("float", 4, &[]),
+    ("double", 8, &[]),
+    ("_Bool", 1, &["bool"]),
+];
+
+/// What the evaluator needs to know about the program being debugged.
+pub trait Context {
+    /// Reads `len` bytes of the inferior's memory starting at `addr`.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String>;
+    /// Returns the value of the register called `name` (without the `$`), or None if there is
+    /// no such register.
+    fn read_register(&self, name: &str) -> Option<u64>;
+    /// Returns the address and type of the variable called `name`, as seen from the selected
+    /// frame, or None if there is no such variable.
+    fn lookup_variable(&self, name: &str) -> Result<Option<(usize, Type)>, String>;
+    /// Returns the type called `name`, e.g. "struct node" or "long unsigned int".
+    fn lookup_type(&self, name: &str) -> Option<Type>;
+    /// Returns the type at a .debug_info offset, to follow `Pointee::Offset`.
+    fn type_at(&self, offset: usize) -> Option<Type>;
+}
+
+/// Where a value lives.
+#[derive(Clone, Debug)]
+pub enum Contents {
+    /// An object in the inferior's memory, which can have its address taken or be assigned to.
+    Memory(usize),
+    /// The result of a computation, as raw little-endian bytes.
+    Computed(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Value {
+    pub dtype: Type,
+    pub contents: Contents,
+}
+
+impl Value {
+    /// Returns the address of the value, if it is an object in memory.
+    pub fn address(&self) -> Option<usize> {
+        match self.contents {
+            Contents::Memory(addr) => Some(addr),
+            Contents::Computed(_) => None,
+        }
+    }
+
+    /// Returns the raw bytes of the value, reading them from memory if necessary.
+    pub fn bytes(&self, ctx: &dyn Context) -> Result<Vec<u8>, String> {
+        match &self.contents {
+            Contents::Memory(addr) => ctx.read_memory(*addr, self.dtype.size),
+            Contents::Computed(bytes) => Ok(bytes.clone()),
+        }
+    }
+
+    fn integer(dtype: Type, value: i64) -> Value {
+        let bytes = value.to_le_bytes()[..dtype.size.min(8)].to_vec();
+        Value {
+            dtype,
+            contents: Contents::Computed(bytes),
+        }
+    }
+
+    fn float(dtype: Type, value: f64) -> Value {
+        let bytes = if dtype.size == 4 {
+            (value as f32).to_bits().to_le_bytes().to_vec()
+        } else {
+            value.to_bits().to_le_bytes().to_vec()
+        };
+        Value {
+            dtype,
+            contents: Contents::Computed(bytes),
+        }
+    }
+}
+
+/// Parses and evaluates `expr`.
+pub fn evaluate(expr: &str, ctx: &dyn Context) -> Result<Value, String> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Err("Argument required (expression to compute).".to_string());
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        ctx,
+    };
+    let ast = parser.parse_expr()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(format!("A syntax error in expression, near `{}'.", token));
+    }
+    Evaluator { ctx }.eval(&ast)
+}
+
+/// Evaluates the assignment `lhs = rhs` without performing it: returns the address of the object
+/// named by `lhs`, and the bytes to store there, with `rhs` converted to the object's type as C
+/// would.
+pub fn assign(lhs: &str, rhs: &str, ctx: &dyn Context) -> Result<(usize, Vec<u8>), String> {
+    let target = evaluate(lhs, ctx)?;
+    let addr = target
+        .address()
+        .ok_or_else(|| "Left operand of assignment is not an lvalue.".to_string())?;
+    if let TypeKind::Array { .. } = target.dtype.strip_aliases().kind {
+        return Err("Invalid cast.".to_string());
+    }
+    let value = Evaluator { ctx }.cast(evaluate(rhs, ctx)?, target.dtype.clone())?;
+    let mut bytes = value.bytes(ctx)?;
+    bytes.resize(target.dtype.size, 0);
+    Ok((addr, bytes))
+}
+
+/// Formats a value for `print`. Character pointers are followed by the string they point to, as
+/// gdb does.
+pub fn format_value(value: &Value, ctx: &dyn Context) -> Result<String, String> {
+    let bytes = value.bytes(ctx)?;
+    let text = value.dtype.format_value(&bytes);
+    let evaluator = Evaluator { ctx };
+    if let Ok(Some(pointee)) = evaluator.pointee(&value.dtype) {
+        let addr = value.dtype.int_value(&bytes) as usize;
+        if pointee.is_char() && addr != 0 {
+            if let Some(string) = read_string(ctx, addr) {
+                return Ok(format!("{} \"{}\"", text, string.escape_debug()));
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// Reads a NUL-terminated string, a word at a time so that we don't run off the end of mapped
+/// memory. Returns None if the first word can't be read.
+fn read_string(ctx: &dyn Context, addr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    'words: while bytes.len() < MAX_STRING_LEN {
+        let word = match ctx.read_memory(addr + bytes.len(), size_of::<usize>()) {
+            Ok(word) => word,
+            Err(_) if bytes.is_empty() => return None,
+            Err(_) => break,
+        };
+        for byte in word {
+            if byte == 0 {
+                break 'words;
+            }
+            bytes.push(byte);
+        }
+    }
+    bytes.truncate(MAX_STRING_LEN);
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Integer(i64),
+    Float(f64),
+    Char(u8),
+    Identifier(String),
+    Register(String),
+    Operator(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Integer(value) => write!(f, "{}", value),
+            Token::Float(value) => write!(f, "{}", value),
+            Token::Char(value) => write!(f, "'{}'", *value as char),
+            Token::Identifier(name) => write!(f, "{}", name),
+            Token::Register(name) => write!(f, "${}", name),
+            Token::Operator(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+/// Operators, with the longer ones first so that "->" is not read as "-".
+const OPERATORS: [&str; 22] = [
+    "->", "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "~", "&",
+    "(", ")", "[", "]", ".",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (is_identifier(chars[i]) || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            if let Some(value) = parse_integer(&text) {
+                tokens.push(Token::Integer(value));
+            } else if let Ok(value) = text.parse::<f64>() {
+                tokens.push(Token::Float(value));
+            } else {
+                return Err(format!("Invalid number \"{}\".", text));
+            }
+        } else if is_identifier(c) || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && is_identifier(chars[i]) {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            if c == '$' {
+                tokens.push(Token::Register(text[1..].to_string()));
+            } else {
+                tokens.push(Token::Identifier(text));
+            }
+        } else if c == '\'' {
+            let (value, len) = match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some('\\'), Some(escaped)) => {
+                    let value = match escaped {
+                        'n' => b'\n',
+                        't' => b'\t',
+                        'r' => b'\r',
+                        '0' => 0,
+                        other => *other as u8,
+                    };
+                    (value, 2)
+                }
+                (Some(c), _) => (*c as u8, 1),
+                (None, _) => return Err("Unmatched single quote.".to_string()),
+            };
+            if chars.get(i + 1 + len) != Some(&'\'') {
+                return Err("Unmatched single quote.".to_string());
+            }
+            tokens.push(Token::Char(value));
+            i += len + 2;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(Token::Operator(op));
+                    i += op.len();
+                }
+                None => return Err(format!("Invalid character '{}' in expression.", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// The name of a type in a cast, e.g. `struct node *` is { base: "struct node", pointers: 1 }.
+#[derive(Clone, Debug)]
+struct TypeName {
+    base: String,
+    pointers: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Integer(i64),
+    Float(f64),
+    Char(u8),
+    Variable(String),
+    Register(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Member(Box<Expr>, String),
+    Arrow(Box<Expr>, String),
+    Cast(TypeName, Box<Expr>),
+}
+
+/// A recursive descent parser for C expressions. Binary operators are listed from the loosest
+/// binding to the tightest.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    ctx: &'a dyn Context,
+}
+
+const BINARY_OPERATORS: [&[&str]; 6] = [
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_operator() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("A syntax error in expression, expected `{}'.", op))
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        self.parse_binary(0)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_operator() {
+            if !BINARY_OPERATORS[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek_operator() {
+            Some(op @ "-") | Some(op @ "!") | Some(op @ "~") | Some(op @ "*") | Some(op @ "&") => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            Some("(") => match self.parse_cast_type()? {
+                Some(type_name) => Ok(Expr::Cast(type_name, Box::new(self.parse_unary()?))),
+                None => self.parse_postfix(),
+            },
+            _ => self.parse_postfix(),
+        }
+    }
+
+    /// If the parenthesis at the current position starts a cast, consumes the type name and
+    /// returns it. Something like `(x)` is a cast only if there is a type called x, but
+    /// `(struct x)` and `(x *)` can only be casts, so they are an error if x is unknown.
+    fn parse_cast_type(&mut self) -> Result<Option<TypeName>, String> {
+        let mut words = Vec::new();
+        let mut pointers = 0;
+        let mut end = self.pos + 1;
+        loop {
+            match self.tokens.get(end) {
+                Some(Token::Identifier(word)) if pointers == 0 => words.push(word.clone()),
+                Some(Token::Operator("*")) if !words.is_empty() => pointers += 1,
+                Some(Token::Operator(")")) if !words.is_empty() => break,
+                _ => return Ok(None),
+            }
+            end += 1;
+        }
+        let type_name = TypeName {
+            base: words.join(" "),
+            pointers,
+        };
+        let keywords = ["struct", "union", "enum", "const", "volatile", "signed", "unsigned"];
+        match resolve_type(self.ctx, &type_name) {
+            Ok(_) => {}
+            Err(err) if pointers > 0 || keywords.contains(&words[0].as_str()) => return Err(err),
+            Err(_) => return Ok(None),
+        }
+        self.pos = end + 1;
+        Ok(Some(type_name))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek_operator() {
+                Some("[") => {
+                    self.pos += 1;
+                    let index = self.parse_expr()?;
+                    self.expect("]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                Some(op @ ".") | Some(op @ "->") => {
+                    self.pos += 1;
+                    let field = match self.tokens.get(self.pos) {
+                        Some(Token::Identifier(field)) => field.clone(),
+                        _ => return Err(format!("A syntax error in expression, near `{}'.", op)),
+                    };
+                    self.pos += 1;
+                    expr = if op == "." {
+                        Expr::Member(Box::new(expr), field)
+                    } else {
+                        Expr::Arrow(Box::new(expr), field)
+                    };
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token.clone(),
+            None => return Err("A syntax error in expression, near `'.".to_string()),
+        };
+        self.pos += 1;
+        match token {
+            Token::Integer(value) => Ok(Expr::Integer(value)),
+            Token::Float(value) => Ok(Expr::Float(value)),
+            Token::Char(value) => Ok(Expr::Char(value)),
+            Token::Identifier(name) => Ok(Expr::Variable(name)),
+            Token::Register(name) => Ok(Expr::Register(name)),
+            Token::Operator("(") => {
+                let expr = self.parse_expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            token => Err(format!("A syntax error in expression, near `{}'.", token)),
+        }
+    }
+}
+
+/// Resolves a type name written by the user, accepting the usual spellings of the built-in
+/// types (e.g. "unsigned long" for gcc's "long unsigned int").
+fn resolve_type(ctx: &dyn Context, type_name: &TypeName) -> Result<Type, String> {
+    let base: Vec<&str> = type_name
+        .base
+        .split_whitespace()
+        .filter(|word| *word != "const" && *word != "volatile")
+        .collect();
+    let base = base.join(" ");
+    let builtin = BUILTIN_TYPES
+        .iter()
+        .find(|(name, _, aliases)| *name == base || aliases.contains(&base.as_str()));
+
+    let mut dtype = if base == "void" {
+        if type_name.pointers == 0 {
+            return Err("Invalid cast.".to_string());
+        }
+        None
+    } else if let Some(dtype) = ctx.lookup_type(builtin.map_or(base.as_str(), |b| b.0)) {
+        Some(dtype)
+    } else if let Some((name, size, _)) = builtin {
+        Some(Type::new(name.to_string(), *size))
+    } else {
+        return Err(format!("No symbol \"{}\" in current context.", base));
+    };
+
+    for _ in 0..type_name.pointers {
+        dtype = Some(pointer_to(dtype));
+    }
+    Ok(dtype.unwrap())
+}
+
+/// Makes up a pointer type to `dtype`, or to void if it is None.
+fn pointer_to(dtype: Option<Type>) -> Type {
+    match dtype {
+        Some(dtype) => Type {
+            name: format!("{} *", dtype.name),
+            size: size_of::<usize>(),
+            kind: TypeKind::Pointer(Pointee::Type(Box::new(dtype))),
+        },
+        None => Type {
+            name: "void *".to_string(),
+            size: size_of::<usize>(),
+            kind: TypeKind::Pointer(Pointee::Void),
+        },
+    }
+}
+
+/// Returns the type of integer arithmetic results, following C's promotion to at least int.
+fn integer_type(size: usize, unsigned: bool) -> Type {
+    let name = match (size > 4, unsigned) {
+        (false, false) => "int",
+        (false, true) => "unsigned int",
+        (true, false) => "long int",
+        (true, true) => "long unsigned int",
+    };
+    Type::new(name.to_string(), if size > 4 { 8 } else { 4 })
+}
+
+/// Applies the comparison operator `op`.
+fn compare<T: PartialOrd>(op: &str, x: T, y: T) -> bool {
+    match op {
+        "==" => x == y,
+        "!=" => x != y,
+        "<" => x < y,
+        "<=" => x <= y,
+        ">" => x > y,
+        _ => x >= y,
+    }
+}
+
+fn is_float(dtype: &Type) -> bool {
+    let dtype = dtype.strip_aliases();
+    match dtype.kind {
+        TypeKind::Base => dtype.name == "float" || dtype.name == "double",
+        _ => false,
+    }
+}
+
+/// A value reduced to a number for arithmetic.
+#[derive(Clone, Copy, Debug)]
+enum Number {
+    Integer { value: i64, size: usize, unsigned: bool },
+    Float(f64),
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Integer { value, unsigned: true, .. } => value as u64 as f64,
+            Number::Integer { value, .. } => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+
+    fn as_i64(self) -> i64 {
+        match self {
+            Number::Integer { value, .. } => value,
+            Number::Float(value) => value as i64,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        match self {
+            Number::Integer { value, .. } => value == 0,
+            Number::Float(value) => value == 0.0,
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    ctx: &'a dyn Context,
+}
+
+impl<'a> Evaluator<'a> {
+    fn eval(&self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Integer(value) => {
+                let fits_int = *value >= i32::MIN as i64 && *value <= i32::MAX as i64;
+                Ok(Value::integer(integer_type(if fits_int { 4 } else { 8 }, false), *value))
+            }
+            Expr::Float(value) => Ok(Value::float(Type::new("double".to_string(), 8), *value)),
+            Expr::Char(value) => Ok(Value::integer(Type::new("char".to_string(), 1), *value as i64)),
+            Expr::Variable(name) => match self.ctx.lookup_variable(name)? {
+                Some((addr, dtype)) => Ok(Value {
+                    dtype,
+                    contents: Contents::Memory(addr),
+                }),
+                None => Err(format!("No symbol \"{}\" in current context.", name)),
+            },
+            Expr::Register(name) => {
+                let value = self
+                    .ctx
+                    .read_register(name)
+                    .ok_or_else(|| format!("Invalid register \"{}\"", name))?;
+                let dtype = match name.as_str() {
+                    "rip" | "rsp" | "rbp" => pointer_to(None),
+                    _ => integer_type(8, false),
+                };
+                Ok(Value::integer(dtype, value as i64))
+            }
+            Expr::Unary(op, operand) => self.eval_unary(op, operand),
+            Expr::Binary(op, lhs, rhs) => self.eval_binary(op, lhs, rhs),
+            Expr::Index(base, index) => {
+                let base = self.eval(base)?;
+                let index = self.number(&self.eval(index)?)?.as_i64();
+                match base.dtype.strip_aliases().kind {
+                    TypeKind::Array { .. } | TypeKind::Pointer(_) => {
+                        let element = self.offset_pointer(&base, index)?;
+                        self.dereference(&element)
+                    }
+                    _ => Err("Cannot subscript requested type.".to_string()),
+                }
+            }
+            Expr::Member(base, field) => self.member(self.eval(base)?, field),
+            Expr::Arrow(base, field) => {
+                let base = self.eval(base)?;
+                self.member(self.dereference(&base)?, field)
+            }
+            Expr::Cast(type_name, operand) => {
+                let dtype = resolve_type(self.ctx, type_name)?;
+                self.cast(self.eval(operand)?, dtype)
+            }
+        }
+    }
+
+    fn eval_unary(&self, op: &str, operand: &Expr) -> Result<Value, String> {
+        let value = self.eval(operand)?;
+        match op {
+            "*" => self.dereference(&value),
+            "&" => match value.address() {
+                Some(addr) => Ok(Value::integer(pointer_to(Some(value.dtype)), addr as i64)),
+                None => Err("Attempt to take address of value not located in memory.".to_string()),
+            },
+            "!" => Ok(Value::integer(
+                integer_type(4, false),
+                self.number(&value)?.is_zero() as i64,
+            )),
+            "-" => match self.number(&value)? {
+                Number::Integer { value, size, unsigned } => {
+                    Ok(Value::integer(integer_type(size, unsigned), value.wrapping_neg()))
+                }
+                Number::Float(value) => Ok(Value::float(Type::new("double".to_string(), 8), -value)),
+            },
+            "~" => match self.number(&value)? {
+                Number::Integer { value, size, unsigned } => {
+                    Ok(Value::integer(integer_type(size, unsigned), !value))
+                }
+                Number::Float(_) => Err("Argument to complement operation not an integer.".to_string()),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn eval_binary(&self, op: &str, lhs: &Expr, rhs: &Expr) -> Result<Value, String> {
+        let lhs = self.eval(lhs)?;
+        // && and || only evaluate their right-hand side when they need to, so that e.g.
+        // `p && p->x` is safe when p is null
+        if op == "&&" || op == "||" {
+            let lhs_true = !self.number(&lhs)?.is_zero();
+            let result = if lhs_true == (op == "||") {
+                lhs_true
+            } else {
+                !self.number(&self.eval(rhs)?)?.is_zero()
+            };
+            return Ok(Value::integer(integer_type(4, false), result as i64));
+        }
+        let rhs = self.eval(rhs)?;
+
+        let is_pointer = |value: &Value| match value.dtype.strip_aliases().kind {
+            TypeKind::Pointer(_) | TypeKind::Array { .. } => true,
+            _ => false,
+        };
+        match (op, is_pointer(&lhs), is_pointer(&rhs)) {
+            ("+", true, false) => return self.offset_pointer(&lhs, self.number(&rhs)?.as_i64()),
+            ("+", false, true) => return self.offset_pointer(&rhs, self.number(&lhs)?.as_i64()),
+            ("-", true, false) => {
+                return self.offset_pointer(&lhs, self.number(&rhs)?.as_i64().wrapping_neg())
+            }
+            ("-", true, true) => {
+                let size = self.element_size(&lhs)? as i64;
+                let difference = self.number(&lhs)?.as_i64() - self.number(&rhs)?.as_i64();
+                return Ok(Value::integer(integer_type(8, false), difference / size));
+            }
+            _ => {}
+        }
+
+        let (a, b) = (self.number(&lhs)?, self.number(&rhs)?);
+        let comparison = match op {
+            "==" | "!=" | "<" | "<=" | ">" | ">=" => true,
+            _ => false,
+        };
+        match (a, b) {
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                let (x, y) = (a.as_f64(), b.as_f64());
+                if comparison {
+                    let result = compare(op, x, y);
+                    return Ok(Value::integer(integer_type(4, false), result as i64));
+                }
+                let result = match op {
+                    "+" => x + y,
+                    "-" => x - y,
+                    "*" => x * y,
+                    "/" => x / y,
+                    _ => return Err("Integer only operation %.".to_string()),
+                };
+                Ok(Value::float(Type::new("double".to_string(), 8), result))
+            }
+            (
+                Number::Integer { value: x, size: x_size, unsigned: x_unsigned },
+                Number::Integer { value: y, size: y_size, unsigned: y_unsigned },
+            ) => {
+                let size = x_size.max(y_size).max(4);
+                let unsigned = (x_unsigned && x_size == size) || (y_unsigned && y_size == size);
+                if comparison {
+                    let result = if unsigned {
+                        compare(op, x as u64, y as u64)
+                    } else {
+                        compare(op, x, y)
+                    };
+                    return Ok(Value::integer(integer_type(4, false), result as i64));
+                }
+                if (op == "/" || op == "%") && y == 0 {
+                    return Err("Division by zero".to_string());
+                }
+                let result = match (op, unsigned) {
+                    ("+", _) => x.wrapping_add(y),
+                    ("-", _) => x.wrapping_sub(y),
+                    ("*", _) => x.wrapping_mul(y),
+                    ("/", true) => ((x as u64) / (y as u64)) as i64,
+                    ("/", false) => x.wrapping_div(y),
+                    ("%", true) => ((x as u64) % (y as u64)) as i64,
+                    _ => x.wrapping_rem(y),
+                };
+                Ok(Value::integer(integer_type(size, unsigned), result))
+            }
+        }
+    }
+
+    /// Reduces a value to a number. Pointers become their address, and arrays decay to the
+    /// address of their first element.
+    fn number(&self, value: &Value) -> Result<Number, String> {
+        let dtype = value.dtype.strip_aliases();
+        if let TypeKind::Array { .. } = dtype.kind {
+            return match value.address() {
+                Some(addr) => Ok(Number::Integer {
+                    value: addr as i64,
+                    size: 8,
+                    unsigned: true,
+                }),
+                None => Err("Attempt to take address of value not located in memory.".to_string()),
+            };
+        }
+        let bytes = value.bytes(self.ctx)?;
+        match dtype.kind {
+            TypeKind::Base if is_float(dtype) => Ok(Number::Float(if dtype.size == 4 {
+                f32::from_bits(dtype.int_value(&bytes) as u32) as f64
+            } else {
+                f64::from_bits(dtype.int_value(&bytes) as u64)
+            })),
+            TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_) => Ok(Number::Integer {
+                value: dtype.int_value(&bytes),
+                size: dtype.size,
+                unsigned: dtype.is_unsigned(),
+            }),
+            _ => Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+        }
+    }
+
+    /// Returns what a pointer type points to, or None for `void *`.
+    fn pointee(&self, dtype: &Type) -> Result<Option<Type>, String> {
+        match &dtype.strip_aliases().kind {
+            TypeKind::Pointer(Pointee::Void) => Ok(None),
+            TypeKind::Pointer(Pointee::Offset(offset)) => match self.ctx.type_at(*offset) {
+                Some(pointee) => Ok(Some(pointee)),
+                None => Err("Attempt to take contents of a pointer to an unknown type.".to_string()),
+            },
+            TypeKind::Pointer(Pointee::Type(pointee)) => Ok(Some((**pointee).clone())),
+            TypeKind::Array { element, .. } => Ok(Some((**element).clone())),
+            _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+        }
+    }
+
+    fn element_size(&self, pointer: &Value) -> Result<usize, String> {
+        // Like gcc, treat void * arithmetic as byte arithmetic
+        Ok(self.pointee(&pointer.dtype)?.map_or(1, |pointee| pointee.size))
+    }
+
+    /// Computes `pointer + count`, scaled by the size of what it points to. Arrays decay to a
+    /// pointer to their first element.
+    fn offset_pointer(&self, pointer: &Value, count: i64) -> Result<Value, String> {
+        let addr = self.number(pointer)?.as_i64();
+        let size = self.element_size(pointer)? as i64;
+        let dtype = match pointer.dtype.strip_aliases().kind {
+            TypeKind::Array { .. } => pointer_to(self.pointee(&pointer.dtype)?),
+            _ => pointer.dtype.clone(),
+        };
+        Ok(Value::integer(dtype, addr.wrapping_add(count.wrapping_mul(size))))
+    }
+
+    fn dereference(&self, pointer: &Value) -> Result<Value, String> {
+        let pointee = self
+            .pointee(&pointer.dtype)?
+            .ok_or_else(|| "Attempt to take contents of a non-pointer value.".to_string())?;
+        let addr = self.number(pointer)?.as_i64() as usize;
+        Ok(Value {
+            dtype: pointee,
+            contents: Contents::Memory(addr),
+        })
+    }
+
+    fn member(&self, value: Value, field: &str) -> Result<Value, String> {
+        match value.dtype.strip_aliases().kind {
+            TypeKind::Struct(_) => {}
+            _ => {
+                return Err(
+                    "Attempt to extract a component of a value that is not a structure."
+                        .to_string(),
+                )
+            }
+        }
+        let member = value
+            .dtype
+            .member(field)
+            .ok_or_else(|| format!("There is no member named {}.", field))?
+            .clone();
+        let contents = match value.contents {
+            Contents::Memory(addr) => Contents::Memory(addr + member.offset),
+            Contents::Computed(bytes) => {
+                let end = (member.offset + member.entity_type.size).min(bytes.len());
+                Contents::Computed(bytes[member.offset.min(end)..end].to_vec())
+            }
+        };
+        Ok(Value {
+            dtype: member.entity_type,
+            contents,
+        })
+    }
+
+    fn cast(&self, value: Value, dtype: Type) -> Result<Value, String> {
+        match dtype.strip_aliases().kind {
+            TypeKind::Base if is_float(&dtype) => {
+                let number = self.number(&value)?.as_f64();
+                Ok(Value::float(dtype, number))
+            }
+            TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_) => {
+                let number = self.number(&value)?.as_i64();
+                Ok(Value::integer(dtype, number))
+            }
+            _ if dtype.name == value.dtype.name => Ok(Value { dtype, ..value }),
+            _ => Err("Invalid cast.".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf_data::Member;
+    use std::collections::HashMap;
+
+    /// Offset of `struct node` in the fake DWARF data, so that it can point to itself.
+    const NODE_OFFSET: usize = 0x100;
+
+    /// A fake program: little-endian memory, a few registers and variables.
+    struct MockContext {
+        memory: HashMap<usize, u8>,
+        registers: HashMap<String, u64>,
+        variables: HashMap<String, (usize, Type)>,
+        types: HashMap<usize, Type>,
+    }
+
+    impl MockContext {
+        fn write(&mut self, addr: usize, bytes: &[u8]) {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.memory.insert(addr + i, *byte);
+            }
+        }
+
+        fn add_variable(&mut self, name: &str, addr: usize, dtype: Type, bytes: &[u8]) {
+            self.write(addr, bytes);
+            self.variables.insert(name.to_string(), (addr, dtype));
+        }
+    }
+
+    impl Context for MockContext {
+        fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+            (addr..addr + len)
+                .map(|addr| self.memory.get(&addr).copied())
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+        }
+
+        fn read_register(&self, name: &str) -> Option<u64> {
+            self.registers.get(name).copied()
+        }
+
+        fn lookup_variable(&self, name: &str) -> Result<Option<(usize, Type)>, String> {
+            Ok(self.variables.get(name).cloned())
+        }
+
+        fn lookup_type(&self, name: &str) -> Option<Type> {
+            self.types.values().find(|dtype| dtype.name == name).cloned()
+        }
+
+        fn type_at(&self, offset: usize) -> Option<Type> {
+            self.types.get(&offset).cloned()
+        }
+    }
+
+    fn int() -> Type {
+        Type::new("int".to_string(), 4)
+    }
+
+    fn point() -> Type {
+        Type {
+            name: "struct point".to_string(),
+            size: 8,
+            kind: TypeKind::Struct(vec![
+                Member { name: "x".to_string(), offset: 0, entity_type: int() },
+                Member { name: "y".to_string(), offset: 4, entity_type: int() },
+            ]),
+        }
+    }
+
+    fn node() -> Type {
+        Type {
+            name: "struct node".to_string(),
+            size: 16,
+            kind: TypeKind::Struct(vec![
+                Member { name: "value".to_string(), offset: 0, entity_type: int() },
+                Member {
+                    name: "next".to_string(),
+                    offset: 8,
+                    entity_type: Type {
+                        name: "struct node *".to_string(),
+                        size: 8,
+                        kind: TypeKind::Pointer(Pointee::Offset(NODE_OFFSET)),
+                    },
+                },
+            ]),
+        }
+    }
+
+    fn words(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()
+    }
+
+    /// Sets up:
+    ///   int x = 42;                       at 0x1000
+    ///   int arr[4] = {10, 20, 30, 40};    at 0x2000
+    ///   struct point pt = {3, -4};        at 0x3000
+    ///   struct node second = {2, NULL};   at 0x4010
+    ///   struct node *head = &first;       at 0x5000, first = {1, &second} at 0x4000
+    ///   char *name = "deet";              at 0x6000, string at 0x6100
+    ///   double ratio = 2.5;               at 0x7000
+    ///   $rax = 7, $rsp = 0x1000
+    fn context() -> MockContext {
+        let mut ctx = MockContext {
+            memory: HashMap::new(),
+            registers: HashMap::new(),
+            variables: HashMap::new(),
+            types: HashMap::new(),
+        };
+        ctx.types.insert(0x10, int());
+        ctx.types.insert(0x20, point());
+        ctx.types.insert(NODE_OFFSET, node());
+        ctx.types.insert(0x30, Type::new("char".to_string(), 1));
+
+        ctx.add_variable("x", 0x1000, int(), &42i32.to_le_bytes());
+        let arr = Type {
+            name: "int [4]".to_string(),
+            size: 16,
+            kind: TypeKind::Array { element: Box::new(int()), count: Some(4) },
+        };
+        let arr_bytes: Vec<u8> =
+            [10i32, 20, 30, 40].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        ctx.add_variable("arr", 0x2000, arr, &arr_bytes);
+        let pt_bytes: Vec<u8> = [3i32, -4].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        ctx.add_variable("pt", 0x3000, point(), &pt_bytes);
+
+        ctx.write(0x4000, &words(&[1, 0x4010]));
+        ctx.add_variable("second", 0x4010, node(), &words(&[2, 0]));
+        let node_pointer = Type {
+            name: "struct node *".to_string(),
+            size: 8,
+            kind: TypeKind::Pointer(Pointee::Offset(NODE_OFFSET)),
+        };
+        ctx.add_variable("head", 0x5000, node_pointer, &words(&[0x4000]));
+
+        let char_pointer = Type {
+            name: "char *".to_string(),
+            size: 8,
+            kind: TypeKind::Pointer(Pointee::Offset(0x30)),
+        };
+        ctx.add_variable("name", 0x6000, char_pointer, &words(&[0x6100]));
+        ctx.write(0x6100, b"deet\0\0\0\0");
+        ctx.add_variable(
+            "ratio",
+            0x7000,
+            Type::new("double".to_string(), 8),
+            &2.5f64.to_bits().to_le_bytes(),
+        );
+
+        ctx.registers.insert("rax".to_string(), 7);
+        ctx.registers.insert("rsp".to_string(), 0x1000);
+        ctx
+    }
+
+    fn print(expr: &str) -> String {
+        let ctx = context();
+        match evaluate(expr, &ctx).and_then(|value| format_value(&value, &ctx)) {
+            Ok(text) => text,
+            Err(err) => format!("error: {}", err),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(print("1 + 2 * 3"), "7");
+        assert_eq!(print("(1 + 2) * 3"), "9");
+        assert_eq!(print("-7 / 2"), "-3");
+        assert_eq!(print("7 % 3 - 10"), "-9");
+        assert_eq!(print("x * 2 + 1"), "85");
+        assert_eq!(print("0x10 + 1"), "17");
+        assert_eq!(print("ratio * 2"), "5");
+        assert_eq!(print("1 / 0"), "error: Division by zero");
+    }
+
+    #[test]
+    fn comparisons_and_logic() {
+        assert_eq!(print("x == 42"), "1");
+        assert_eq!(print("x != 42"), "0");
+        assert_eq!(print("3 < 4 && 4 <= 4"), "1");
+        assert_eq!(print("x > 100 || !x"), "0");
+        // The right-hand side must not be evaluated, or it would read unmapped memory
+        assert_eq!(print("0 && *(int *)0"), "0");
+        assert_eq!(print("1 || *(int *)0"), "1");
+    }
+
+    #[test]
+    fn variables_and_addresses() {
+        assert_eq!(print("x"), "42");
+        assert_eq!(print("&x"), "0x1000");
+        assert_eq!(print("*&x"), "42");
+        assert_eq!(print("y"), "error: No symbol \"y\" in current context.");
+        assert_eq!(print("&1"), "error: Attempt to take address of value not located in memory.");
+    }
+
+    #[test]
+    fn structs_and_pointers() {
+        assert_eq!(print("pt"), "{x = 3, y = -4}");
+        assert_eq!(print("pt.y"), "-4");
+        assert_eq!(print("head->value"), "1");
+        assert_eq!(print("head->next->value"), "2");
+        assert_eq!(print("(*head).next->next"), "0x0");
+        assert_eq!(print("*head->next"), "{value = 2, next = 0x0}");
+        assert_eq!(print("pt.z"), "error: There is no member named z.");
+        assert_eq!(print("*x"), "error: Attempt to take contents of a non-pointer value.");
+        assert_eq!(print("name"), "0x6100 \"deet\"");
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(print("arr"), "{10, 20, 30, 40}");
+        assert_eq!(print("arr[2]"), "30");
+        assert_eq!(print("arr[x - 41]"), "20");
+        assert_eq!(print("*(arr + 3)"), "40");
+        assert_eq!(print("&arr[3] - &arr[1]"), "2");
+        assert_eq!(print("pt[0]"), "error: Cannot subscript requested type.");
+    }
+
+    #[test]
+    fn casts() {
+        assert_eq!(print("(char)321"), "65 'A'");
+        assert_eq!(print("*(int *)0x2004"), "20");
+        assert_eq!(print("(unsigned char)-1"), "255 '\\xff'");
+        assert_eq!(print("((struct point *)0x3000)->x"), "3");
+        assert_eq!(print("(long)head"), "16384");
+        assert_eq!(print("(int)ratio"), "2");
+        assert_eq!(print("(x)"), "42");
+        assert_eq!(
+            print("(struct nope *)0"),
+            "error: No symbol \"struct nope\" in current context."
+        );
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(print("$rax + 1"), "8");
+        assert_eq!(print("$rsp"), "0x1000");
+        assert_eq!(print("*(int *)$rsp"), "42");
+        assert_eq!(print("$nope"), "error: Invalid register \"nope\"");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(print("1 +"), "error: A syntax error in expression, near `'.");
+        assert_eq!(print("(1"), "error: A syntax error in expression, expected `)'.");
+        assert_eq!(print("1 2"), "error: A syntax error in expression, near `2'.");
+        assert_eq!(print("x @ 2"), "error: Invalid character '@' in expression.");
+    }
+
+    #[test]
+    fn assignments() {
+        let ctx = context();
+        let assign = |lhs, rhs| assign(lhs, rhs, &ctx);
+        assert_eq!(assign("x", "5"), Ok((0x1000, vec![5, 0, 0, 0])));
+        assert_eq!(assign("arr[1]", "-1"), Ok((0x2004, vec![0xff; 4])));
+        assert_eq!(assign("*(char *)0x2000", "0x141"), Ok((0x2000, vec![0x41])));
+        assert_eq!(assign("x", "ratio * 3"), Ok((0x1000, vec![7, 0, 0, 0])));
+        assert_eq!(assign("ratio", "1"), Ok((0x7000, 1f64.to_bits().to_le_bytes().to_vec())));
+        assert_eq!(assign("head->next", "0"), Ok((0x4008, vec![0; 8])));
+        assert_eq!(
+            assign("x + 1", "2"),
+            Err("Left operand of assignment is not an lvalue.".to_string())
+        );
+        assert_eq!(assign("pt", "1"), Err("Invalid cast.".to_string()));
+    }
+}
@@ -0,0 +1,407 @@
+//! `deet --gdbserver [host]:port`: runs the target under our ptrace engine and lets another
+//! debugger drive it over the GDB Remote Serial Protocol. We serve a single connection, and support
+//! the packets needed to read and write registers and memory, set software breakpoints, continue
+//! and single-step. The inferior can't be interrupted while it is running.
+
+use crate::debugger::Breakpoint;
+use crate::inferior::{register_mut, Inferior, Status, REGISTER_NAMES};
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Largest packet we accept, as advertised in the reply to `qSupported`.
+const MAX_PACKET_SIZE: usize = 0x4000;
+/// Number of registers in the `g` packet: the general-purpose registers in the order of gdb's
+/// amd64 description, which is also the order of `REGISTER_NAMES`. The first 17 (up to %rip)
+/// are 8 bytes wide, the rest 4.
+const NUM_GDB_REGISTERS: usize = 24;
+const NUM_WIDE_REGISTERS: usize = 17;
+/// gdb's number for a signal it has no name for.
+const GDB_SIGNAL_UNKNOWN: u8 = 143;
+
+/// gdb numbers signals its own way, which agrees with Linux only for the older ones.
+const GDB_SIGNALS: &[(Signal, u8)] = &[
+    (Signal::SIGHUP, 1),
+    (Signal::SIGINT, 2),
+    (Signal::SIGQUIT, 3),
+    (Signal::SIGILL, 4),
+    (Signal::SIGTRAP, 5),
+    (Signal::SIGABRT, 6),
+    (Signal::SIGFPE, 8),
+    (Signal::SIGKILL, 9),
+    (Signal::SIGBUS, 10),
+    (Signal::SIGSEGV, 11),
+    (Signal::SIGSYS, 12),
+    (Signal::SIGPIPE, 13),
+    (Signal::SIGALRM, 14),
+    (Signal::SIGTERM, 15),
+    (Signal::SIGURG, 16),
+    (Signal::SIGSTOP, 17),
+    (Signal::SIGTSTP, 18),
+    (Signal::SIGCONT, 19),
+    (Signal::SIGCHLD, 20),
+    (Signal::SIGTTIN, 21),
+    (Signal::SIGTTOU, 22),
+    (Signal::SIGIO, 23),
+    (Signal::SIGXCPU, 24),
+    (Signal::SIGXFSZ, 25),
+    (Signal::SIGVTALRM, 26),
+    (Signal::SIGPROF, 27),
+    (Signal::SIGWINCH, 28),
+    (Signal::SIGUSR1, 30),
+    (Signal::SIGUSR2, 31),
+    (Signal::SIGPWR, 32),
+];
+
+fn gdb_signal(signal: Signal) -> u8 {
+    GDB_SIGNALS
+        .iter()
+        .find(|(sig, _)| *sig == signal)
+        .map_or(GDB_SIGNAL_UNKNOWN, |(_, number)| *number)
+}
+
+fn from_gdb_signal(number: u8) -> Option<Signal> {
+    GDB_SIGNALS
+        .iter()
+        .find(|(_, num)| *num == number)
+        .map(|(sig, _)| *sig)
+}
+
+/// Starts `target` stopped at its first instruction, waits for a client on `address` (e.g.
+/// ":1234", or ":0" for any free port) and serves it until it kills or detaches from the
+/// inferior, or hangs up.
+pub fn serve(target: &str, args: &Vec<String>, address: &str) -> io::Result<()> {
+    // gdbserver listens on every interface when no host is given. Whoever connects can do
+    // anything the inferior can, so we only listen on this machine.
+    let address = if address.starts_with(':') {
+        format!("localhost{}", address)
+    } else {
+        address.to_string()
+    };
+    let listener = TcpListener::bind(&address)?;
+    let inferior = match Inferior::new(target, args, false) {
+        Some(inferior) => inferior,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Error starting subprocess {}", target),
+            ))
+        }
+    };
+    println!("Process {} created; pid = {}", target, inferior.pid());
+    println!("Listening on port {}", listener.local_addr()?.port());
+
+    let mut server = GdbServer {
+        inferior: Some(inferior),
+        breakpoints: HashMap::new(),
+        last_stop: format!("S{:02x}", gdb_signal(Signal::SIGTRAP)),
+        done: false,
+    };
+    let result = listener.accept().and_then(|(stream, peer)| {
+        println!("Remote debugging from host {}", peer.ip());
+        server.run(Connection::new(stream)?)
+    });
+    // Don't leave the inferior behind if the client hung up without killing it
+    if let Some(mut inferior) = server.inferior.take() {
+        let _ = inferior.kill();
+    }
+    result
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Splits a remote protocol stream into packets, acknowledging each one.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        Ok(Connection {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Returns the contents of the next packet, or None once the client hangs up. Packets with
+    /// a bad checksum are asked for again.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut byte = [0u8];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            // Anything outside a packet is an acknowledgement, or an interrupt we can't act on
+            if byte[0] != b'$' {
+                continue;
+            }
+            let mut data = Vec::new();
+            self.reader.read_until(b'#', &mut data)?;
+            if data.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut sum = [0u8; 2];
+            self.reader.read_exact(&mut sum)?;
+            let expected = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+            if expected == Some(checksum(&data)) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    /// Sends a packet, resending it until the client acknowledges it.
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        loop {
+            self.writer.write_all(packet.as_bytes())?;
+            let mut ack = [0u8];
+            if self.reader.read(&mut ack)? == 0 || ack[0] != b'-' {
+                return Ok(());
+            }
+        }
+    }
+}
+
+struct GdbServer {
+    /// None once the inferior has exited, been killed or been detached.
+    inferior: Option<Inferior>,
+    /// Software breakpoints set with `Z0`. The client numbers its breakpoints itself, so ours are
+    /// unnumbered.
+    breakpoints: HashMap<usize, Breakpoint>,
+    /// The reply to `?`: why the inferior last stopped.
+    last_stop: String,
+    /// Set once the client kills or detaches from the inferior.
+    done: bool,
+}
+
+impl GdbServer {
+    fn run(&mut self, mut connection: Connection) -> io::Result<()> {
+        while !self.done {
+            let packet = match connection.read_packet()? {
+                Some(packet) => packet,
+                None => break,
+            };
+            if let Some(reply) = self.handle(&packet) {
+                connection.send_packet(&reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries out the request in `packet`, and returns the reply to send, if any.
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let (kind, args) = match packet.chars().next() {
+            Some(kind) => (kind, &packet[kind.len_utf8()..]),
+            None => return Some(String::new()),
+        };
+        let reply = match kind {
+            '?' => Some(self.last_stop.clone()),
+            'q' if packet.starts_with("qSupported") => {
+                Some(format!("PacketSize={:x}", MAX_PACKET_SIZE))
+            }
+            'g' => self.read_registers(),
+            'G' => self.write_registers(args),
+            'm' => self.read_memory(args),
+            'M' => self.write_memory(args),
+            'c' => self.resume(args, None, false),
+            'C' => {
+                // "Csig;addr": continue, delivering a signal
+                let mut parts = args.splitn(2, ';');
+                let signal = u8::from_str_radix(parts.next().unwrap_or(""), 16)
+                    .ok()
+                    .and_then(from_gdb_signal);
+                self.resume(parts.next().unwrap_or(""), signal, false)
+            }
+            's' => self.resume(args, None, true),
+            'Z' if args.starts_with("0,") => self.insert_breakpoint(&args[2..]),
+            'z' if args.starts_with("0,") => self.remove_breakpoint(&args[2..]),
+            // We have no threads to pick between as far as the client knows
+            'H' => Some("OK".to_string()),
+            'k' => {
+                if let Some(mut inferior) = self.inferior.take() {
+                    let _ = inferior.kill();
+                }
+                self.done = true;
+                return None;
+            }
+            'D' => {
+                self.done = true;
+                let inferior = self.inferior.take()?;
+                inferior
+                    .detach(&self.breakpoints)
+                    .ok()
+                    .map(|_| "OK".to_string())
+            }
+            // An empty reply tells the client that we don't support the packet
+            _ => Some(String::new()),
+        };
+        Some(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    fn read_registers(&self) -> Option<String> {
+        let mut regs = self.inferior.as_ref()?.getregs().ok()?;
+        let mut reply = String::new();
+        for (index, name) in REGISTER_NAMES[..NUM_GDB_REGISTERS].iter().enumerate() {
+            let value = *register_mut(&mut regs, name)?;
+            reply.push_str(&encode_hex(&value.to_le_bytes()[..register_width(index)]));
+        }
+        Some(reply)
+    }
+
+    /// Sets the registers from a `G` packet. A short packet leaves the registers at the end
+    /// alone.
+    fn write_registers(&self, hex: &str) -> Option<String> {
+        let inferior = self.inferior.as_ref()?;
+        let bytes = decode_hex(hex)?;
+        let mut regs = inferior.getregs().ok()?;
+        let mut offset = 0;
+        for (index, name) in REGISTER_NAMES[..NUM_GDB_REGISTERS].iter().enumerate() {
+            let width = register_width(index);
+            if offset + width > bytes.len() {
+                break;
+            }
+            let mut value = [0u8; 8];
+            value[..width].copy_from_slice(&bytes[offset..offset + width]);
+            *register_mut(&mut regs, name)? = u64::from_le_bytes(value);
+            offset += width;
+        }
+        inferior.setregs(regs).ok()?;
+        Some("OK".to_string())
+    }
+
+    /// Handles "addr,length". Our breakpoints don't show up in the bytes returned.
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = parse_addr_len(args)?;
+        let len = len.min(MAX_PACKET_SIZE / 2);
+        let bytes = self
+            .inferior
+            .as_ref()?
+            .read_bytes(addr, len, &self.breakpoints)
+            .ok()?;
+        Some(encode_hex(&bytes))
+    }
+
+    /// Handles "addr,length:XX...".
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let mut parts = args.splitn(2, ':');
+        let (addr, len) = parse_addr_len(parts.next()?)?;
+        let bytes = decode_hex(parts.next()?)?;
+        if bytes.len() != len {
+            return None;
+        }
+        let inferior = self.inferior.as_mut()?;
+        inferior
+            .write_memory(addr, &bytes, &mut self.breakpoints)
+            .ok()?;
+        Some("OK".to_string())
+    }
+
+    /// Handles "addr,kind" for a software breakpoint.
+    fn insert_breakpoint(&mut self, args: &str) -> Option<String> {
+        let addr = usize::from_str_radix(args.split(',').next()?, 16).ok()?;
+        if !self.breakpoints.contains_key(&addr) {
+            let orig_byte = self.inferior.as_mut()?.write_byte(addr, 0xcc).ok()?;
+            self.breakpoints
+                .insert(addr, Breakpoint::temporary(addr, orig_byte));
+        }
+        Some("OK".to_string())
+    }
+
+    fn remove_breakpoint(&mut self, args: &str) -> Option<String> {
+        let addr = usize::from_str_radix(args.split(',').next()?, 16).ok()?;
+        if let Some(bp) = self.breakpoints.remove(&addr) {
+            self.inferior
+                .as_mut()?
+                .write_byte(addr, bp.orig_byte)
+                .ok()?;
+        }
+        Some("OK".to_string())
+    }
+
+    /// Continues or single-steps, from `addr` if one is given, and returns the stop reply.
+    fn resume(&mut self, addr: &str, signal: Option<Signal>, step: bool) -> Option<String> {
+        let inferior = self.inferior.as_mut()?;
+        if !addr.is_empty() {
+            let mut regs = inferior.getregs().ok()?;
+            regs.rip = u64::from_str_radix(addr, 16).ok()?;
+            inferior.setregs(regs).ok()?;
+        }
+        let mut signal = signal;
+        let status = loop {
+            let status = if step {
+                inferior.step_instruction(&self.breakpoints)
+            } else {
+                inferior.con(&self.breakpoints, signal.take())
+            };
+            match status.ok()? {
+                // The Inferior keeps track of new threads
+                Status::Cloned(_) => {}
+                // We only debug one process, so let children go
+                Status::Forked(pid) => {
+                    let child = inferior.fork_child(pid).ok()?;
+                    child.detach(&self.breakpoints).ok()?;
+                }
+                // The new program has none of the breakpoints the client set
+                Status::Execed => {
+                    self.breakpoints.clear();
+                    break Status::Stopped(Signal::SIGTRAP, 0, None);
+                }
+                status => break status,
+            }
+            if step {
+                break Status::Stopped(Signal::SIGTRAP, 0, None);
+            }
+        };
+        self.last_stop = match status {
+            Status::Exited(code) => {
+                self.inferior = None;
+                format!("W{:02x}", code as u8)
+            }
+            Status::Signaled(signal) => {
+                self.inferior = None;
+                format!("X{:02x}", gdb_signal(signal))
+            }
+            Status::Stopped(signal, _, _) => format!("S{:02x}", gdb_signal(signal)),
+            _ => format!("S{:02x}", gdb_signal(Signal::SIGTRAP)),
+        };
+        Some(self.last_stop.clone())
+    }
+}
+
+fn register_width(index: usize) -> usize {
+    if index < NUM_WIDE_REGISTERS {
+        8
+    } else {
+        4
+    }
+}
+
+/// Parses the "addr,length" at the start of `m` and `M` packets.
+fn parse_addr_len(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((addr, len))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -10,13 +10,18 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    File, Function, Line, Location, Member, Pointee, Type, TypeKind, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -39,8 +44,12 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    // Types by .debug_info offset. They are resolved into `Type`s once every unit has been read,
+    // since a type may refer to types that come after it.
+    let mut raw_types: HashMap<usize, RawType> = HashMap::new();
+    // Variables whose types still need to be filled in, as (file, function, variable, type offset)
+    // indices. Global variables have no function.
+    let mut pending_types: Vec<(usize, Option<usize>, usize, usize)> = Vec::new();
 
     let mut compilation_units: Vec<File> = Vec::new();
 
@@ -52,8 +61,13 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
         let mut entries = unit.entries();
+        // Structs, arrays and enums whose children we are reading, as (depth, type offset)
+        let mut type_parents: Vec<(isize, usize)> = Vec::new();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
+            while type_parents.last().map_or(false, |(parent_depth, _)| *parent_depth >= depth) {
+                type_parents.pop();
+            }
             // Update the offset_to_type mapping for types
             // Update the variable list for formal params/variables
             match entry.tag() {
@@ -75,31 +89,143 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     });
                 }
                 gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
+                    raw_types.insert(
+                        entry_offset(&entry, &unit),
+                        RawType {
+                            name: Some(name_attr(&entry, &unit, &dwarf)),
+                            byte_size: byte_size_attr(&entry),
+                            kind: RawKind::Base,
+                        },
+                    );
+                }
+                gimli::DW_TAG_pointer_type => {
+                    raw_types.insert(
+                        entry_offset(&entry, &unit),
+                        RawType {
+                            name: None,
+                            byte_size: Some(std::mem::size_of::<usize>()),
+                            kind: RawKind::Pointer(type_attr(&entry, &unit, &dwarf)),
+                        },
+                    );
+                }
+                gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type => {
+                    let keyword = if entry.tag() == gimli::DW_TAG_union_type {
+                        "union"
                     } else {
-                        "<unknown>".to_string()
+                        "struct"
                     };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
+                    let offset = entry_offset(&entry, &unit);
+                    raw_types.insert(
+                        offset,
+                        RawType {
+                            name: optional_name_attr(&entry, &unit, &dwarf),
+                            byte_size: byte_size_attr(&entry),
+                            kind: RawKind::Struct {
+                                keyword,
+                                members: Vec::new(),
+                            },
+                        },
+                    );
+                    type_parents.push((depth, offset));
+                }
+                gimli::DW_TAG_member => {
+                    if let Some(RawKind::Struct { members, .. }) =
+                        parent_type(&type_parents, depth, &mut raw_types)
+                    {
+                        // Union members have no location; they all start at offset 0
+                        let offset = match entry.attr(gimli::DW_AT_data_member_location) {
+                            Ok(Some(attr)) => attr.udata_value().unwrap_or(0),
+                            _ => 0,
+                        };
+                        members.push((
+                            name_attr(&entry, &unit, &dwarf),
+                            offset.try_into().unwrap(),
+                            type_attr(&entry, &unit, &dwarf),
+                        ));
+                    }
+                }
+                gimli::DW_TAG_array_type => {
+                    let offset = entry_offset(&entry, &unit);
+                    raw_types.insert(
+                        offset,
+                        RawType {
+                            name: None,
+                            byte_size: None,
+                            kind: RawKind::Array {
+                                element: type_attr(&entry, &unit, &dwarf),
+                                counts: Vec::new(),
+                            },
+                        },
+                    );
+                    type_parents.push((depth, offset));
+                }
+                gimli::DW_TAG_subrange_type => {
+                    if let Some(RawKind::Array { counts, .. }) =
+                        parent_type(&type_parents, depth, &mut raw_types)
+                    {
+                        let count = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_count) {
+                            attr.udata_value()
+                        } else if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_upper_bound) {
+                            // gcc gives flexible array members an upper bound of -1
+                            attr.sdata_value()
+                                .filter(|bound| *bound >= 0)
+                                .map(|bound| bound as u64 + 1)
                         } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
+                            None
+                        };
+                        counts.push(count.map(|count| count.try_into().unwrap()));
+                    }
+                }
+                gimli::DW_TAG_typedef => {
+                    raw_types.insert(
+                        entry_offset(&entry, &unit),
+                        RawType {
+                            name: Some(name_attr(&entry, &unit, &dwarf)),
+                            byte_size: None,
+                            kind: RawKind::Typedef(type_attr(&entry, &unit, &dwarf)),
+                        },
+                    );
+                }
+                gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type | gimli::DW_TAG_restrict_type => {
+                    let keyword = match entry.tag() {
+                        gimli::DW_TAG_const_type => "const",
+                        gimli::DW_TAG_volatile_type => "volatile",
+                        _ => "restrict",
                     };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
+                    raw_types.insert(
+                        entry_offset(&entry, &unit),
+                        RawType {
+                            name: None,
+                            byte_size: None,
+                            kind: RawKind::Qualifier {
+                                keyword,
+                                target: type_attr(&entry, &unit, &dwarf),
+                            },
+                        },
+                    );
+                }
+                gimli::DW_TAG_enumeration_type => {
+                    let offset = entry_offset(&entry, &unit);
+                    raw_types.insert(
+                        offset,
+                        RawType {
+                            name: optional_name_attr(&entry, &unit, &dwarf),
+                            byte_size: byte_size_attr(&entry),
+                            kind: RawKind::Enum(Vec::new()),
+                        },
+                    );
+                    type_parents.push((depth, offset));
+                }
+                gimli::DW_TAG_enumerator => {
+                    if let Some(RawKind::Enum(values)) =
+                        parent_type(&type_parents, depth, &mut raw_types)
+                    {
+                        let value = match entry.attr(gimli::DW_AT_const_value) {
+                            Ok(Some(attr)) => attr.sdata_value().unwrap_or(0),
+                            _ => 0,
+                        };
+                        values.push((name_attr(&entry, &unit, &dwarf), value));
+                    }
                 }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
@@ -136,7 +262,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
+                    let mut type_offset: Option<usize> = None;
                     let mut location: Option<Location> = None;
                     let mut line_number = 0;
                     let mut attrs = entry.attrs();
@@ -151,9 +277,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             }
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
+                                    type_offset = Some(offset);
                                 }
                             }
                             gimli::DW_AT_location => {
@@ -169,28 +293,34 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
+                    if type_offset.is_some() && location.is_some() {
                         let var = Variable {
                             name,
-                            entity_type: entity_type.unwrap(),
+                            entity_type: Type::default(),
                             location: location.unwrap(),
                             line_number: line_number.try_into().unwrap(),
+                            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
                         };
+                        let file_index = compilation_units.len() - 1;
+                        let file = compilation_units.last_mut().unwrap();
                         if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
+                            pending_types.push((
+                                file_index,
+                                None,
+                                file.global_variables.len(),
+                                type_offset.unwrap(),
+                            ));
+                            file.global_variables.push(var);
                         } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                            let func_index = file.functions.len() - 1;
+                            let func = file.functions.last_mut().unwrap();
+                            pending_types.push((
+                                file_index,
+                                Some(func_index),
+                                func.variables.len(),
+                                type_offset.unwrap(),
+                            ));
+                            func.variables.push(var);
                         }
                     }
                 }
@@ -240,7 +370,235 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+
+    // Now that every type has been seen, build the type trees and fill in the variables' types
+    let mut types: HashMap<usize, Type> = HashMap::new();
+    for offset in raw_types.keys() {
+        resolve_type(&raw_types, Some(*offset), &mut types);
+    }
+    for (file, func, var, offset) in pending_types {
+        let variable = match func {
+            Some(func) => &mut compilation_units[file].functions[func].variables[var],
+            None => &mut compilation_units[file].global_variables[var],
+        };
+        variable.entity_type = resolve_type(&raw_types, Some(offset), &mut types);
+    }
+    Ok((compilation_units, types))
+}
+
+/// A type as it appears in the DWARF data, referring to other types by .debug_info offset.
+struct RawType {
+    name: Option<String>,
+    byte_size: Option<usize>,
+    kind: RawKind,
+}
+
+enum RawKind {
+    Base,
+    Pointer(Option<usize>),
+    Struct {
+        keyword: &'static str,
+        /// (name, offset, type) of each member
+        members: Vec<(String, usize, Option<usize>)>,
+    },
+    Array {
+        element: Option<usize>,
+        /// Length of each dimension, outermost first
+        counts: Vec<Option<usize>>,
+    },
+    Typedef(Option<usize>),
+    Qualifier {
+        keyword: &'static str,
+        target: Option<usize>,
+    },
+    Enum(Vec<(String, i64)>),
+}
+
+/// Returns the C name of the type at `offset`, e.g. "struct node *" or "const char [6]". A
+/// missing offset means void.
+fn type_name(raw_types: &HashMap<usize, RawType>, offset: Option<usize>) -> String {
+    let raw = match offset {
+        Some(offset) => match raw_types.get(&offset) {
+            Some(raw) => raw,
+            None => return "<unknown>".to_string(),
+        },
+        None => return "void".to_string(),
+    };
+    match &raw.kind {
+        RawKind::Base | RawKind::Typedef(_) => {
+            raw.name.clone().unwrap_or_else(|| "<unknown>".to_string())
+        }
+        RawKind::Pointer(pointee) => format!("{} *", type_name(raw_types, *pointee)),
+        RawKind::Struct { keyword, .. } => {
+            format!("{} {}", keyword, raw.name.as_deref().unwrap_or("{...}"))
+        }
+        RawKind::Enum(_) => format!("enum {}", raw.name.as_deref().unwrap_or("{...}")),
+        RawKind::Array { element, counts } => {
+            format!("{} {}", type_name(raw_types, *element), array_bounds(counts))
+        }
+        RawKind::Qualifier { keyword, target } => {
+            format!("{} {}", keyword, type_name(raw_types, *target))
+        }
+    }
+}
+
+/// Formats array dimensions the way C declares them, e.g. "[2][3]".
+fn array_bounds(counts: &[Option<usize>]) -> String {
+    counts
+        .iter()
+        .map(|count| match count {
+            Some(count) => format!("[{}]", count),
+            None => "[]".to_string(),
+        })
+        .collect()
+}
+
+/// Builds the `Type` tree for the type at `offset`, caching it in `types`. Pointers are the only
+/// types that can refer back to themselves, and they keep their pointee as an offset, so this
+/// always terminates.
+fn resolve_type(
+    raw_types: &HashMap<usize, RawType>,
+    offset: Option<usize>,
+    types: &mut HashMap<usize, Type>,
+) -> Type {
+    let offset = match offset {
+        Some(offset) => offset,
+        None => return Type::new("void".to_string(), 0),
+    };
+    if let Some(dtype) = types.get(&offset) {
+        return dtype.clone();
+    }
+    let raw = match raw_types.get(&offset) {
+        Some(raw) => raw,
+        None => return Type::new("<unknown>".to_string(), 0),
+    };
+
+    let name = type_name(raw_types, Some(offset));
+    let dtype = match &raw.kind {
+        RawKind::Base => Type::new(name, raw.byte_size.unwrap_or(0)),
+        RawKind::Pointer(pointee) => Type {
+            name,
+            size: std::mem::size_of::<usize>(),
+            kind: TypeKind::Pointer(match pointee {
+                Some(offset) => Pointee::Offset(*offset),
+                None => Pointee::Void,
+            }),
+        },
+        RawKind::Struct { members, .. } => Type {
+            name,
+            size: raw.byte_size.unwrap_or(0),
+            kind: TypeKind::Struct(
+                members
+                    .iter()
+                    .map(|(name, offset, member_type)| Member {
+                        name: name.clone(),
+                        offset: *offset,
+                        entity_type: resolve_type(raw_types, *member_type, types),
+                    })
+                    .collect(),
+            ),
+        },
+        RawKind::Array { element, counts } => {
+            let mut dtype = resolve_type(raw_types, *element, types);
+            let element_name = dtype.name.clone();
+            let counts = if counts.is_empty() {
+                vec![None]
+            } else {
+                counts.clone()
+            };
+            // A multi-dimensional array is an array of arrays, so build it from the innermost
+            // dimension out
+            for dimension in (0..counts.len()).rev() {
+                dtype = Type {
+                    name: format!("{} {}", element_name, array_bounds(&counts[dimension..])),
+                    size: dtype.size * counts[dimension].unwrap_or(0),
+                    kind: TypeKind::Array {
+                        element: Box::new(dtype),
+                        count: counts[dimension],
+                    },
+                };
+            }
+            dtype
+        }
+        RawKind::Typedef(target) | RawKind::Qualifier { target, .. } => {
+            let target = resolve_type(raw_types, *target, types);
+            Type {
+                name,
+                size: target.size,
+                kind: TypeKind::Alias(Box::new(target)),
+            }
+        }
+        RawKind::Enum(values) => Type {
+            name,
+            size: raw.byte_size.unwrap_or(4),
+            kind: TypeKind::Enum(values.clone()),
+        },
+    };
+    types.insert(offset, dtype.clone());
+    dtype
+}
+
+/// Returns the type whose children are at `depth`, if it is the innermost struct, array or enum
+/// being read.
+fn parent_type<'a>(
+    type_parents: &[(isize, usize)],
+    depth: isize,
+    raw_types: &'a mut HashMap<usize, RawType>,
+) -> Option<&'a mut RawKind> {
+    let (parent_depth, offset) = type_parents.last()?;
+    if *parent_depth != depth - 1 {
+        return None;
+    }
+    raw_types.get_mut(offset).map(|raw| &mut raw.kind)
+}
+
+/// Returns the .debug_info offset of `entry`, which is how DW_AT_type attributes refer to it.
+fn entry_offset<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> usize {
+    match entry.offset().to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+    }
+}
+
+/// Returns the .debug_info offset of the type `entry` refers to, or None for void.
+fn type_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    let attr = entry.attr(gimli::DW_AT_type).ok()??;
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+fn optional_name_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    let attr = entry.attr(gimli::DW_AT_name).ok()??;
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+fn name_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> String {
+    optional_name_attr(entry, unit, dwarf).unwrap_or_else(|| "<unknown>".to_string())
+}
+
+fn byte_size_attr<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>) -> Option<usize> {
+    let size = entry.attr(gimli::DW_AT_byte_size).ok()??.udata_value()?;
+    Some(size.try_into().unwrap())
 }
 
 #[derive(Debug, Clone)]
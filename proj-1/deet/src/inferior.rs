@@ -2,19 +2,67 @@ use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::process::{Child, Command};
+use std::process::{Command, Stdio};
 use std::os::unix::process::CommandExt;
 use crate::dwarf_data::DwarfData;
+use crate::output;
 use std::mem::size_of;
 use std::collections::HashMap;
+use std::convert::TryInto;
 use crate::debugger::Breakpoint;
+use libc::user_regs_struct;
+use std::thread::JoinHandle;
+use crate::unwind::{self, Unwound};
+use crate::coredump::{CoreFile, MappedFile, Segment, PF_R, PF_W, PF_X};
+use crate::record::Recording;
+use nix::errno::Errno;
+use std::io;
+use std::os::unix::fs::FileExt;
 
 
 
+/// Number of x86-64 debug address registers (DR0-DR3), i.e. how many watchpoints we can have.
+const NUM_WATCH_SLOTS: usize = 4;
+const DR6: usize = 6;
+const DR7: usize = 7;
+/// Backtraces stop after this many frames, in case the stack is corrupt.
+const MAX_FRAMES: usize = 256;
+/// The `syscall` instruction.
+const SYSCALL: [u8; 2] = [0x0f, 0x05];
+
+/// A memory range being watched through one of the debug address registers, along with the last
+/// value we saw there.
+struct HardwareWatchpoint {
+    addr: usize,
+    len: usize,
+    value: u64,
+}
+
+/// A frame of the inferior's call stack. `cfa` is the canonical frame address (the caller's
+/// %rsp before the call), which is also the frame base that local variables are relative to.
+#[derive(Clone, Debug)]
+pub struct StackFrame {
+    pub rip: usize,
+    pub rbp: usize,
+    pub cfa: usize,
+    pub return_address: Option<usize>,
+    /// Where this frame saved its caller's registers, as (DWARF register number, address) pairs.
+    pub saved_registers: Vec<(usize, usize)>,
+}
+
+/// Describes a stop caused by a write to watched memory.
+pub struct WatchpointHit {
+    /// The debug register slot (0-3) that fired.
+    pub slot: usize,
+    pub old_value: u64,
+    pub new_value: u64,
+}
+
 pub enum Status {
-    /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
-    /// current instruction pointer that it is stopped at.
-    Stopped(signal::Signal, usize),
+    /// Indicates inferior stopped. Contains the signal that stopped the process, the current
+    /// instruction pointer that it is stopped at, and the watchpoint that fired, if that is why
+    /// it stopped.
+    Stopped(signal::Signal, usize, Option<WatchpointHit>),
 
     /// Indicates inferior exited normally. Contains the exit status code.
     Exited(i32),
@@ -22,6 +70,16 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior called fork() or vfork(). Contains the pid of the new process,
+    /// which is traced too.
+    Forked(Pid),
+
+    /// Indicates the inferior created a thread. Contains the thread id, which is traced too.
+    Cloned(Pid),
+
+    /// Indicates the inferior called exec() and is now running a new program.
+    Execed,
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
@@ -33,140 +91,1119 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+/// Turns off address space randomization in the child before it execs the target, as gdb does, so
+/// that a position-independent program and its libraries load at the same addresses every run and
+/// breakpoints found in one run still apply to the next.
+fn disable_randomization() -> Result<(), std::io::Error> {
+    unsafe {
+        // 0xffffffff only queries the current personality
+        let persona = libc::personality(0xffffffff);
+        if persona == -1
+            || libc::personality((persona | libc::ADDR_NO_RANDOMIZE) as libc::c_ulong) == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Names of the registers shown by `info registers`, in the order gdb lists them.
+pub const REGISTER_NAMES: [&str; 26] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+];
+
+/// Returns a reference to the general-purpose register called `name` within `regs`.
+pub fn register_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" => &mut regs.rbp,
+        "rsp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    })
+}
+
+/// Returns the breakpoint at `addr` if its 0xcc is currently planted in the inferior.
+fn installed_breakpoint(breakpoints: &HashMap<usize, Breakpoint>, addr: usize) -> Option<&Breakpoint> {
+    breakpoints.get(&addr).filter(|bp| bp.enabled)
+}
+
+/// Asks the kernel to also trace the processes and threads the inferior creates, and to report
+/// its execs as events rather than plain SIGTRAPs.
+fn set_trace_options(pid: Pid) -> Result<(), nix::Error> {
+    ptrace::setoptions(
+        pid,
+        ptrace::Options::PTRACE_O_TRACEFORK
+            | ptrace::Options::PTRACE_O_TRACEVFORK
+            | ptrace::Options::PTRACE_O_TRACECLONE
+            | ptrace::Options::PTRACE_O_TRACEEXEC,
+    )
+}
+
+/// Turns an error reading or writing /proc/<pid>/mem into the kind ptrace gives.
+fn from_io_error(err: io::Error) -> nix::Error {
+    nix::Error::Sys(Errno::from_i32(err.raw_os_error().unwrap_or(libc::EIO)))
+}
+
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+/// Returns the offset of debug register `index` within the tracee's `struct user`, as expected
+/// by PTRACE_PEEKUSER/PTRACE_POKEUSER.
+fn debug_reg_offset(index: usize) -> usize {
+    let user: libc::user = unsafe { std::mem::zeroed() };
+    let base = &user as *const _ as usize;
+    let debugreg = &user.u_debugreg as *const _ as usize;
+    debugreg - base + index * size_of::<u64>()
+}
+
+/// A thread of the inferior. ptrace controls each thread separately: each has its own registers
+/// and stops on its own.
+pub struct Thread {
+    /// Number shown to the user, counting from 1 in creation order.
+    pub id: usize,
+    pub tid: Pid,
+    /// A signal the thread received while we were stopping it, to deliver when it resumes.
+    pending_signal: Option<signal::Signal>,
+    /// False while the thread is stopped in ptrace.
+    running: bool,
+}
+
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// True if we attached to an already running process rather than spawning it, in which case
+    /// we detach from it instead of killing it when we are done.
+    attached: bool,
+    watch_slots: [Option<HardwareWatchpoint>; NUM_WATCH_SLOTS],
+    /// Live threads, in creation order. The main thread's id is the pid.
+    threads: Vec<Thread>,
+    /// The thread whose registers we access and which we single-step: the one that stopped
+    /// last, unless another was picked with `thread N`.
+    current: Pid,
+    next_thread_id: usize,
+    /// What waitpid reported for new threads and processes before the clone or fork event that
+    /// created them, oldest first. The first event of each is its initial stop.
+    early_events: Vec<WaitStatus>,
+    /// Threads passing on what the program writes, when its output is captured.
+    output_threads: Vec<JoinHandle<()>>,
+    /// Set if this is a core dump rather than a live process. Its memory and registers can be
+    /// read but not changed, and it can't run.
+    core: Option<CoreFile>,
+    /// Set while `record` is on. The current thread then runs one instruction at a time, and
+    /// each one is logged so that it can be undone.
+    record: Option<Recording>,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints: &mut HashMap<usize, Breakpoint>) -> Option<Inferior> {
+    /// an error is encountered. The process is left stopped at exec, before the dynamic linker has
+    /// run, and without breakpoints: where they go depends on where the program was loaded.
+    ///
+    /// With `capture_output`, what the program writes is passed on as events rather than going
+    /// straight to our stdout, and it gets no stdin, since ours carries requests from a front end.
+    pub fn new(target: &str, args: &Vec<String>, capture_output: bool) -> Option<Inferior> {
         let mut cmd = Command::new(target);
         cmd.args(args);
+        if capture_output {
+            cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
         unsafe {
+            cmd.pre_exec(disable_randomization);
             cmd.pre_exec(child_traceme);
         }
-        let mut inferior = Inferior { child: cmd.spawn().ok()? };
-
-        let bp = breakpoints.clone();
-        for addr in bp.keys() {
-            match inferior.write_byte(*addr, 0xcc) {
-                Ok(orig_instr) => { breakpoints.insert(*addr, Breakpoint{ addr: *addr, orig_byte: orig_instr }); },
-                Err(_) => println!("Invalid breakpoint address {:#x}", addr),
-            }
+        let mut child = cmd.spawn().ok()?;
+        let mut inferior = Inferior::traced(Pid::from_raw(child.id() as i32), false);
+        if let Some(stdout) = child.stdout.take() {
+            inferior
+                .output_threads
+                .push(output::forward_program_output(stdout, "stdout"));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            inferior
+                .output_threads
+                .push(output::forward_program_output(stderr, "stderr"));
         }
         let status = inferior.wait(None).ok()?;
 
         match status {
-            Status::Stopped(sign, _) => {
-                match sign {
-                    signal::Signal::SIGTRAP => Some(inferior),
-                    _ => None
+            Status::Stopped(signal::Signal::SIGTRAP, _, _) => {}
+            _ => return None,
+        }
+
+        set_trace_options(inferior.pid()).ok()?;
+        Some(inferior)
+    }
+
+    /// Returns an Inferior for the process `pid`, which we are already tracing, with just its
+    /// main thread.
+    fn traced(pid: Pid, attached: bool) -> Inferior {
+        Inferior {
+            pid,
+            attached,
+            watch_slots: [None, None, None, None],
+            threads: vec![Thread {
+                id: 1,
+                tid: pid,
+                pending_signal: None,
+                running: false,
+            }],
+            current: pid,
+            next_thread_id: 2,
+            early_events: Vec::new(),
+            output_threads: Vec::new(),
+            core: None,
+            record: None,
+        }
+    }
+
+    /// Returns an Inferior for looking at a core dump, with the threads saved in it.
+    pub fn from_core(core: CoreFile) -> Inferior {
+        let mut inferior = Inferior::traced(core.pid, false);
+        inferior.threads = core
+            .threads
+            .iter()
+            .enumerate()
+            .map(|(index, (tid, _))| Thread {
+                id: index + 1,
+                tid: *tid,
+                pending_signal: None,
+                running: false,
+            })
+            .collect();
+        inferior.next_thread_id = inferior.threads.len() + 1;
+        inferior.current = core.threads[0].0;
+        inferior.core = Some(core);
+        inferior
+    }
+
+    /// Returns true if this is a core dump rather than a live process.
+    pub fn is_core(&self) -> bool {
+        self.core.is_some()
+    }
+
+    /// Fails if this is a core dump, whose state can't be changed.
+    fn check_live(&self) -> Result<(), nix::Error> {
+        match self.core {
+            Some(_) => Err(nix::Error::Sys(Errno::EROFS)),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a snapshot of the stopped inferior for a core file: the registers of each thread,
+    /// starting with the current one, and every mapping of memory that can be read, with the
+    /// original bytes put back where we planted breakpoints.
+    pub fn dump_core(
+        &self,
+        breakpoints: &HashMap<usize, Breakpoint>,
+        signal: signal::Signal,
+    ) -> io::Result<CoreFile> {
+        let to_io_error = |err: nix::Error| io::Error::new(io::ErrorKind::Other, err);
+        let mut threads = Vec::new();
+        for thread in &self.threads {
+            let regs = ptrace::getregs(thread.tid).map_err(to_io_error)?;
+            if thread.tid == self.current {
+                threads.insert(0, (thread.tid, regs));
+            } else {
+                threads.push((thread.tid, regs));
+            }
+        }
+        let command = std::fs::read(format!("/proc/{}/cmdline", self.pid))?;
+        let command = String::from_utf8_lossy(&command).replace('\0', " ");
+
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.pid))?;
+        let mem = std::fs::File::open(format!("/proc/{}/mem", self.pid))?;
+        let mut files = Vec::new();
+        let mut segments = Vec::new();
+        for line in maps.lines() {
+            // e.g. "7ffff7fc3000-7ffff7fc5000 r--p 00000000 00:1e 1337   /usr/lib/ld-linux.so.2"
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (range, perms, offset) = match fields.as_slice() {
+                [range, perms, offset, ..] => (*range, *perms, *offset),
+                _ => continue,
+            };
+            let mut range = range.split('-');
+            let start = usize::from_str_radix(range.next().unwrap_or(""), 16);
+            let end = usize::from_str_radix(range.next().unwrap_or(""), 16);
+            let (start, end) = match (start, end) {
+                (Ok(start), Ok(end)) => (start, end),
+                _ => continue,
+            };
+            if let Some(path) = fields.get(5).filter(|path| path.starts_with('/')) {
+                files.push(MappedFile {
+                    start,
+                    end,
+                    offset: usize::from_str_radix(offset, 16).unwrap_or(0),
+                    path: path.to_string(),
+                });
+            }
+            let mut flags = 0;
+            for (letter, flag) in "rwx".chars().zip(&[PF_R, PF_W, PF_X]) {
+                if perms.contains(letter) {
+                    flags |= flag;
+                }
+            }
+            // Some mappings, like [vvar], can't be read even by us
+            let mut data = vec![0; end - start];
+            if flags & PF_R == 0 || mem.read_exact_at(&mut data, start as u64).is_err() {
+                data = Vec::new();
+            }
+            for bp in breakpoints.values().filter(|bp| bp.enabled) {
+                if let Some(byte) = bp.addr.checked_sub(start).and_then(|i| data.get_mut(i)) {
+                    *byte = bp.orig_byte;
                 }
-            }, 
-            Status::Exited(_) => None,
-            Status::Signaled(_) => None,
+            }
+            segments.push(Segment {
+                addr: start,
+                size: end - start,
+                flags,
+                data,
+            });
         }
+
+        Ok(CoreFile {
+            pid: self.pid,
+            threads,
+            signal: Some(signal),
+            command: command.trim_end().to_string(),
+            auxv: std::fs::read(format!("/proc/{}/auxv", self.pid))?,
+            files,
+            segments,
+        })
+    }
+
+    /// Takes control of a process this one forked, which the kernel attached to us. Waits for
+    /// the SIGSTOP it starts with. Our breakpoints are already planted in its copy of our memory,
+    /// but debug registers are not inherited, so it has no watchpoints.
+    pub fn fork_child(&mut self, pid: Pid) -> Result<Inferior, nix::Error> {
+        self.wait_initial_stop(pid)?;
+        let mut child = Inferior::traced(pid, false);
+        // Anything else the child did before we heard about it is the child's to deal with
+        let (child_events, events) = self
+            .early_events
+            .drain(..)
+            .partition(|event| event.pid() == Some(pid));
+        self.early_events = events;
+        child.early_events = child_events;
+        Ok(child)
+    }
+
+    /// Waits for a new thread or process to report the SIGSTOP it starts with, unless it already
+    /// did.
+    fn wait_initial_stop(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        match self.early_events.iter().position(|event| event.pid() == Some(tid)) {
+            Some(index) => {
+                self.early_events.remove(index);
+            }
+            None => {
+                waitpid(tid, Some(WaitPidFlag::__WALL))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts tracking a thread the inferior created, once it has reached its initial stop.
+    fn add_thread(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        self.wait_initial_stop(tid)?;
+        self.threads.push(Thread {
+            id: self.next_thread_id,
+            tid,
+            pending_signal: None,
+            running: false,
+        });
+        self.next_thread_id += 1;
+        if self.watch_slots.iter().any(|slot| slot.is_some()) {
+            self.program_debug_regs(tid)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the live threads, in creation order.
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// Makes the thread numbered `id` the current one. Returns its thread id, or None if there is
+    /// no such thread.
+    pub fn select_thread(&mut self, id: usize) -> Option<Pid> {
+        let tid = self.threads.iter().find(|thread| thread.id == id)?.tid;
+        self.current = tid;
+        Some(tid)
+    }
+
+    /// Returns the number of the current thread.
+    pub fn current_thread(&self) -> usize {
+        self.threads
+            .iter()
+            .find(|thread| thread.tid == self.current)
+            .map_or(0, |thread| thread.id)
+    }
+
+    /// Attaches to the already running process `pid` with PTRACE_ATTACH. The process is left
+    /// stopped, without our breakpoints.
+    pub fn attach(pid: Pid) -> Result<Inferior, nix::Error> {
+        ptrace::attach(pid)?;
+        let mut inferior = Inferior::traced(pid, true);
+        // PTRACE_ATTACH sends a SIGSTOP, which we must wait for before touching the process
+        inferior.wait(None)?;
+        set_trace_options(pid)?;
+        // Each thread has to be attached to separately. Threads created from now on are attached
+        // automatically, thanks to PTRACE_O_TRACECLONE.
+        if let Ok(tasks) = std::fs::read_dir(format!("/proc/{}/task", pid)) {
+            for task in tasks.filter_map(|task| task.ok()) {
+                let tid = match task.file_name().to_str().and_then(|name| name.parse().ok()) {
+                    Some(tid) if tid != pid.as_raw() => Pid::from_raw(tid),
+                    _ => continue,
+                };
+                if ptrace::attach(tid).is_ok() {
+                    inferior.add_thread(tid)?;
+                    set_trace_options(tid)?;
+                }
+            }
+        }
+        Ok(inferior)
+    }
+
+    /// Removes our breakpoints and watchpoints from the process and lets it run on untraced.
+    pub fn detach(mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        self.check_live()?;
+        for bp in breakpoints.values().filter(|bp| bp.enabled) {
+            self.write_byte(bp.addr, bp.orig_byte)?;
+        }
+        for slot in 0..NUM_WATCH_SLOTS {
+            if self.watch_slots[slot].is_some() {
+                self.remove_watchpoint(slot)?;
+            }
+        }
+        for thread in &self.threads {
+            ptrace::detach(thread.tid, thread.pending_signal)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if we attached to this process rather than spawning it.
+    pub fn is_attached(&self) -> bool {
+        self.attached
     }
 
     pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        self.check_live()?;
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
+        let word = ptrace::read(self.tid(), aligned_addr as ptrace::AddressType)? as u64;
         let orig_byte = (word >> 8 * byte_offset) & 0xff;
         let masked_word = word & !(0xff << 8 * byte_offset);
         let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
         ptrace::write(
-            self.pid(),
+            self.tid(),
             aligned_addr as ptrace::AddressType,
             updated_word as *mut std::ffi::c_void,
         )?;
         Ok(orig_byte as u8)
     }
 
+    /// Writes `bytes` to the inferior's memory at `addr`. ptrace transfers whole words, so the
+    /// words at either end of the range are read first and only the requested bytes are changed.
+    /// Writing over an enabled breakpoint updates the byte that will be restored when it is
+    /// removed, leaving the 0xcc in place.
+    pub fn write_memory(
+        &mut self,
+        addr: usize,
+        bytes: &[u8],
+        breakpoints: &mut HashMap<usize, Breakpoint>,
+    ) -> Result<(), nix::Error> {
+        self.check_live()?;
+        let end = addr + bytes.len();
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < end {
+            let word = ptrace::read(self.tid(), word_addr as ptrace::AddressType)? as u64;
+            let mut word_bytes = word.to_le_bytes();
+            for (i, byte) in word_bytes.iter_mut().enumerate() {
+                let byte_addr = word_addr + i;
+                if byte_addr < addr || byte_addr >= end {
+                    continue;
+                }
+                let val = bytes[byte_addr - addr];
+                match breakpoints.get_mut(&byte_addr).filter(|bp| bp.enabled) {
+                    Some(bp) => bp.orig_byte = val,
+                    None => *byte = val,
+                }
+            }
+            ptrace::write(
+                self.tid(),
+                word_addr as ptrace::AddressType,
+                u64::from_le_bytes(word_bytes) as *mut std::ffi::c_void,
+            )?;
+            word_addr += size_of::<usize>();
+        }
+        Ok(())
+    }
+
+    /// Returns the entry of the process's auxiliary vector with type `key`, e.g. `AT_ENTRY` for the
+    /// runtime address of the program's entry point.
+    pub fn auxv_entry(&self, key: libc::c_ulong) -> Option<usize> {
+        let auxv = match &self.core {
+            Some(core) => core.auxv.clone(),
+            None => std::fs::read(format!("/proc/{}/auxv", self.pid)).ok()?,
+        };
+        auxv.chunks_exact(2 * size_of::<u64>()).find_map(|entry| {
+            let (entry_key, value) = entry.split_at(size_of::<u64>());
+            if u64::from_le_bytes(entry_key.try_into().unwrap()) == key as u64 {
+                Some(u64::from_le_bytes(value.try_into().unwrap()) as usize)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the path of the file mapped at `addr`, according to /proc/<pid>/maps.
+    pub fn mapped_file(&self, addr: usize) -> Option<String> {
+        if let Some(core) = &self.core {
+            return core
+                .files
+                .iter()
+                .find(|file| addr >= file.start && addr < file.end)
+                .map(|file| file.path.clone());
+        }
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.pid)).ok()?;
+        maps.lines().find_map(|line| {
+            // e.g. "7ffff7fc3000-7ffff7fc5000 r--p 00000000 00:1e 1337   /usr/lib/ld-linux.so.2"
+            let mut fields = line.split_whitespace();
+            let mut range = fields.next()?.split('-');
+            let start = usize::from_str_radix(range.next()?, 16).ok()?;
+            let end = usize::from_str_radix(range.next()?, 16).ok()?;
+            let path = fields.nth(4)?;
+            if addr >= start && addr < end && path.starts_with('/') {
+                Some(path.to_string())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the faulting address and `si_code` from the siginfo of the signal the inferior is
+    /// stopped with, e.g. the address a SIGSEGV tried to access.
+    pub fn fault_info(&self) -> Result<(usize, i32), nix::Error> {
+        let info = ptrace::getsiginfo(self.tid())?;
+        Ok((unsafe { info.si_addr() } as usize, info.si_code))
+    }
+
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Returns the thread id of the current thread.
+    pub fn tid(&self) -> Pid {
+        self.current
+    }
+
+    /// Starts recording every instruction the current thread runs.
+    pub fn start_recording(&mut self) -> Result<(), nix::Error> {
+        self.check_live()?;
+        self.record = Some(Recording::start(self.pid).map_err(from_io_error)?);
+        Ok(())
+    }
+
+    /// Stops recording and throws away the log.
+    pub fn stop_recording(&mut self) {
+        self.record = None;
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// Returns the log of instructions run while recording, if `record` is on.
+    pub fn recording(&self) -> Option<&Recording> {
+        self.record.as_ref()
+    }
+
+    /// Undoes the last instruction recorded, putting back the registers and memory from before
+    /// it ran. Returns false if there is nothing left to undo.
+    pub fn reverse_step_instruction(&mut self) -> Result<bool, nix::Error> {
+        let record = match self.record.as_mut() {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        match record.undo().map_err(from_io_error)? {
+            Some(regs) => {
+                ptrace::setregs(self.tid(), regs)?;
+                Ok(true)
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+            None => Ok(false),
+        }
     }
 
-    pub fn con(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        let rip = regs.rip as usize;
+    /// Calls waitpid on the current thread and returns a Status to indicate the state of the
+    /// process after the waitpid call. The other threads are left alone.
+    pub fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        let flags = options.unwrap_or(WaitPidFlag::empty()) | WaitPidFlag::__WALL;
+        loop {
+            let wait_status = waitpid(self.tid(), Some(flags))?;
+            if let Some(status) = self.thread_status(wait_status)? {
+                return Ok(status);
+            }
+            // If the thread we were waiting for has exited, carry on with the main thread, which
+            // is still around until the whole process exits.
+            if !self.threads.iter().any(|thread| thread.tid == self.current) {
+                self.current = self.pid;
+            }
+        }
+    }
 
-        if let Some(bp) = breakpoints.get(&(rip - 1)) {
-            self.write_byte(bp.addr, bp.orig_byte).ok();
+    /// Waits for any thread to stop, then stops all the others, so that the whole process is
+    /// stopped while we look at it.
+    fn wait_all(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        loop {
+            // Events of threads we have heard about since they happened come first
+            let queued = self.early_events.iter().position(|event| {
+                let tid = event.pid();
+                self.threads.iter().any(|thread| Some(thread.tid) == tid)
+            });
+            let wait_status = match queued {
+                Some(index) => self.early_events.remove(index),
+                None => waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL))?,
+            };
+            let tid = match wait_status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            if !self.threads.iter().any(|thread| thread.tid == tid) {
+                // A new thread or process that we haven't heard about yet
+                self.early_events.push(wait_status);
+                continue;
+            }
+            if let Some(status) = self.thread_status(wait_status)? {
+                match status {
+                    Status::Exited(_) | Status::Signaled(_) => {}
+                    // Every thread but the one calling exec is gone
+                    Status::Execed => {}
+                    _ => self.stop_running_threads(breakpoints)?,
+                }
+                return Ok(status);
+            }
+        }
+    }
 
-            regs.rip = (rip - 1) as u64;
-            ptrace::setregs(self.pid(), regs).ok();
+    /// Turns what waitpid said about one of our threads into a Status, making that thread the
+    /// current one. Returns None if a thread other than the main one exited, which only removes
+    /// it from our list, or for a status we never ask for, which is ignored.
+    fn thread_status(&mut self, wait_status: WaitStatus) -> Result<Option<Status>, nix::Error> {
+        Ok(Some(match wait_status {
+            WaitStatus::Exited(tid, exit_code) => {
+                self.threads.retain(|thread| thread.tid != tid);
+                if tid != self.pid {
+                    return Ok(None);
+                }
+                Status::Exited(exit_code)
+            }
+            WaitStatus::Signaled(tid, signal, _core_dumped) => {
+                self.threads.retain(|thread| thread.tid != tid);
+                if tid != self.pid {
+                    return Ok(None);
+                }
+                Status::Signaled(signal)
+            }
+            WaitStatus::Stopped(tid, signal) => {
+                self.set_current_stopped(tid);
+                let regs = ptrace::getregs(self.tid())?;
+                let hit = if signal == signal::Signal::SIGTRAP {
+                    self.check_watchpoints()?
+                } else {
+                    None
+                };
+                Status::Stopped(signal, regs.rip as usize, hit)
+            }
+            WaitStatus::PtraceEvent(tid, _signal, event) => {
+                self.set_current_stopped(tid);
+                match event {
+                    libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK => {
+                        Status::Forked(Pid::from_raw(ptrace::getevent(self.tid())? as i32))
+                    }
+                    libc::PTRACE_EVENT_CLONE => {
+                        let new_tid = Pid::from_raw(ptrace::getevent(self.tid())? as i32);
+                        self.add_thread(new_tid)?;
+                        Status::Cloned(new_tid)
+                    }
+                    libc::PTRACE_EVENT_EXEC => {
+                        // The thread that called exec has taken over the main thread's id
+                        let pid = self.pid;
+                        self.threads.retain(|thread| thread.tid == pid);
+                        self.current = pid;
+                        Status::Execed
+                    }
+                    _ => {
+                        let regs = ptrace::getregs(self.tid())?;
+                        Status::Stopped(signal::Signal::SIGTRAP, regs.rip as usize, None)
+                    }
+                }
+            }
+            // Syscall stops, continues and "still alive" only come with options we don't use
+            _ => return Ok(None),
+        }))
+    }
 
-            ptrace::step(self.pid(), None).ok();
+    /// Records that thread `tid` has stopped, and makes it the current thread.
+    fn set_current_stopped(&mut self, tid: Pid) {
+        if let Some(thread) = self.threads.iter_mut().find(|thread| thread.tid == tid) {
+            thread.running = false;
+        }
+        self.current = tid;
+    }
 
-            match self.wait(None).ok().unwrap() {
-                Status::Exited(exit_code) => return Ok(Status::Exited(exit_code)),
-                Status::Signaled(signal) => return Ok(Status::Signaled(signal)),
-                Status::Stopped(_, _) => {
-                    self.write_byte(rip - 1, 0xcc).ok();
+    /// Stops every thread that is still running with SIGSTOP. A thread may have stopped for
+    /// another reason before the SIGSTOP arrived: a signal is kept to be delivered when the
+    /// thread resumes, and a thread that hit a breakpoint is backed up so that it hits it again.
+    fn stop_running_threads(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        let running: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|thread| thread.running)
+            .map(|thread| thread.tid)
+            .collect();
+        for tid in &running {
+            let res = unsafe {
+                libc::syscall(libc::SYS_tgkill, self.pid.as_raw(), tid.as_raw(), libc::SIGSTOP)
+            };
+            nix::errno::Errno::result(res)?;
+        }
+        for tid in running {
+            loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => {
+                        if let Some(thread) = self.threads.iter_mut().find(|thread| thread.tid == tid) {
+                            thread.running = false;
+                        }
+                        break;
+                    }
+                    WaitStatus::Stopped(_, signal::Signal::SIGTRAP) => {
+                        let mut regs = ptrace::getregs(tid)?;
+                        if installed_breakpoint(breakpoints, regs.rip as usize - 1).is_some() {
+                            regs.rip -= 1;
+                            ptrace::setregs(tid, regs)?;
+                        }
+                    }
+                    WaitStatus::Stopped(_, signal) => {
+                        if let Some(thread) = self.threads.iter_mut().find(|thread| thread.tid == tid) {
+                            thread.pending_signal = Some(signal);
+                        }
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                        let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                        self.add_thread(new_tid)?;
+                    }
+                    WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
+                        self.threads.retain(|thread| thread.tid != tid);
+                        break;
+                    }
+                    _ => {}
                 }
+                // Let the SIGSTOP we sent arrive
+                ptrace::cont(tid, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the general-purpose registers of the stopped inferior.
+    pub fn getregs(&self) -> Result<user_regs_struct, nix::Error> {
+        match &self.core {
+            Some(core) => core.registers(self.tid()).ok_or(nix::Error::Sys(Errno::ESRCH)),
+            None => ptrace::getregs(self.tid()),
+        }
+    }
+
+    /// Replaces the general-purpose registers of the stopped inferior.
+    pub fn setregs(&self, regs: user_regs_struct) -> Result<(), nix::Error> {
+        self.check_live()?;
+        ptrace::setregs(self.tid(), regs)
+    }
+
+    /// Returns the value of the general-purpose register called `name` (e.g. "rax"), or None if
+    /// there is no such register.
+    pub fn get_register(&self, name: &str) -> Result<Option<u64>, nix::Error> {
+        let mut regs = self.getregs()?;
+        Ok(register_mut(&mut regs, name).map(|reg| *reg))
+    }
+
+    /// Sets the general-purpose register called `name`. Returns false if there is no such
+    /// register.
+    pub fn set_register(&self, name: &str, value: u64) -> Result<bool, nix::Error> {
+        self.check_live()?;
+        let mut regs = ptrace::getregs(self.tid())?;
+        match register_mut(&mut regs, name) {
+            Some(reg) => *reg = value,
+            None => return Ok(false),
+        }
+        ptrace::setregs(self.tid(), regs)?;
+        Ok(true)
+    }
+
+    /// Reads `len` bytes of inferior memory starting at `addr`. Any 0xcc bytes we planted for
+    /// breakpoints are replaced with the original instruction bytes.
+    pub fn read_bytes(&self, addr: usize, len: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Vec<u8>, nix::Error> {
+        if let Some(core) = &self.core {
+            return core.read_memory(addr, len).ok_or(nix::Error::Sys(Errno::EIO));
+        }
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < addr + len {
+            let word = ptrace::read(self.tid(), word_addr as ptrace::AddressType)? as u64;
+            bytes.extend_from_slice(&word.to_le_bytes());
+            word_addr += size_of::<usize>();
+        }
+        let start = addr - align_addr_to_word(addr);
+        let mut bytes = bytes[start..start + len].to_vec();
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            if let Some(bp) = installed_breakpoint(breakpoints, addr + i) {
+                *byte = bp.orig_byte;
             }
         }
-        match ptrace::cont(self.pid(), None) {
-            Ok(_) => self.wait(None),
-            Err(err) => Err(err)
+        Ok(bytes)
+    }
+
+    /// Calls waitpid after the inferior's threads have been resumed with PTRACE_CONT. If the inferior stopped
+    /// because it executed one of our 0xcc bytes, %rip is rewound to point at the breakpoint
+    /// address, so that the reported location is the instruction that was replaced.
+    fn wait_for_breakpoint(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        match self.wait_all(breakpoints)? {
+            Status::Stopped(signal::Signal::SIGTRAP, rip, None) if installed_breakpoint(breakpoints, rip - 1).is_some() => {
+                let mut regs = ptrace::getregs(self.tid())?;
+                regs.rip = (rip - 1) as u64;
+                ptrace::setregs(self.tid(), regs)?;
+                Ok(Status::Stopped(signal::Signal::SIGTRAP, rip - 1, None))
+            }
+            status => Ok(status),
+        }
+    }
+
+    /// If the inferior is stopped at a breakpoint, temporarily restores the original instruction,
+    /// executes it with a single step and re-arms the breakpoint. Returns the status after the
+    /// step, or None if there was no breakpoint to step over.
+    fn step_over_breakpoint(
+        &mut self,
+        breakpoints: &HashMap<usize, Breakpoint>,
+        signal: Option<signal::Signal>,
+    ) -> Result<Option<Status>, nix::Error> {
+        let rip = ptrace::getregs(self.tid())?.rip as usize;
+
+        if let Some(bp) = installed_breakpoint(breakpoints, rip) {
+            self.write_byte(bp.addr, bp.orig_byte)?;
+            ptrace::step(self.tid(), signal)?;
+
+            let status = self.wait(None)?;
+            if let Status::Stopped(_, _, _) = status {
+                self.write_byte(bp.addr, 0xcc)?;
+            }
+            return Ok(Some(status));
         }
+        Ok(None)
     }
 
-    pub fn kill(&mut self) -> Result<std::process::ExitStatus, std::io::Error> {
-        match self.child.kill() {
-            Ok(_) => {
-                let status = self.child.wait()?;
-                Ok(status)
-            },
-            Err(err) => Err(err)
+    /// Resumes all threads until one of them stops, or the inferior exits. `signal` is delivered
+    /// to the current thread as it resumes, e.g. to pass on the signal it last stopped with.
+    /// While recording, only the current thread runs, one instruction at a time.
+    pub fn con(
+        &mut self,
+        breakpoints: &HashMap<usize, Breakpoint>,
+        signal: Option<signal::Signal>,
+    ) -> Result<Status, nix::Error> {
+        if self.record.is_some() {
+            return self.step_until_breakpoint(breakpoints, signal);
+        }
+        let signal = match self.step_over_breakpoint(breakpoints, signal)? {
+            None => signal,
+            // The signal, if any, was delivered by the step
+            Some(Status::Stopped(signal::Signal::SIGTRAP, _, None)) => None,
+            Some(status) => return Ok(status),
+        };
+        for thread in self.threads.iter_mut() {
+            let signal = if thread.tid == self.current {
+                signal
+            } else {
+                thread.pending_signal.take()
+            };
+            ptrace::cont(thread.tid, signal)?;
+            thread.running = true;
         }
+        self.wait_for_breakpoint(breakpoints)
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid())?;
-        
-        let mut rip = regs.rip as usize;
-        let mut rbp = regs.rbp as usize;
+    /// Executes a single machine instruction in the current thread. The other threads stay
+    /// stopped.
+    pub fn step_instruction(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        self.step(breakpoints, None)
+    }
 
+    /// Executes a single machine instruction in the current thread, delivering `signal` as it
+    /// resumes, and logs it if we are recording.
+    fn step(
+        &mut self,
+        breakpoints: &HashMap<usize, Breakpoint>,
+        signal: Option<signal::Signal>,
+    ) -> Result<Status, nix::Error> {
+        let before = match self.record {
+            Some(_) => {
+                let regs = ptrace::getregs(self.tid())?;
+                let syscall = self.read_bytes(regs.rip as usize, SYSCALL.len(), breakpoints)? == SYSCALL;
+                Some((regs, syscall))
+            }
+            None => None,
+        };
+        let status = match self.step_over_breakpoint(breakpoints, signal)? {
+            Some(status) => status,
+            None => {
+                ptrace::step(self.tid(), signal)?;
+                self.wait(None)?
+            }
+        };
+        // Any other stop means the instruction did not run, e.g. because it faulted
+        if let (Some(record), Some((regs, syscall)), Status::Stopped(signal::Signal::SIGTRAP, _, _)) =
+            (self.record.as_mut(), before, &status)
+        {
+            record.log_instruction(regs, syscall).map_err(from_io_error)?;
+        }
+        Ok(status)
+    }
+
+    /// Runs the current thread one instruction at a time, so that each one is recorded, until it
+    /// reaches a breakpoint or stops for any other reason.
+    fn step_until_breakpoint(
+        &mut self,
+        breakpoints: &HashMap<usize, Breakpoint>,
+        mut signal: Option<signal::Signal>,
+    ) -> Result<Status, nix::Error> {
         loop {
-            match (debug_data.get_line_from_addr(rip), debug_data.get_function_from_addr(rip)) {
-                (Some(line_info), Some(function)) => {
-                    println!("{} ({}:{})", function, line_info.file, line_info.number);
-                    if function.eq("main") {
-                        break;
-                    }
-                    rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)? as usize;
-                    rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;   
-                },
+            match self.step(breakpoints, signal.take())? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip, None)
+                    if installed_breakpoint(breakpoints, rip).is_none() => {}
+                status => return Ok(status),
+            }
+        }
+    }
 
-                (_, _) => {
-                    println!("%rip register: {:#x} \n%rbp register: {:#x}", rip, rbp);
-                    break;
-                }
+    /// Programs a free debug register to stop the inferior after it writes to the `len` bytes at
+    /// `addr`. `len` must be 1, 2, 4 or 8 and `addr` aligned to it. Returns the slot used, or None
+    /// if all four debug registers are taken.
+    pub fn set_watchpoint(&mut self, addr: usize, len: usize) -> Result<Option<usize>, nix::Error> {
+        let slot = match self.watch_slots.iter().position(|slot| slot.is_none()) {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        let value = self.read_watched_value(addr, len)?;
+        self.watch_slots[slot] = Some(HardwareWatchpoint { addr, len, value });
+        for thread in &self.threads {
+            self.program_debug_regs(thread.tid)?;
+        }
+        Ok(Some(slot))
+    }
+
+    /// Frees the debug register used by a watchpoint.
+    pub fn remove_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        self.watch_slots[slot] = None;
+        for thread in &self.threads {
+            self.program_debug_regs(thread.tid)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the occupied slots into the debug registers of thread `tid`. Every thread has its
+    /// own debug registers, so a watchpoint must be set in all of them to catch every write.
+    fn program_debug_regs(&self, tid: Pid) -> Result<(), nix::Error> {
+        // Disable everything first, since the kernel checks each address against DR7 as it is
+        // written
+        self.poke_debug_reg(tid, DR7, 0)?;
+        for (i, slot) in self.watch_slots.iter().enumerate() {
+            self.poke_debug_reg(tid, i, slot.as_ref().map_or(0, |wp| wp.addr as u64))?;
+        }
+        self.poke_debug_reg(tid, DR7, self.dr7())
+    }
 
+    /// Computes the DR7 value that enables exactly the occupied slots as write watchpoints.
+    fn dr7(&self) -> u64 {
+        let mut dr7: u64 = 0;
+        for (i, slot) in self.watch_slots.iter().enumerate() {
+            if let Some(wp) = slot {
+                let len_bits: u64 = match wp.len {
+                    1 => 0b00,
+                    2 => 0b01,
+                    8 => 0b10,
+                    _ => 0b11,
+                };
+                // Local enable bit, then the R/W bits (01 = break on data writes) and LEN bits
+                dr7 |= 1 << (2 * i);
+                dr7 |= 0b01 << (16 + 4 * i);
+                dr7 |= len_bits << (18 + 4 * i);
             }
         }
+        dr7
+    }
+
+    /// Checks DR6 after a SIGTRAP to see whether one of our watchpoints fired. Clears DR6 so
+    /// that the next stop starts from a clean slate.
+    fn check_watchpoints(&mut self) -> Result<Option<WatchpointHit>, nix::Error> {
+        if self.watch_slots.iter().all(|slot| slot.is_none()) {
+            return Ok(None);
+        }
+        let dr6 = self.peek_debug_reg(self.tid(), DR6)?;
+        self.poke_debug_reg(self.tid(), DR6, 0)?;
+
+        for slot in 0..NUM_WATCH_SLOTS {
+            if dr6 & (1 << slot) == 0 {
+                continue;
+            }
+            if let Some(wp) = &self.watch_slots[slot] {
+                let (addr, len, old_value) = (wp.addr, wp.len, wp.value);
+                let new_value = self.read_watched_value(addr, len)?;
+                self.watch_slots[slot].as_mut().unwrap().value = new_value;
+                return Ok(Some(WatchpointHit {
+                    slot,
+                    old_value,
+                    new_value,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_watched_value(&self, addr: usize, len: usize) -> Result<u64, nix::Error> {
+        let bytes = self.read_bytes(addr, len, &HashMap::new())?;
+        let mut buf = [0u8; 8];
+        buf[..len].copy_from_slice(&bytes);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn peek_debug_reg(&self, tid: Pid, index: usize) -> Result<u64, nix::Error> {
+        // PEEKUSER returns the data itself, so -1 is only an error if errno was set
+        let value = unsafe {
+            nix::errno::Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                tid.as_raw(),
+                debug_reg_offset(index),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if value == -1 && nix::errno::errno() != 0 {
+            return Err(nix::Error::last());
+        }
+        Ok(value as u64)
+    }
+
+    fn poke_debug_reg(&self, tid: Pid, index: usize, value: u64) -> Result<(), nix::Error> {
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEUSER,
+                tid.as_raw(),
+                debug_reg_offset(index),
+                value as usize,
+            )
+        };
+        nix::errno::Errno::result(res).map(drop)
+    }
+
+    /// Waits until everything the program wrote has been passed on, once it has exited. A
+    /// process it forked may keep its output open for longer.
+    pub fn flush_output(&mut self) {
+        for thread in self.output_threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+
+    pub fn kill(&mut self) -> Result<(), nix::Error> {
+        // There is no process behind a core dump, and its pid may well belong to another one by
+        // now
+        if self.is_core() {
+            return Ok(());
+        }
+        signal::kill(self.pid(), signal::Signal::SIGKILL)?;
+        // Collect every thread's exit, so that none is left behind as a zombie
+        for thread in &self.threads {
+            if thread.tid != self.pid {
+                let _ = waitpid(thread.tid, Some(WaitPidFlag::__WALL));
+            }
+        }
+        waitpid(self.pid(), None)?;
         Ok(())
     }
+
+    /// Walks the call stack from the innermost frame out to main, using the call frame
+    /// information where there is some and following saved %rbp links where there isn't.
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<Vec<StackFrame>, nix::Error> {
+        let pid = self.tid();
+        let mut read_word = |addr: usize| match &self.core {
+            Some(core) => core
+                .read_memory(addr, size_of::<u64>())
+                .map(|bytes| u64::from_le_bytes(bytes.as_slice().try_into().unwrap())),
+            None => ptrace::read(pid, addr as ptrace::AddressType).ok().map(|word| word as u64),
+        };
+
+        let mut regs = unwind::registers_from(&self.getregs()?);
+        let mut frames: Vec<StackFrame> = Vec::new();
+        while frames.len() < MAX_FRAMES {
+            let rip = match regs[unwind::RIP] {
+                Some(rip) if rip != 0 => rip as usize,
+                _ => break,
+            };
+            // A return address may be just past the end of the calling function, so look up the
+            // call instruction instead
+            let lookup_addr = if frames.is_empty() { rip } else { rip - 1 };
+            let rbp = regs[unwind::RBP].unwrap_or(0) as usize;
+            let unwound = match debug_data.unwind_frame(&regs, lookup_addr, &mut read_word) {
+                Some(unwound) => unwound,
+                None if rbp != 0 => {
+                    let mut caller = [None; unwind::NUM_REGISTERS];
+                    caller[unwind::RBP] = read_word(rbp);
+                    caller[unwind::RIP] = read_word(rbp + 8);
+                    caller[unwind::RSP] = Some(rbp as u64 + 16);
+                    Unwound {
+                        cfa: rbp + 16,
+                        caller,
+                        saved: vec![(unwind::RBP, rbp), (unwind::RIP, rbp + 8)],
+                    }
+                }
+                None => Unwound {
+                    cfa: rbp + 16,
+                    caller: [None; unwind::NUM_REGISTERS],
+                    saved: Vec::new(),
+                },
+            };
+            // The stack grows down, so callers' frames must be at higher addresses
+            if frames.last().map_or(false, |last| unwound.cfa <= last.cfa) {
+                break;
+            }
+            frames.push(StackFrame {
+                rip,
+                rbp,
+                cfa: unwound.cfa,
+                return_address: unwound.caller[unwind::RIP].map(|addr| addr as usize),
+                saved_registers: unwound.saved,
+            });
+            if debug_data.get_function_from_addr(lookup_addr).as_deref() == Some("main") {
+                break;
+            }
+            regs = unwound.caller;
+        }
+        Ok(frames)
+    }
 }
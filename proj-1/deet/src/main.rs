@@ -1,24 +1,154 @@
+#[macro_use]
+mod output;
+
+mod completion;
+mod condition;
+mod coredump;
+mod dap;
 mod debugger;
 mod debugger_command;
+mod disassembler;
 mod inferior;
 mod dwarf_data;
+mod expression;
+mod gdbserver;
 mod gimli_wrapper;
+mod json;
+mod record;
+mod signals;
+mod source;
+mod unwind;
 
 use crate::debugger::Debugger;
+use crate::output::Mode;
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
+use std::fs;
+use std::path::Path;
+
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [--interpreter=json] [-x <script>]... [--batch] <target program> [--core <file>]", program);
+    println!("       {} [--interpreter=json] [-x <script>]... [--batch] --pid <pid>", program);
+    println!("       {} --gdbserver [host]:<port> <target program> [args]...", program);
+    println!("       {} --dap", program);
+    std::process::exit(1);
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    let mut target = None;
+    let mut pid = None;
+    let mut scripts = Vec::new();
+    let mut batch = false;
+    let mut gdbserver = None;
+    let mut dap = false;
+    let mut core = None;
+    let mut program_args = Vec::new();
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-x" => match rest.next() {
+                Some(script) => scripts.push(script.clone()),
+                None => usage(&args[0]),
+            },
+            "--batch" | "-batch" => batch = true,
+            "--interpreter=json" => output::set_mode(Mode::Json),
+            _ if arg.starts_with("--interpreter=") => {
+                println!("Interpreter `{}' unrecognized", &arg["--interpreter=".len()..]);
+                std::process::exit(1);
+            }
+            "--dap" => dap = true,
+            "--core" => match rest.next() {
+                Some(file) => core = Some(file.clone()),
+                None => usage(&args[0]),
+            },
+            "--gdbserver" => match rest.next() {
+                Some(address) => gdbserver = Some(address.clone()),
+                None => usage(&args[0]),
+            },
+            "--pid" => {
+                let arg = match rest.next() {
+                    Some(arg) => arg,
+                    None => usage(&args[0]),
+                };
+                match arg.parse::<i32>() {
+                    Ok(value) => pid = Some(value),
+                    Err(_) => {
+                        println!("Invalid pid {}", arg);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ if !arg.starts_with('-') && target.is_none() => {
+                target = Some(arg.clone());
+                // As with gdbserver, whatever follows the program is passed to it
+                if gdbserver.is_some() {
+                    program_args = rest.by_ref().cloned().collect();
+                }
+            }
+            _ => usage(&args[0]),
+        }
     }
-    let target = &args[1];
-
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    // The program to debug comes with the client's launch request
+    if dap {
+        if target.is_some() || pid.is_some() || gdbserver.is_some() || core.is_some() {
+            usage(&args[0]);
+        }
+        output::set_mode(Mode::Dap);
+        if let Err(err) = dap::serve() {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // A core dump is of the program we are given, not of a running process
+    if pid.is_some() && core.is_some() {
+        usage(&args[0]);
+    }
+    let target = match (target, pid) {
+        (Some(target), None) => target,
+        // Load debugging symbols from the executable the process is running
+        (None, Some(pid)) => match fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(err) => {
+                println!("Can not find the executable of process {}: {}", pid, err);
+                std::process::exit(1);
+            }
+        },
+        _ => usage(&args[0]),
+    };
+
+    if let Some(address) = gdbserver {
+        if pid.is_some() || core.is_some() {
+            usage(&args[0]);
+        }
+        if let Err(err) = gdbserver::serve(&target, &program_args, &address) {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut debugger = Debugger::new(&target);
+    if let Some(pid) = pid {
+        debugger.attach(pid);
+    }
+    if let Some(core) = core {
+        debugger.load_core(&core);
+    }
+    // ~/.deetinit runs first, then the scripts given with -x, in order
+    if let Ok(home) = env::var("HOME") {
+        let init = format!("{}/.deetinit", home);
+        if Path::new(&init).exists() {
+            scripts.insert(0, init);
+        }
+    }
+    debugger.source(&scripts);
+    debugger.set_batch(batch);
+    debugger.run();
 }
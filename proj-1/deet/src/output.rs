@@ -0,0 +1,273 @@
+//! Where the debugger's output goes. Normally it is text for the user. With
+//! `--interpreter=json`, every line of text becomes a "console" event and the debugger also
+//! reports what happens as structured events, one JSON object per line. With `--dap`, events are
+//! handed to the debug adapter, which passes them on as Debug Adapter Protocol messages.
+
+use crate::dap;
+use crate::json::Json;
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Text,
+    Json,
+    Dap,
+}
+
+static MODE: AtomicUsize = AtomicUsize::new(Mode::Text as usize);
+
+thread_local! {
+    /// Events collected by `capture` instead of being sent on.
+    static CAPTURED: RefCell<Option<Vec<Event>>> = RefCell::new(None);
+}
+
+/// Prints a line of text for the user, like `println!`.
+macro_rules! console {
+    () => {
+        $crate::output::console(String::new())
+    };
+    ($($arg:tt)*) => {
+        $crate::output::console(format!($($arg)*))
+    };
+}
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as usize, Ordering::SeqCst);
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::SeqCst) {
+        m if m == Mode::Json as usize => Mode::Json,
+        m if m == Mode::Dap as usize => Mode::Dap,
+        _ => Mode::Text,
+    }
+}
+
+pub fn console(text: String) {
+    match mode() {
+        Mode::Text => println!("{}", text),
+        Mode::Json | Mode::Dap => emit(Event::Console(text)),
+    }
+}
+
+/// A frame in a `Backtrace` event.
+pub struct Frame {
+    pub level: usize,
+    pub addr: usize,
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+}
+
+/// Something a front end may want to know about, beyond the text it shows the user.
+pub enum Event {
+    Console(String),
+    /// The inferior stopped. `reason` is "breakpoint-hit", "watchpoint-trigger",
+    /// "signal-received" or "end-stepping-range".
+    Stopped {
+        reason: &'static str,
+        signal: String,
+        thread: usize,
+        rip: usize,
+        breakpoint: Option<usize>,
+        function: Option<String>,
+        file: Option<String>,
+        line: Option<usize>,
+    },
+    /// The inferior exited with `code`, or was killed by `signal`.
+    Exited {
+        code: Option<i32>,
+        signal: Option<String>,
+    },
+    BreakpointCreated {
+        id: usize,
+        /// None while the breakpoint is pending.
+        addr: Option<usize>,
+        location: String,
+        file: Option<String>,
+        line: Option<usize>,
+    },
+    Backtrace(Vec<Frame>),
+    /// The threads of the inferior, as `(id, tid)`, and the id of the current one.
+    Threads {
+        threads: Vec<(usize, i32)>,
+        current: usize,
+    },
+    /// The value of a variable or expression, as shown by `print` or `info locals`.
+    Value {
+        expression: String,
+        value: String,
+    },
+    /// Text the inferior wrote to "stdout" or "stderr".
+    ProgramOutput {
+        stream: &'static str,
+        text: String,
+    },
+    /// A request has been carried out. `id` is the one the request came with.
+    Done {
+        id: Json,
+    },
+    /// A request could not be understood.
+    Error {
+        id: Json,
+        message: String,
+    },
+}
+
+fn hex(addr: usize) -> Json {
+    Json::from(format!("{:#x}", addr))
+}
+
+impl Event {
+    pub fn to_json(&self) -> Json {
+        match self {
+            Event::Console(text) => Json::object(vec![
+                ("event", "console".into()),
+                ("text", text.as_str().into()),
+            ]),
+            Event::Stopped {
+                reason,
+                signal,
+                thread,
+                rip,
+                breakpoint,
+                function,
+                file,
+                line,
+            } => Json::object(vec![
+                ("event", "stopped".into()),
+                ("reason", (*reason).into()),
+                ("signal", signal.as_str().into()),
+                ("thread", (*thread).into()),
+                ("rip", hex(*rip)),
+                ("breakpoint", (*breakpoint).into()),
+                ("function", function.clone().into()),
+                ("file", file.clone().into()),
+                ("line", (*line).into()),
+            ]),
+            Event::Exited { code, signal } => Json::object(vec![
+                ("event", "exited".into()),
+                ("code", (*code).into()),
+                ("signal", signal.clone().into()),
+            ]),
+            Event::BreakpointCreated {
+                id,
+                addr,
+                location,
+                file,
+                line,
+            } => Json::object(vec![
+                ("event", "breakpoint-created".into()),
+                ("id", (*id).into()),
+                ("addr", addr.map_or(Json::Null, hex)),
+                ("location", location.as_str().into()),
+                ("file", file.clone().into()),
+                ("line", (*line).into()),
+            ]),
+            Event::Backtrace(frames) => Json::object(vec![
+                ("event", "backtrace".into()),
+                (
+                    "frames",
+                    Json::Array(
+                        frames
+                            .iter()
+                            .map(|frame| {
+                                Json::object(vec![
+                                    ("level", frame.level.into()),
+                                    ("addr", hex(frame.addr)),
+                                    ("function", frame.function.clone().into()),
+                                    ("file", frame.file.clone().into()),
+                                    ("line", frame.line.into()),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+            ]),
+            Event::Threads { threads, current } => Json::object(vec![
+                ("event", "threads".into()),
+                ("current", (*current).into()),
+                (
+                    "threads",
+                    Json::Array(
+                        threads
+                            .iter()
+                            .map(|(id, tid)| {
+                                Json::object(vec![("id", (*id).into()), ("tid", (*tid).into())])
+                            })
+                            .collect(),
+                    ),
+                ),
+            ]),
+            Event::Value { expression, value } => Json::object(vec![
+                ("event", "value".into()),
+                ("expression", expression.as_str().into()),
+                ("value", value.as_str().into()),
+            ]),
+            Event::ProgramOutput { stream, text } => Json::object(vec![
+                ("event", "output".into()),
+                ("stream", (*stream).into()),
+                ("text", text.as_str().into()),
+            ]),
+            Event::Done { id } => Json::object(vec![("event", "done".into()), ("id", id.clone())]),
+            Event::Error { id, message } => Json::object(vec![
+                ("event", "error".into()),
+                ("id", id.clone()),
+                ("message", message.as_str().into()),
+            ]),
+        }
+    }
+}
+
+/// Writes `event` as a line of JSON, or hands it to the debug adapter. Outside these modes,
+/// events are not shown: the user sees the text printed alongside them instead.
+pub fn emit(event: Event) {
+    let event = match CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(events) => {
+            events.push(event);
+            None
+        }
+        None => Some(event),
+    }) {
+        Some(event) => event,
+        None => return,
+    };
+    match mode() {
+        Mode::Text => {}
+        Mode::Json => println!("{}", event.to_json()),
+        Mode::Dap => dap::send_event(&event),
+    }
+}
+
+/// Runs `f`, collecting the events it emits on this thread (including its console text) rather
+/// than sending them on, and returns what `f` returned along with those events.
+pub fn capture<T, F: FnOnce() -> T>(f: F) -> (T, Vec<Event>) {
+    let outer = CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let events = CAPTURED.with(|captured| captured.replace(outer));
+    (result, events.unwrap_or_default())
+}
+
+/// Passes on what the inferior writes to `pipe` as `ProgramOutput` events, a line at a time, from
+/// a thread of its own, which finishes once the pipe is closed.
+pub fn forward_program_output<R: Read + Send + 'static>(
+    pipe: R,
+    stream: &'static str,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => emit(Event::ProgramOutput {
+                    stream,
+                    text: String::from_utf8_lossy(&line).into_owned(),
+                }),
+            }
+        }
+    })
+}
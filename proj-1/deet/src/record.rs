@@ -0,0 +1,232 @@
+//! Process record, for reverse execution. While recording, the inferior runs one instruction at a
+//! time, and for each one we log the registers before it ran and the previous contents of any
+//! memory it changed. Undoing the last entry puts the process back the way it was before that
+//! instruction, so `reverse-stepi` and friends move the live process backwards, and running
+//! forward again executes the program from there. Effects outside the process, like output it
+//! wrote, are not undone.
+//!
+//! Rather than decoding each instruction to find out what it writes, we keep a copy of every
+//! writable mapping and compare it with the process after each step. That also catches memory
+//! filled in by system calls. It is slow, but fine for small programs.
+
+use libc::user_regs_struct;
+use nix::unistd::Pid;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+
+/// Most instructions kept in the log, as gdb's default `record full insn-number-max`. Older
+/// ones are dropped.
+pub const MAX_INSTRUCTIONS: usize = 200_000;
+/// Unchanged memory is skipped over a page at a time, then this many bytes at a time within a
+/// page that changed.
+const PAGE_SIZE: usize = 4096;
+const CHUNK_SIZE: usize = 64;
+
+/// What one instruction did.
+struct Entry {
+    /// Registers before the instruction ran.
+    regs: user_regs_struct,
+    /// Previous contents of the memory it wrote, as (address, bytes) pairs.
+    memory: Vec<(usize, Vec<u8>)>,
+}
+
+/// Our copy of a writable mapping, as of the last instruction logged.
+struct Region {
+    addr: usize,
+    copy: Vec<u8>,
+}
+
+pub struct Recording {
+    pid: Pid,
+    /// /proc/<pid>/mem, to read memory in bulk and to put it back.
+    mem: File,
+    regions: Vec<Region>,
+    entries: VecDeque<Entry>,
+    /// Scratch space for reading a region.
+    buffer: Vec<u8>,
+}
+
+/// Returns the start and end of each writable mapping of process `pid`.
+fn writable_mappings(pid: Pid) -> io::Result<Vec<(usize, usize)>> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    Ok(maps
+        .lines()
+        .filter_map(|line| {
+            // e.g. "555555558000-555555559000 rw-p 00003000 00:1e 1337   /tmp/count"
+            let mut fields = line.split_whitespace();
+            let mut range = fields.next()?.split('-');
+            if !fields.next()?.starts_with("rw") {
+                return None;
+            }
+            let start = usize::from_str_radix(range.next()?, 16).ok()?;
+            let end = usize::from_str_radix(range.next()?, 16).ok()?;
+            Some((start, end))
+        })
+        .collect())
+}
+
+/// Brings `copy`, which starts at `addr`, up to date with `current`, and returns the runs of bytes
+/// that changed, with their previous contents. A run is split where it crosses a page boundary.
+fn update_copy(addr: usize, copy: &mut [u8], current: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut changes = Vec::new();
+    for page_start in (0..copy.len()).step_by(PAGE_SIZE) {
+        let page_end = (page_start + PAGE_SIZE).min(copy.len());
+        if copy[page_start..page_end] == current[page_start..page_end] {
+            continue;
+        }
+        let mut start = page_start;
+        while start < page_end {
+            let end = (start + CHUNK_SIZE).min(page_end);
+            if copy[start..end] == current[start..end] {
+                start = end;
+                continue;
+            }
+            let mut i = start;
+            while i < end {
+                if copy[i] == current[i] {
+                    i += 1;
+                    continue;
+                }
+                let run_start = i;
+                while i < page_end && copy[i] != current[i] {
+                    i += 1;
+                }
+                changes.push((addr + run_start, copy[run_start..i].to_vec()));
+                copy[run_start..i].copy_from_slice(&current[run_start..i]);
+            }
+            start = i;
+        }
+    }
+    changes
+}
+
+impl Recording {
+    /// Starts recording process `pid`, which must be stopped, from its current state.
+    pub fn start(pid: Pid) -> io::Result<Recording> {
+        let mut recording = Recording {
+            pid,
+            mem: OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(format!("/proc/{}/mem", pid))?,
+            regions: Vec::new(),
+            entries: VecDeque::new(),
+            buffer: Vec::new(),
+        };
+        recording.remap()?;
+        Ok(recording)
+    }
+
+    /// Returns the number of instructions that can be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the registers that undoing the last instruction would restore.
+    pub fn last_regs(&self) -> Option<&user_regs_struct> {
+        self.entries.back().map(|entry| &entry.regs)
+    }
+
+    /// Logs an instruction that has just run, given the registers from before it. After a system
+    /// call, which may have added or removed mappings, `remap` picks up the new ones.
+    pub fn log_instruction(&mut self, regs: user_regs_struct, remap: bool) -> io::Result<()> {
+        let mut memory = Vec::new();
+        for region in &mut self.regions {
+            let len = region.copy.len();
+            if self.buffer.len() < len {
+                self.buffer.resize(len, 0);
+            }
+            let current = &mut self.buffer[..len];
+            // A system call may have unmapped it
+            if self.mem.read_exact_at(current, region.addr as u64).is_err() {
+                continue;
+            }
+            memory.extend(update_copy(region.addr, &mut region.copy, current));
+        }
+        if remap {
+            self.remap()?;
+        }
+        self.entries.push_back(Entry { regs, memory });
+        if self.entries.len() > MAX_INSTRUCTIONS {
+            self.entries.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Puts back the memory the last instruction logged wrote, and returns the registers from
+    /// before it ran, or None if the log is empty.
+    pub fn undo(&mut self) -> io::Result<Option<user_regs_struct>> {
+        let entry = match self.entries.pop_back() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        for (addr, bytes) in &entry.memory {
+            self.mem.write_all_at(bytes, *addr as u64)?;
+            let region = self
+                .regions
+                .iter_mut()
+                .find(|region| region.addr <= *addr && *addr < region.addr + region.copy.len());
+            if let Some(region) = region {
+                let offset = addr - region.addr;
+                region.copy[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        Ok(Some(entry.regs))
+    }
+
+    /// Updates the list of writable mappings, keeping our copies of the ones that are unchanged
+    /// and reading the others.
+    fn remap(&mut self) -> io::Result<()> {
+        let mut old_regions = std::mem::take(&mut self.regions);
+        for (start, end) in writable_mappings(self.pid)? {
+            let kept = old_regions
+                .iter()
+                .position(|region| region.addr == start && region.copy.len() == end - start);
+            match kept {
+                Some(index) => self.regions.push(old_regions.swap_remove(index)),
+                None => {
+                    let mut copy = vec![0; end - start];
+                    if self.mem.read_exact_at(&mut copy, start as u64).is_ok() {
+                        self.regions.push(Region { addr: start, copy });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_copy_finds_changed_runs() {
+        let mut copy = vec![0u8; PAGE_SIZE + 200];
+        let mut current = copy.clone();
+        current[3] = 1;
+        current[4] = 2;
+        // A run that crosses from one chunk into the next
+        for byte in &mut current[60..70] {
+            *byte = 9;
+        }
+        // And one that crosses into the next page
+        for byte in &mut current[PAGE_SIZE - 2..PAGE_SIZE + 1] {
+            *byte = 7;
+        }
+        let changes = update_copy(0x1000, &mut copy, &current);
+        assert_eq!(
+            changes,
+            vec![
+                (0x1003, vec![0, 0]),
+                (0x103c, vec![0; 10]),
+                (0x1ffe, vec![0; 2]),
+                (0x2000, vec![0]),
+            ]
+        );
+        assert_eq!(copy, current);
+        assert!(update_copy(0x1000, &mut copy, &current).is_empty());
+    }
+}
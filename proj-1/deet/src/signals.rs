@@ -0,0 +1,148 @@
+//! The `handle` table, which decides whether signals received by the inferior stop it, are
+//! reported, and are delivered when it is resumed.
+
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::str::FromStr;
+
+/// What to do when the inferior receives a signal, as set with `handle`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SignalPolicy {
+    /// Stop the inferior and return to the prompt.
+    pub stop: bool,
+    /// Say that the signal arrived, even if we don't stop.
+    pub print: bool,
+    /// Deliver the signal to the inferior when it is resumed, rather than discarding it.
+    pub pass: bool,
+}
+
+impl SignalPolicy {
+    const STOP_PRINT_PASS: SignalPolicy = SignalPolicy { stop: true, print: true, pass: true };
+    const QUIET: SignalPolicy = SignalPolicy { stop: false, print: false, pass: true };
+}
+
+/// The policy for every signal, starting from gdb's defaults.
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl SignalTable {
+    pub fn new() -> SignalTable {
+        let mut policies = HashMap::new();
+        for signal in Signal::iterator() {
+            let policy = match signal {
+                // Used by the debugger itself, so the inferior never sees them
+                Signal::SIGTRAP | Signal::SIGINT => SignalPolicy {
+                    pass: false,
+                    ..SignalPolicy::STOP_PRINT_PASS
+                },
+                // Routine signals that programs handle all the time
+                Signal::SIGALRM
+                | Signal::SIGCHLD
+                | Signal::SIGURG
+                | Signal::SIGIO
+                | Signal::SIGVTALRM
+                | Signal::SIGPROF
+                | Signal::SIGWINCH => SignalPolicy::QUIET,
+                _ => SignalPolicy::STOP_PRINT_PASS,
+            };
+            policies.insert(signal, policy);
+        }
+        SignalTable { policies }
+    }
+
+    pub fn get(&self, signal: Signal) -> SignalPolicy {
+        self.policies.get(&signal).copied().unwrap_or(SignalPolicy::STOP_PRINT_PASS)
+    }
+
+    /// Applies `handle` keywords to `signal`. As in gdb, `stop` implies `print` and `noprint`
+    /// implies `nostop`.
+    pub fn update(&mut self, signal: Signal, keywords: &[String]) -> Result<(), String> {
+        let mut policy = self.get(signal);
+        for keyword in keywords {
+            match keyword.to_lowercase().as_str() {
+                "stop" => {
+                    policy.stop = true;
+                    policy.print = true;
+                }
+                "nostop" => policy.stop = false,
+                "print" => policy.print = true,
+                "noprint" => {
+                    policy.print = false;
+                    policy.stop = false;
+                }
+                "pass" | "noignore" => policy.pass = true,
+                "nopass" | "ignore" => policy.pass = false,
+                _ => return Err(format!("Unrecognized or ambiguous flag word: \"{}\".", keyword)),
+            }
+        }
+        self.policies.insert(signal, policy);
+        Ok(())
+    }
+
+    /// Prints the policy for each of `signals` in gdb's tabular format.
+    pub fn print(&self, signals: &[Signal]) {
+        console!("Signal        Stop\tPrint\tPass to program\tDescription");
+        for signal in signals {
+            let policy = self.get(*signal);
+            console!(
+                "{:<14}{}\t{}\t{}\t\t{}",
+                signal.as_str(),
+                yes_no(policy.stop),
+                yes_no(policy.print),
+                yes_no(policy.pass),
+                describe(*signal)
+            );
+        }
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "Yes"
+    } else {
+        "No"
+    }
+}
+
+/// Parses a signal name, with or without the "SIG" prefix, or a signal number.
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = name.to_uppercase();
+    if name.starts_with("SIG") {
+        Signal::from_str(&name).ok()
+    } else {
+        Signal::from_str(&format!("SIG{}", name)).ok()
+    }
+}
+
+/// Returns the C library's description of `signal`, e.g. "Segmentation fault".
+pub fn describe(signal: Signal) -> String {
+    unsafe {
+        let description = libc::strsignal(signal as libc::c_int);
+        if description.is_null() {
+            return signal.as_str().to_string();
+        }
+        CStr::from_ptr(description).to_string_lossy().into_owned()
+    }
+}
+
+/// Describes the `si_code` of a fault, as found in the siginfo of a SIGSEGV or SIGBUS.
+pub fn describe_fault(signal: Signal, code: i32) -> Option<&'static str> {
+    // From <asm-generic/siginfo.h>
+    match (signal, code) {
+        (Signal::SIGSEGV, 1) => Some("address not mapped to object"),
+        (Signal::SIGSEGV, 2) => Some("invalid permissions for mapped object"),
+        (Signal::SIGBUS, 1) => Some("invalid address alignment"),
+        (Signal::SIGBUS, 2) => Some("nonexistent physical address"),
+        (Signal::SIGBUS, 3) => Some("object specific hardware error"),
+        (Signal::SIGFPE, 1) => Some("integer divide by zero"),
+        (Signal::SIGFPE, 2) => Some("integer overflow"),
+        (Signal::SIGILL, 1) => Some("illegal opcode"),
+        _ => None,
+    }
+}
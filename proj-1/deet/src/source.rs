@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Number of lines printed by a plain `list`.
+pub const LIST_SIZE: usize = 10;
+
+/// Finds and caches the source files named in the debugging information.
+pub struct SourceCache {
+    /// Extra directories to search, most recently added first.
+    dirs: Vec<PathBuf>,
+    /// Lines of each file we have looked for, or None if it could not be found.
+    files: HashMap<String, Option<Vec<String>>>,
+}
+
+impl SourceCache {
+    pub fn new() -> SourceCache {
+        SourceCache {
+            dirs: Vec::new(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Adds a directory to search for source files. Forgets files that could not be found
+    /// before, so that they are looked up again in the new directory.
+    pub fn add_directory(&mut self, dir: &str) {
+        self.dirs.insert(0, PathBuf::from(dir));
+        self.files.retain(|_, lines| lines.is_some());
+    }
+
+    pub fn directories(&self) -> Vec<String> {
+        self.dirs
+            .iter()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Returns the lines of the source file `path` (as recorded in the DWARF data), searching the
+    /// path itself and then each source directory, both with the full path and with just the
+    /// file name.
+    pub fn get_lines(&mut self, path: &str) -> Option<&Vec<String>> {
+        if !self.files.contains_key(path) {
+            let lines = self.find(path).and_then(|found| fs::read_to_string(found).ok()).map(
+                |contents| contents.lines().map(|line| line.to_string()).collect(),
+            );
+            if lines.is_none() {
+                console!(
+                    "Can not find source file {} (use \"directory <path>\" to add a search path)",
+                    path
+                );
+            }
+            self.files.insert(path.to_string(), lines);
+        }
+        self.files[path].as_ref()
+    }
+
+    fn find(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        let mut candidates = vec![path.to_path_buf()];
+        for dir in &self.dirs {
+            if path.is_relative() {
+                candidates.push(dir.join(path));
+            }
+            if let Some(name) = path.file_name() {
+                candidates.push(dir.join(name));
+            }
+        }
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    }
+
+    /// Prints lines `first..=last` (1-based, clamped to the file) of `path`, marking `current`
+    /// with "=>". Returns the last line printed, or None if the file could not be read.
+    pub fn print_lines(
+        &mut self,
+        path: &str,
+        first: usize,
+        last: usize,
+        current: Option<usize>,
+    ) -> Option<usize> {
+        let lines = self.get_lines(path)?;
+        let first = first.max(1);
+        let last = last.min(lines.len());
+        if first > last {
+            console!("Line number {} out of range; \"{}\" has {} lines.", first, path, lines.len());
+            return None;
+        }
+        for number in first..=last {
+            let marker = if Some(number) == current { "=>" } else { "  " };
+            console!("{} {:<4}\t{}", marker, number, lines[number - 1]);
+        }
+        Some(last)
+    }
+}
@@ -0,0 +1,174 @@
+//! Unwinds the inferior's stack using the call frame information in `.eh_frame` or
+//! `.debug_frame`, so that backtraces work for code compiled without frame pointers.
+
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, Register, RegisterRule,
+    UninitializedUnwindContext, UnwindSection, UnwindTableRow,
+};
+use libc::user_regs_struct;
+use object::{Object, ObjectSection};
+use std::rc::Rc;
+
+type Reader = gimli::EndianRcSlice<gimli::RunTimeEndian>;
+
+/// Number of x86-64 DWARF registers we track: the 16 general-purpose registers plus the return
+/// address column.
+pub const NUM_REGISTERS: usize = 17;
+pub const RBP: usize = 6;
+pub const RSP: usize = 7;
+/// The return address column, which holds %rip once a frame has been unwound.
+pub const RIP: usize = 16;
+/// %rbx, %rbp and %r12-%r15 are preserved across calls, so a frame that says nothing about them
+/// has left them untouched.
+const CALLEE_SAVED: [usize; 6] = [3, 6, 12, 13, 14, 15];
+
+/// Register values indexed by DWARF register number. None means the value could not be recovered.
+pub type Registers = [Option<u64>; NUM_REGISTERS];
+
+/// Names of the registers, indexed by DWARF register number.
+pub const REGISTER_NAMES: [&str; NUM_REGISTERS] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+
+/// The result of unwinding one frame.
+pub struct Unwound {
+    /// The canonical frame address of the unwound frame.
+    pub cfa: usize,
+    /// The registers of its caller.
+    pub caller: Registers,
+    /// Where the frame saved its caller's registers on the stack, as (DWARF register number,
+    /// address) pairs.
+    pub saved: Vec<(usize, usize)>,
+}
+
+/// Converts ptrace's register struct into DWARF register numbering.
+pub fn registers_from(regs: &user_regs_struct) -> Registers {
+    [
+        Some(regs.rax),
+        Some(regs.rdx),
+        Some(regs.rcx),
+        Some(regs.rbx),
+        Some(regs.rsi),
+        Some(regs.rdi),
+        Some(regs.rbp),
+        Some(regs.rsp),
+        Some(regs.r8),
+        Some(regs.r9),
+        Some(regs.r10),
+        Some(regs.r11),
+        Some(regs.r12),
+        Some(regs.r13),
+        Some(regs.r14),
+        Some(regs.r15),
+        Some(regs.rip),
+    ]
+}
+
+pub struct CallFrameInfo {
+    eh_frame: Option<EhFrame<Reader>>,
+    debug_frame: Option<DebugFrame<Reader>>,
+    bases: BaseAddresses,
+}
+
+impl CallFrameInfo {
+    /// Loads the call frame information from the object file. Sections that are missing are
+    /// simply not used; if both are missing, every lookup fails and callers fall back to walking
+    /// frame pointers.
+    pub fn load(object: &object::File, endian: gimli::RunTimeEndian) -> CallFrameInfo {
+        let load_section = |name: &str| -> Option<Reader> {
+            let data = object.section_data_by_name(name)?;
+            Some(gimli::EndianRcSlice::new(Rc::from(&*data), endian))
+        };
+        let section_address = |name: &str| object.section_by_name(name).map(|s| s.address());
+
+        let mut bases = BaseAddresses::default();
+        if let Some(addr) = section_address(".eh_frame") {
+            bases = bases.set_eh_frame(addr);
+        }
+        if let Some(addr) = section_address(".text") {
+            bases = bases.set_text(addr);
+        }
+        if let Some(addr) = section_address(".got") {
+            bases = bases.set_got(addr);
+        }
+
+        CallFrameInfo {
+            eh_frame: load_section(".eh_frame").map(|data| {
+                let mut section = EhFrame::from(data);
+                section.set_address_size(8);
+                section
+            }),
+            debug_frame: load_section(".debug_frame").map(|data| {
+                let mut section = DebugFrame::from(data);
+                section.set_address_size(8);
+                section
+            }),
+            bases,
+        }
+    }
+
+    fn row_for_address(&self, addr: u64) -> Option<UnwindTableRow<Reader>> {
+        let mut ctx = UninitializedUnwindContext::new();
+        if let Some(eh_frame) = &self.eh_frame {
+            if let Ok(row) =
+                eh_frame.unwind_info_for_address(&self.bases, &mut ctx, addr, EhFrame::cie_from_offset)
+            {
+                return Some(row);
+            }
+        }
+        if let Some(debug_frame) = &self.debug_frame {
+            if let Ok(row) = debug_frame.unwind_info_for_address(
+                &self.bases,
+                &mut ctx,
+                addr,
+                DebugFrame::cie_from_offset,
+            ) {
+                return Some(row);
+            }
+        }
+        None
+    }
+
+    /// Computes the canonical frame address of the frame whose registers are `regs`, the
+    /// registers of its caller and where they were saved. `lookup_addr` is the address whose unwind rules apply: the
+    /// frame's %rip for the innermost frame, and one less than the return address for the others
+    /// (which may point just past the end of the function when the call was the last instruction).
+    /// Returns None if there is no call frame information for the address, or if it uses DWARF
+    /// expressions, which we don't evaluate.
+    pub fn unwind(
+        &self,
+        regs: &Registers,
+        lookup_addr: usize,
+        read_word: &mut dyn FnMut(usize) -> Option<u64>,
+    ) -> Option<Unwound> {
+        let row = self.row_for_address(lookup_addr as u64)?;
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                (regs.get(register.0 as usize).copied()?? as i64 + offset) as usize
+            }
+            CfaRule::Expression(_) => return None,
+        };
+
+        let mut caller = [None; NUM_REGISTERS];
+        let mut saved = Vec::new();
+        for (reg, value) in caller.iter_mut().enumerate() {
+            *value = match row.register(Register(reg as u16)) {
+                RegisterRule::Undefined if CALLEE_SAVED.contains(&reg) => regs[reg],
+                RegisterRule::Undefined => None,
+                RegisterRule::SameValue => regs[reg],
+                RegisterRule::Offset(offset) => {
+                    let addr = (cfa as i64 + offset) as usize;
+                    saved.push((reg, addr));
+                    read_word(addr)
+                }
+                RegisterRule::ValOffset(offset) => Some((cfa as i64 + offset) as u64),
+                RegisterRule::Register(other) => regs.get(other.0 as usize).copied().flatten(),
+                _ => None,
+            };
+        }
+        // The CFA is by definition the caller's stack pointer before the call
+        caller[RSP] = Some(cfa as u64);
+        Some(Unwound { cfa, caller, saved })
+    }
+}
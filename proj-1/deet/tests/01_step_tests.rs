@@ -0,0 +1,49 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// `step` enters a call and stops once the callee has set up its frame, `next` and `finish` move
+/// on from there, and `stepi` runs a single instruction.
+#[test]
+fn test_step_next_finish() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break 13",
+            "run",
+            "step",
+            "next",
+            "finish",
+            "next",
+            "stepi",
+            "stepi",
+        ],
+    );
+    assert!(output.contains("function_calls.c: 13\n"));
+    assert!(output.contains("function_calls.c: 6\n"));
+    assert!(output.contains("function_calls.c: 7\n"));
+    assert!(output.contains("Run till exit from func3\n"));
+    assert!(output.contains("function_calls.c: 14\n"));
+    assert!(output.contains("function_calls.c: 19\n"));
+    // The first stepi stays on the line, and the second executes the call into func3
+    let after_next = &output[output.find("function_calls.c: 19\n").unwrap()..];
+    assert!(after_next.contains("function_calls.c: 5\n"));
+}
+
+/// A breakpoint on a function stops after its prologue.
+#[test]
+fn test_break_function() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(&program, &["break func2", "run"]);
+    assert!(output.contains("function_calls.c: 10\n"));
+}
+
+/// `finish` runs until the selected frame returns, not just the innermost one.
+#[test]
+fn test_finish_selected_frame() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(&program, &["break func3", "run", "up", "finish", "backtrace"]);
+    assert!(output.contains("Run till exit from func2\n"));
+    assert!(output.contains("#0  func1 (a=42) at "));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// `print` reads locals relative to the frame and globals at their address, and `info locals`
+/// lists everything in the current function.
+#[test]
+fn test_print_variables() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break 12",
+            "run",
+            "print sum",
+            "print global",
+            "info locals",
+            "print nope",
+        ],
+    );
+    assert!(output.contains("function_calls.c: 12\n"));
+    assert!(output.contains("sum = 47\nglobal = 5\na = 42\nb = 5\nsum = 47\n"));
+    assert!(output.contains("No symbol \"nope\" in current context.\n"));
+}
+
+/// Locals and globals of the base types print the way C would show them.
+#[test]
+fn test_print_base_types() {
+    let program = compile_sample("types");
+    let output = run_deet(
+        &program,
+        &[
+            "break 12",
+            "run",
+            "print initial",
+            "print offset",
+            "print scale",
+            "print where",
+            "print letter",
+            "print big",
+            "print ratio",
+        ],
+    );
+    assert!(output.contains("initial = 100 'd'\noffset = 9876543210\nscale = -0.25\nwhere = 0x7"));
+    assert!(output.contains("letter = 120 'x'\nbig = -1234567890123\nratio = 2.5\n"));
+}
+
+/// A breakpoint on a function stops after its prologue, where the arguments can be read.
+#[test]
+fn test_print_arguments() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(&program, &["break func2", "run", "print a", "print b"]);
+    assert!(output.contains("function_calls.c: 10\n"));
+    assert!(output.contains("a = 42\nb = 5\n"));
+}
+
+/// Structs, unions, arrays, enums, pointers and typedefs print in nested form.
+#[test]
+fn test_print_types() {
+    let program = compile_sample("structs");
+    let output = run_deet(
+        &program,
+        &[
+            "break 40",
+            "run",
+            "print first",
+            "print arr",
+            "print greeting",
+            "print grid",
+            "print num",
+            "print *first.next",
+        ],
+    );
+    assert!(output.contains("first = {value = 1, pos = {x = 1, y = 2}, color = GREEN, next = 0x"));
+    assert!(output.contains("arr = {10, 20, 30, 40, 50}\n"));
+    assert!(output.contains("greeting = \"hello\"\n"));
+    assert!(output.contains("grid = {{1, 2, 3}, {4, 5, 6}}\n"));
+    assert!(output.contains("num = {i = 1069547520, f = 1.5}\n"));
+    assert!(output
+        .contains("*first.next = {value = 2, pos = {x = 3, y = 4}, color = BLUE, next = 0x0}\n"));
+
+    let output = run_deet(&program, &["break sum_list", "run", "print *head"]);
+    assert!(output.contains("structs.c: 27\n"));
+    assert!(output.contains("*head = {value = 1, pos = {x = 1, y = 2}, color = GREEN, next = 0x"));
+}
+
+/// `print` evaluates C expressions: arithmetic, comparisons, member access through pointers,
+/// indexing, address-of, casts and registers.
+#[test]
+fn test_print_expressions() {
+    let program = compile_sample("structs");
+    let output = run_deet(
+        &program,
+        &[
+            "break 40",
+            "run",
+            "print arr[3] * 2 + 1",
+            "print first.next->pos.y",
+            "print grid[1][2]",
+            "print arr[1] > arr[0]",
+            "print (char)arr[4]",
+            "print &arr[1]",
+            "print $rip",
+            "print arr[",
+        ],
+    );
+    assert!(output.contains("arr[3] * 2 + 1 = 81\n"));
+    assert!(output.contains("first.next->pos.y = 4\n"));
+    assert!(output.contains("grid[1][2] = 6\n"));
+    assert!(output.contains("arr[1] > arr[0] = 1\n"));
+    assert!(output.contains("(char)arr[4] = 50 '2'\n"));
+    assert!(output.contains("&arr[1] = 0x7"));
+    assert!(output.contains("$rip = 0x"));
+    assert!(output.contains("A syntax error in expression, near `'.\n"));
+}
+
+/// `set var` and `set *address` write values of the right size, which the program then uses.
+#[test]
+fn test_set_var() {
+    let program = compile_sample("structs");
+    let output = run_deet(
+        &program,
+        &[
+            "break 40",
+            "run",
+            "set var first.value = 100",
+            "set var arr[3] = -41",
+            "set var num.f = 2.5",
+            "set *(int *)&grid[1][2] = 7",
+            "print arr",
+            "print grid[1]",
+            "continue",
+        ],
+    );
+    assert!(output.contains("arr = {10, 20, 30, -41, 50}\n"));
+    assert!(output.contains("grid[1] = {4, 5, 7}\n"));
+    assert!(output.contains("sum = 102\n"));
+    assert!(output.contains("hello -41 7 2.500000\n"));
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// Breakpoints keep their numbers, are listed with where they are, and can be disabled,
+/// enabled and deleted.
+#[test]
+fn test_manage_breakpoints() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break 6",
+            "break func2",
+            "disable 1",
+            "info breakpoints",
+            "run",
+            "delete 0",
+            "enable 1",
+            "info breakpoints",
+            "delete 7",
+            "continue",
+        ],
+    );
+    assert!(output.contains("Set breakpoint 0 at 0x"));
+    assert!(output.contains("Set breakpoint 1 at 0x"));
+    assert!(output.contains("1    n    0x"));
+    assert!(output.contains(" in func2 at "));
+    // func2 runs first, but its breakpoint is disabled
+    let output = &output[output.find("Child stopped").unwrap()..];
+    assert!(output.starts_with("Child stopped (signal SIGTRAP)\nStopped at "));
+    assert!(output.contains("function_calls.c: 6\n"));
+    assert!(output.contains("1    y    0x"));
+    assert!(!output.contains("0    y    0x"));
+    assert!(output.contains("No breakpoint number 7.\n"));
+    assert!(output.contains("The program exited with code 0\n"));
+}
+
+/// A conditional breakpoint only stops, and only counts a hit, when its condition holds, and
+/// `ignore` resumes through the next hits.
+#[test]
+fn test_condition_and_ignore() {
+    let program = compile_sample("globals");
+    let output = run_deet(
+        &program,
+        &["break 7 if i == 2", "run", "print i", "info breakpoints"],
+    );
+    assert!(output.contains("globals.c: 7\n"));
+    assert!(output.contains("i = 2\n"));
+    assert!(output.contains("\tstop only if i == 2\n\tbreakpoint already hit 1 time(s)\n"));
+
+    let output = run_deet(
+        &program,
+        &[
+            "break 7",
+            "ignore 0 2",
+            "run",
+            "print i",
+            "info breakpoints",
+        ],
+    );
+    assert!(output.contains("Will ignore next 2 crossings of breakpoint 0.\n"));
+    assert!(output.contains("i = 2\n"));
+    assert!(output.contains("\tbreakpoint already hit 3 time(s)\n"));
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// A watchpoint on a local reports the change, and is deleted once its frame returns.
+#[test]
+fn test_watch_local() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func2",
+            "run",
+            "watch sum",
+            "continue",
+            "continue",
+            "info breakpoints",
+        ],
+    );
+    assert!(output.contains("Hardware watchpoint 1: sum\n"));
+    assert!(output.contains("New value = 47\n"));
+    assert!(output.contains("function_calls.c: 12\n"));
+    let after_return = &output[output
+        .find("Watchpoint 1 deleted because the program has left the block")
+        .unwrap()..];
+    assert!(after_return.contains("function_calls.c: 19\n"));
+    assert!(!after_return.contains("hw watchpoint"));
+}
+
+/// A disabled watchpoint stays listed but doesn't fire until it is enabled again.
+#[test]
+fn test_disable_enable_watchpoint() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func2",
+            "run",
+            "watch sum",
+            "disable 1",
+            "info breakpoints",
+            "continue",
+        ],
+    );
+    assert!(!output.contains("No breakpoint number 1."));
+    assert!(output.contains("1    n    0x"));
+    assert!(!output.contains("New value = 47\n"));
+    assert!(output.contains("Watchpoint 1 deleted because the program has left the block"));
+
+    let output = run_deet(
+        &program,
+        &[
+            "break func2",
+            "run",
+            "watch sum",
+            "disable 1",
+            "enable 1",
+            "info breakpoints",
+            "continue",
+        ],
+    );
+    assert!(output.contains("1    y    0x"));
+    assert!(output.contains("New value = 47\n"));
+}
+
+/// A watchpoint set before `run` follows the variable to where the executable is loaded.
+#[test]
+fn test_watch_before_run() {
+    let program = compile_sample("globals");
+    let output = run_deet(&program, &["watch counter", "run", "info breakpoints", "continue"]);
+    assert!(!output.contains("Can not watch"));
+    assert!(output.contains("0    y    0x00005"));
+    assert!(output.contains("Old value = 0\nNew value = 10\n"));
+    assert!(output.contains("Old value = 10\nNew value = 20\n"));
+    assert!(output.contains("globals.c: 6\n"));
+}
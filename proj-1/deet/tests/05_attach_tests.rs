@@ -0,0 +1,34 @@
+mod common;
+
+use common::{compile_sample, run_deet_with_args};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// `--pid` attaches to a running process and plants breakpoints in it, and `detach` takes them
+/// out again and leaves the process running.
+#[test]
+fn test_attach_detach() {
+    let program = compile_sample("sleepy_print");
+    let mut child = Command::new(&program)
+        .arg("30")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Could not run sample");
+    let pid = child.id().to_string();
+    let output = run_deet_with_args(
+        &["--pid".as_ref(), pid.as_ref()],
+        &["break 18", "continue", "print a", "detach"],
+    );
+    assert!(output.contains(&format!("Attaching to process {}\n", pid)));
+    assert!(output.contains("sleepy_print.c: 18\n"));
+    assert!(output.contains("a = 1\n"));
+    assert!(output.contains(&format!("Detaching from process {}\n", pid)));
+
+    // The loop comes back round to the breakpoint's line after a second, which would kill the
+    // process with SIGTRAP if the breakpoint were still planted
+    std::thread::sleep(Duration::from_millis(1500));
+    let status = child.try_wait().expect("Could not check on sample");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(status.is_none(), "Sample exited after detach: {:?}", status);
+}
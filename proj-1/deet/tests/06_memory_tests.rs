@@ -0,0 +1,41 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// `info registers` shows the registers and decodes eflags, `set $reg` changes one, and `x`
+/// reads memory as words of the given size.
+#[test]
+fn test_registers_and_examine() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func2",
+            "run",
+            "set $rax = 0x1234",
+            "info registers rax",
+            "info registers",
+            "x/2dw &global",
+            "x/1xg &global",
+        ],
+    );
+    assert!(output.contains("rax            0x1234             4660\n"));
+    assert!(output.contains("rdi            0x2a               42\n"));
+    assert!(output.contains(" <func2+"));
+    assert!(output.contains("eflags         0x"));
+    assert!(output.contains("[ IF "));
+    assert!(output.contains(":\t5\t0\n"));
+    assert!(output.contains(":\t0x0000000000000005\n"));
+}
+
+/// `x/c` shows each byte as a number and as a C character constant.
+#[test]
+fn test_examine_chars() {
+    let program = compile_sample("types");
+    let output = run_deet(&program, &["break 12", "run", "x/8c &offset", "x/1c &letter"]);
+    // 9876543210 is 0x24cb016ea
+    assert!(output.contains(
+        ":\t-22 '\\352'\t22 '\\026'\t-80 '\\260'\t76 'L'\t2 '\\002'\t0 '\\000'\t0 '\\000'\t0 '\\000'\n"
+    ));
+    assert!(output.contains(":\t120 'x'\n"));
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// `disassemble` shows the instructions of a function with the source lines they come from,
+/// marks the current one, and shows the original instruction where a breakpoint is planted.
+#[test]
+fn test_disassemble() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func3",
+            "run",
+            "disassemble",
+            "x/2i $rip",
+            "disassemble func2",
+        ],
+    );
+    assert!(output.contains("Dump of assembler code for function func3:\n"));
+    assert!(output.contains("function_calls.c:6:\n=> 0x"));
+    let current = output
+        .lines()
+        .find(|line| line.starts_with("=> 0x"))
+        .unwrap();
+    assert!(current.contains(" <func3+"), "{}", current);
+    assert!(!output.contains("int3"));
+    assert!(output.contains("End of assembler dump.\n"));
+    assert!(output.contains("Dump of assembler code for function func2:\n"));
+    assert!(output.contains(" <func3>\n"));
+}
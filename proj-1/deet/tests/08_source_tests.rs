@@ -0,0 +1,21 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// `list` shows the source around a function or line and carries on from there, every stop
+/// shows the lines around it, and a plain `list` after a stop centres on the stop.
+#[test]
+fn test_list() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &["list func2", "list", "list 3", "break 12", "run", "list"],
+    );
+    assert!(output.contains("   4   \t\n   5   \tvoid func3(int a) {\n"));
+    assert!(output.contains("   13  \t    func3(100);\n   14  \t}\n   15  \t\n"));
+    assert!(output.contains("   23  \tint main() {\n   1   \t#include <stdio.h>\n"));
+    assert!(output.contains("=> 12  \t    printf(\"sum = %d\\n\", sum);\n"));
+    let after_stop = &output[output.find("=> 12  \t").unwrap()..];
+    assert!(after_stop.contains("   7   \t}\n"));
+    assert!(after_stop.contains("   16  \tvoid func1(int a) {\n"));
+}
@@ -0,0 +1,16 @@
+mod common;
+
+use common::{compile_sample_without_frame_pointer, run_deet};
+
+/// Without frame pointers, `backtrace` unwinds with the call frame information and still finds
+/// each frame's arguments.
+#[test]
+fn test_backtrace_without_frame_pointer() {
+    let program = compile_sample_without_frame_pointer("function_calls");
+    let output = run_deet(&program, &["break func3", "run", "backtrace"]);
+    assert!(output.contains("#0  func3 (a=100) at "));
+    assert!(output.contains(" in func2 (a=42, b=5) at "));
+    assert!(output.contains("function_calls.c:13\n#2  0x"));
+    assert!(output.contains(" in func1 (a=42) at "));
+    assert!(output.contains(" in main () at "));
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// `up`, `down` and `frame N` select the frame that `print` and `info locals` read from, and
+/// `info frame` describes it.
+#[test]
+fn test_select_frame() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func3",
+            "run",
+            "up",
+            "print sum",
+            "up",
+            "print a",
+            "down",
+            "frame 0",
+            "print a",
+            "info frame",
+            "frame 9",
+        ],
+    );
+    assert!(output.contains(" in func2 (a=42, b=5) at "));
+    assert!(output.contains("   13  \t    func3(100);\nsum = 47\n"));
+    assert!(output.contains("   18  \t    func2(a, global);\na = 42\n"));
+    assert!(output.contains("#0  func3 (a=100) at "));
+    assert!(output.contains("\na = 100\nStack level 0, frame at 0x"));
+    assert!(output.contains(" in func3 ("));
+    assert!(output.contains("; saved rip = 0x"));
+    assert!(output.contains(" called by frame at 0x"));
+    assert!(output.contains(" Saved registers:\n  rbp at 0x"));
+    assert!(output.contains("No frame at level 9.\n"));
+}
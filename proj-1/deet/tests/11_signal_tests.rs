@@ -0,0 +1,32 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// By default a signal stops the program and is delivered when it continues, and `handle`
+/// changes whether it stops, is reported and reaches the program.
+#[test]
+fn test_handle_signals() {
+    let program = compile_sample("signals");
+    let output = run_deet(&program, &["run", "continue"]);
+    assert!(output.contains("Child stopped (signal SIGUSR1)\n"));
+    assert!(output.contains("Caught signal 10\n"));
+    assert!(output.contains("Received 2 signals\n"));
+
+    let output = run_deet(&program, &["handle SIGUSR1 nostop print nopass", "run"]);
+    assert!(output.contains("SIGUSR1       No\tYes\tNo\t\tUser defined signal 1\n"));
+    assert!(!output.contains("Child stopped"));
+    assert!(output.contains("Child received signal SIGUSR1, User defined signal 1\n"));
+    assert!(!output.contains("Caught signal 10\n"));
+    assert!(output.contains("Received 1 signals\n"));
+}
+
+/// A fault reports the address it was caused by.
+#[test]
+fn test_fault_address() {
+    let program = compile_sample("segfault");
+    let output = run_deet(&program, &["run"]);
+    assert!(output.contains(
+        "Child stopped (signal SIGSEGV)\nFault address: 0x0 (address not mapped to object)\n"
+    ));
+    assert!(output.contains("segfault.c: 5\n"));
+}
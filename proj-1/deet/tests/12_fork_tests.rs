@@ -0,0 +1,33 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// By default the debugger stays with the parent. With `follow-fork-mode child` it follows the
+/// child through its exec, loading the new program's symbols to stop at a breakpoint in it.
+#[test]
+fn test_follow_fork() {
+    let program = compile_sample("fork");
+    let output = run_deet(&program, &["run"]);
+    assert!(output.contains("[Detaching after fork from child process "));
+    assert!(output.contains(" saw child exit with 3\n"));
+    assert!(output.contains("The program exited with code 0\n"));
+
+    let output = run_deet(
+        &program,
+        &[
+            "set follow-fork-mode child",
+            "break 17",
+            "run",
+            "info inferiors",
+            "print argc",
+            "continue",
+        ],
+    );
+    assert!(output.contains("[Attaching after process "));
+    assert!(output.contains("[Detaching after fork from parent process "));
+    assert!(output.contains(" is executing new program: "));
+    assert!(output.contains("fork.c: 17\n"));
+    assert!(output.contains("  Num  Description       Executable\n* 2    process "));
+    assert!(output.contains("argc = 2\n"));
+    assert!(output.contains("The program exited with code 3\n"));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// A breakpoint in a worker thread stops the whole program and reports which thread hit it.
+#[test]
+fn test_breakpoint_in_thread() {
+    let program = compile_sample("threads");
+    let output = run_deet(&program, &["break 12", "run", "p id", "bt"]);
+    assert!(output.contains("[New Thread 2 (LWP "));
+    assert!(output.contains("[Switching to thread "));
+    assert!(output.contains("=> 12  \t    counter += id;"));
+    assert!(output.contains("#0  worker (arg=0x"));
+}
+
+/// `info threads` lists the main thread and the workers, marking the current one.
+#[test]
+fn test_info_threads() {
+    let program = compile_sample("threads");
+    let output = run_deet(&program, &["break 12", "run", "info threads"]);
+    let table: Vec<&str> = output
+        .lines()
+        .skip_while(|line| !line.contains("Target Id"))
+        .skip(1)
+        .take_while(|line| line.contains("LWP "))
+        .collect();
+    assert!(table.len() > 1, "Expected several threads in:\n{}", output);
+    assert!(table.iter().any(|line| line.starts_with("  1    LWP ")));
+    assert_eq!(
+        table.iter().filter(|line| line.starts_with("* ")).count(),
+        1
+    );
+    assert!(table
+        .iter()
+        .any(|line| line.starts_with("* ") && line.contains("worker (")));
+}
+
+/// `thread N` switches to another thread, and rejects threads that don't exist.
+#[test]
+fn test_thread_switching() {
+    let program = compile_sample("threads");
+    let output = run_deet(
+        &program,
+        &["break 12", "run", "thread 1", "thread", "thread 99"],
+    );
+    assert!(output.contains("[Switching to thread 1 (LWP "));
+    assert!(output.contains("[Current thread is 1 (LWP "));
+    assert!(output.contains("Invalid thread ID: 99"));
+}
+
+/// Every worker hits the breakpoint once, and the program runs to completion afterwards.
+#[test]
+fn test_continue_all_threads() {
+    let program = compile_sample("threads");
+    let output = run_deet(&program, &["break 12", "run", "c", "c", "c"]);
+    assert_eq!(output.matches("=> 12  \t    counter += id;").count(), 3);
+    assert!(output.contains("counter = 6"));
+    assert!(output.contains("The program exited with code 0"));
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::{compile_sample_with_library, run_deet};
+
+/// A breakpoint in a library that isn't loaded yet stays pending until the dynamic linker loads
+/// it, and `finish` returns to the executable, which is loaded elsewhere than it was linked.
+#[test]
+fn test_library_breakpoint() {
+    let program = compile_sample_with_library("sharedlib", "greet");
+    let output = run_deet(
+        &program,
+        &[
+            "break greet",
+            "run",
+            "backtrace",
+            "info sharedlibrary",
+            "finish",
+            "continue",
+            "continue",
+        ],
+    );
+    assert!(output.contains("Breakpoint 0 (greet) pending.\n"));
+    assert!(output.contains("libgreet.c: 6\n"));
+    assert!(output.contains("#0  greet (name=0x"));
+    assert!(output.contains("/libgreet.so\n"));
+    assert!(output.contains("Run till exit from greet\n"));
+    assert!(output.contains("sharedlib.c: 5\n"));
+    assert!(output.contains("Hello, deet! (greeting #2)\n"));
+    assert!(output.contains("The program exited with code 0\n"));
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{compile_sample, run_deet, run_deet_script, write_script};
+
+/// A `-x` script runs before the prompt, and `--batch` quits once it is done.
+#[test]
+fn test_batch_script() {
+    let program = compile_sample("count");
+    let output = run_deet_script(&program, &["# Stop halfway", "break 6", "run", "bt"]);
+    assert!(output.contains("=> 6   \t    printf(\"3\\n\");"));
+    assert!(output.contains("#0  main () at "));
+    assert!(output.contains("Killing running inferior"));
+    assert!(!output.contains("The program exited"));
+}
+
+/// The lines of a `commands` block run every time the breakpoint stops the program.
+#[test]
+fn test_breakpoint_commands() {
+    let program = compile_sample("function_calls");
+    let output = run_deet_script(
+        &program,
+        &["break 6", "commands", "bt", "continue", "end", "run"],
+    );
+    assert_eq!(
+        output.matches("#0  func3 (a=100) at ").count(),
+        2,
+        "in:\n{}",
+        output
+    );
+    assert!(output.contains("The program exited"));
+}
+
+/// `source` runs the commands in a file, then goes back to reading from the prompt.
+#[test]
+fn test_source() {
+    let program = compile_sample("count");
+    let script = write_script(&["break 6", "run"]);
+    let output = run_deet(
+        &program,
+        &[&format!("source {}", script.display()), "info breakpoints"],
+    );
+    std::fs::remove_file(&script).ok();
+    assert!(output.contains("=> 6   \t    printf(\"3\\n\");"));
+    assert!(output.contains("\tbreakpoint already hit 1 time(s)"));
+}
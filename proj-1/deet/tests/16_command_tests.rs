@@ -0,0 +1,39 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// Commands with missing or extra arguments print their usage rather than crashing deet.
+#[test]
+fn test_usage_errors() {
+    let program = compile_sample("count");
+    let output = run_deet(
+        &program,
+        &["b", "ignore 1", "continue now", "frobnicate", "help"],
+    );
+    assert!(output.contains("Usage: break <function | line | file:line | *address>"));
+    assert!(output.contains("Usage: ignore <breakpoint> <count>"));
+    assert!(output.contains("Usage: continue\n"));
+    assert!(output.contains("Undefined command: \"frobnicate\".  Try \"help\"."));
+    assert!(output.contains("List of commands:"));
+}
+
+/// `help` describes a command given by its name or an alias.
+#[test]
+fn test_help() {
+    let program = compile_sample("count");
+    let output = run_deet(&program, &["help bt", "help nonsense"]);
+    assert!(
+        output.contains("Print the stack of the selected thread.\nUsage: backtrace\nAliases: bt")
+    );
+    assert!(output.contains("Undefined command: \"nonsense\".  Try \"help\"."));
+}
+
+/// Breakpoints can be set at a line of a given file.
+#[test]
+fn test_break_file_line() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(&program, &["break function_calls.c:12", "run", "p sum"]);
+    assert!(output.contains("Stopped at "));
+    assert!(output.contains("function_calls.c: 12"));
+    assert!(output.contains("sum = 47"));
+}
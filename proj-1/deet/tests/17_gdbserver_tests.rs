@@ -0,0 +1,139 @@
+mod common;
+
+use common::{compile_sample, start_gdbserver, GdbServer};
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Command;
+
+/// A minimal GDB Remote Serial Protocol client.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn connect(server: &GdbServer) -> Client {
+        let stream = TcpStream::connect(("localhost", server.port)).expect("Could not connect");
+        Client { stream }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.stream
+            .read_exact(&mut byte)
+            .expect("Connection closed");
+        byte[0]
+    }
+
+    /// Sends `data` as a packet, for which the server has no reply.
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        assert_eq!(
+            self.read_byte(),
+            b'+',
+            "Packet {} was not acknowledged",
+            data
+        );
+    }
+
+    /// Sends `data` as a packet and returns the contents of the reply.
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let mut sum = [0u8; 2];
+        self.stream.read_exact(&mut sum).unwrap();
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    /// Returns the value of register `index` in the `g` packet, one of the 8-byte ones.
+    fn register(&mut self, index: usize) -> u64 {
+        let regs = self.request("g");
+        let hex = &regs[index * 16..(index + 1) * 16];
+        let bytes: Vec<u8> = (0..8)
+            .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+            .collect();
+        u64::from_le_bytes(bytes[..].try_into().unwrap())
+    }
+}
+
+const RAX: usize = 0;
+const RSP: usize = 7;
+const RIP: usize = 16;
+
+/// Returns the runtime address of `main` in the inferior: the program is loaded at the start of
+/// its first mapping, and `nm` gives the offset of `main` from there.
+fn main_address(program: &Path, pid: i32) -> u64 {
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid)).unwrap();
+    let base = u64::from_str_radix(maps.split('-').next().unwrap(), 16).unwrap();
+    let symbols = Command::new("nm")
+        .arg(program)
+        .output()
+        .expect("Could not run nm");
+    let symbols = String::from_utf8_lossy(&symbols.stdout).into_owned();
+    let offset = symbols
+        .lines()
+        .find(|line| line.ends_with(" T main"))
+        .and_then(|line| u64::from_str_radix(line.split(' ').next()?, 16).ok())
+        .expect("Could not find main");
+    base + offset
+}
+
+/// Registers and memory can be read and written.
+#[test]
+fn test_registers_and_memory() {
+    let program = compile_sample("count");
+    let mut server = start_gdbserver(&program);
+    let mut client = Client::connect(&server);
+    assert!(client
+        .request("qSupported:multiprocess+")
+        .starts_with("PacketSize="));
+    assert_eq!(client.request("?"), "S05");
+    // 17 registers of 8 bytes, then 7 of 4 bytes
+    assert_eq!(client.request("g").len(), (17 * 8 + 7 * 4) * 2);
+
+    let mut regs = client.request("g");
+    regs.replace_range(0..16, "8877665544332211");
+    assert_eq!(client.request(&format!("G{}", regs)), "OK");
+    assert_eq!(client.register(RAX), 0x1122334455667788);
+
+    let addr = client.register(RSP) - 64;
+    assert_eq!(client.request(&format!("M{:x},4:deadbeef", addr)), "OK");
+    assert_eq!(client.request(&format!("m{:x},4", addr)), "deadbeef");
+    assert_eq!(client.request("m0,4"), "E01");
+
+    client.send("k");
+    server.child.wait().unwrap();
+}
+
+/// A breakpoint stops the inferior without showing up in memory, and the inferior can be
+/// stepped and continued to the end.
+#[test]
+fn test_breakpoint_step_continue() {
+    let program = compile_sample("count");
+    let mut server = start_gdbserver(&program);
+    let main = main_address(&program, server.pid);
+    let mut client = Client::connect(&server);
+
+    assert_eq!(client.request(&format!("Z0,{:x},1", main)), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.register(RIP), main);
+    assert_ne!(client.request(&format!("m{:x},1", main)), "cc");
+
+    assert_eq!(client.request("s"), "S05");
+    assert_ne!(client.register(RIP), main);
+    assert_eq!(client.request(&format!("z0,{:x},1", main)), "OK");
+    assert_eq!(client.request("c"), "W00");
+
+    client.send("k");
+    server.child.wait().unwrap();
+}
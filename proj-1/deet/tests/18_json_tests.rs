@@ -0,0 +1,86 @@
+mod common;
+
+use common::{compile_sample, run_deet_json};
+
+/// Returns the first event that contains all of `parts`.
+fn find_event<'a>(events: &'a [String], parts: &[&str]) -> Option<&'a String> {
+    events
+        .iter()
+        .find(|event| parts.iter().all(|part| event.contains(part)))
+}
+
+/// Every line deet prints in JSON mode is an event object.
+#[test]
+fn test_json_events() {
+    let program = compile_sample("function_calls");
+    let events = run_deet_json(
+        &program,
+        &[
+            r#"{"id":1,"command":"break","arguments":["func2"]}"#,
+            r#"{"id":2,"command":"run"}"#,
+            r#"{"id":3,"command":"backtrace"}"#,
+            r#"{"id":4,"command":"print a + b"}"#,
+            r#"{"id":5,"command":"continue"}"#,
+        ],
+    );
+    assert!(events.iter().all(|event| event.starts_with("{\"event\":")));
+    // The dump of the program's debugging information is only for people
+    assert!(find_event(&events, &["Global variables:"]).is_none());
+    assert!(find_event(
+        &events,
+        &[
+            r#""event":"breakpoint-created""#,
+            r#""id":0"#,
+            r#""location":"func2""#
+        ]
+    )
+    .is_some());
+    assert!(find_event(
+        &events,
+        &[
+            r#""event":"stopped""#,
+            r#""reason":"breakpoint-hit""#,
+            r#""signal":"SIGTRAP""#,
+            r#""breakpoint":0"#,
+            r#""function":"func2""#,
+            r#""line":10"#,
+        ]
+    )
+    .is_some());
+    let backtrace = find_event(&events, &[r#""event":"backtrace""#]).expect("No backtrace");
+    assert!(backtrace.contains(r#""level":0,"#));
+    assert!(backtrace.contains(r#""function":"func1""#));
+    assert!(backtrace.contains(r#""function":"main""#));
+    assert!(find_event(&events, &[r#""event":"value""#, r#""expression":"a + b""#]).is_some());
+    assert!(find_event(&events, &[r#""event":"output""#, r#""text":"sum = 47\n""#]).is_some());
+    assert!(find_event(&events, &[r#""event":"exited""#, r#""code":0"#]).is_some());
+    for id in 1..=5 {
+        let done = format!(r#"{{"event":"done","id":{}}}"#, id);
+        assert!(events.contains(&done), "No {}", done);
+    }
+}
+
+/// Requests that cannot be carried out are answered with an error event.
+#[test]
+fn test_json_errors() {
+    let program = compile_sample("count");
+    let events = run_deet_json(
+        &program,
+        &[
+            "not json",
+            r#"{"id":"a","command":"frobnicate"}"#,
+            r#"{"id":"b"}"#,
+        ],
+    );
+    assert!(find_event(&events, &[r#""event":"error""#, r#""id":null"#]).is_some());
+    assert!(find_event(
+        &events,
+        &[
+            r#""event":"error""#,
+            r#""id":"a""#,
+            r#"Undefined command: \"frobnicate\"."#,
+        ]
+    )
+    .is_some());
+    assert!(find_event(&events, &[r#""event":"error""#, r#""id":"b""#]).is_some());
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::{compile_sample, run_deet_dap, run_deet_dap_raw};
+use std::path::Path;
+
+/// Replays the transcript `tests/dap/<sample>.txt` against `deet --dap`, with `${program}` and
+/// `${source}` standing for the compiled sample and its source file. The requests on lines
+/// starting with "->" are sent in order, and the text on each line starting with "<-" must be
+/// found in one of the messages deet sends back: the same one as for the line before, or a later
+/// one.
+fn replay(sample: &str) {
+    let program = compile_sample(sample);
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = manifest_dir.join("samples").join(format!("{}.c", sample));
+    let transcript = std::fs::read_to_string(
+        manifest_dir
+            .join("tests/dap")
+            .join(format!("{}.txt", sample)),
+    )
+    .expect("Could not read transcript")
+    .replace("${program}", &program.to_string_lossy())
+    .replace("${source}", &source.to_string_lossy());
+
+    let requests: Vec<String> = transcript
+        .lines()
+        .filter_map(|line| line.strip_prefix("-> "))
+        .map(String::from)
+        .collect();
+    let messages = run_deet_dap(&requests);
+    let mut current = 0;
+    for expected in transcript
+        .lines()
+        .filter_map(|line| line.strip_prefix("<- "))
+    {
+        match messages[current..]
+            .iter()
+            .position(|message| message.contains(expected))
+        {
+            Some(offset) => current += offset,
+            None => panic!(
+                "No message containing {} in:\n{}",
+                expected,
+                messages[current..].join("\n")
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_dap_function_calls() {
+    replay("function_calls");
+}
+
+#[test]
+fn test_dap_segfault() {
+    replay("segfault");
+}
+
+/// A message whose Content-Length header is missing, not a number or too large ends the session
+/// with an error rather than being skipped.
+#[test]
+fn test_dap_bad_content_length() {
+    let output = run_deet_dap_raw("Content-Length: ten\r\n\r\n{}");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("bad Content-Length \"ten\""));
+
+    let output = run_deet_dap_raw("Content-Type: application/json\r\n\r\n{}");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("message has no Content-Length header")
+    );
+
+    let output = run_deet_dap_raw("Content-Length: 99999999999\r\n\r\n{}");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is over the limit"));
+}
+
+/// A broken message from the client still kills the program it launched.
+#[test]
+fn test_dap_bad_message_kills_program() {
+    let program = compile_sample("sleepy_print");
+    let requests = [
+        format!(
+            r#"{{"seq":1,"type":"request","command":"launch","arguments":{{"program":"{}","args":["30"]}}}}"#,
+            program.display()
+        ),
+        r#"{"seq":2,"type":"request","command":"setBreakpoints","arguments":{"source":{"path":"sleepy_print.c"},"breakpoints":[{"line":18}]}}"#.to_string(),
+        r#"{"seq":3,"type":"request","command":"configurationDone"}"#.to_string(),
+        r#"{"seq":4,"type":"request","command":"threads"}"#.to_string(),
+    ];
+    let mut input: String = requests
+        .iter()
+        .map(|request| format!("Content-Length: {}\r\n\r\n{}", request.len(), request))
+        .collect();
+    input += "Content-Length: ten\r\n\r\n";
+    let output = run_deet_dap_raw(&input);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lwp = stdout
+        .split("(LWP ")
+        .nth(1)
+        .and_then(|rest| rest.split(')').next())
+        .unwrap_or_else(|| panic!("No thread in:\n{}", stdout));
+    assert!(
+        !Path::new("/proc").join(lwp).exists(),
+        "Process {} is still running",
+        lwp
+    );
+}
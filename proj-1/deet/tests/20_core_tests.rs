@@ -0,0 +1,56 @@
+mod common;
+
+use common::{compile_sample, run_deet, run_deet_with_args, scratch_file};
+use std::ffi::OsStr;
+
+/// A core file written when the program crashes shows where it crashed when opened later.
+#[test]
+fn test_generate_and_open_core() {
+    let program = compile_sample("segfault");
+    let core = scratch_file("core");
+    let output = run_deet(
+        &program,
+        &["run", &format!("generate-core-file {}", core.display())],
+    );
+    assert!(output.contains(&format!("Saved corefile {}", core.display())));
+
+    let output = run_deet_with_args(
+        &[program.as_os_str(), OsStr::new("--core"), core.as_os_str()],
+        &[
+            "backtrace",
+            "print a",
+            "info registers rip",
+            "up",
+            "print a",
+            "continue",
+            "set var a = 3",
+        ],
+    );
+    std::fs::remove_file(&core).unwrap();
+    assert!(output.contains(&format!("Core was generated by `{}'.", program.display())));
+    assert!(output.contains("Program terminated with signal SIGSEGV, Segmentation fault."));
+    assert!(output.contains("#0  func2 (a=2) at "));
+    assert!(output.contains(" in func1 (a=42) at "));
+    assert!(output.contains(" in main () at "));
+    assert!(output.contains("a = 2\n"));
+    assert!(output.contains("<func2+"));
+    assert!(output.contains("a = 42\n"));
+    assert!(output.contains("The program is not being run."));
+    assert!(output.contains("Cannot access memory at address"));
+}
+
+/// Files that are not core dumps are refused.
+#[test]
+fn test_open_invalid_core() {
+    let program = compile_sample("segfault");
+    let output = run_deet_with_args(
+        &[
+            program.as_os_str(),
+            OsStr::new("--core"),
+            program.as_os_str(),
+        ],
+        &["backtrace"],
+    );
+    assert!(output.contains(": not an x86-64 core file."));
+    assert!(output.contains("No processes are running!") || output.contains("No stack."));
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::{compile_sample, run_deet};
+
+/// Returns the part of `output` after `fragment`, failing if it is not there.
+fn after<'a>(output: &'a str, fragment: &str) -> &'a str {
+    match output.find(fragment) {
+        Some(pos) => &output[pos + fragment.len()..],
+        None => panic!("{:?} not found in:\n{}", fragment, output),
+    }
+}
+
+/// `reverse-continue` goes back to the breakpoint before, and stops at the start of the log.
+#[test]
+fn test_reverse_continue() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func1",
+            "break func3",
+            "run",
+            "reverse-stepi",
+            "record",
+            "continue",
+            "print a",
+            "reverse-continue",
+            "print a",
+            "info record",
+            "reverse-continue",
+            "continue",
+            "print a",
+            "record stop",
+            "record stop",
+        ],
+    );
+    let output = after(
+        &output,
+        "No recording is currently active.\nUse the \"record\" command first.",
+    );
+    let output = after(output, "function_calls.c: 6\n");
+    let output = after(output, "a = 100\n");
+    let output = after(output, "function_calls.c: 17\n");
+    let output = after(output, "a = 42\n");
+    let output = after(output, "Log contains 0 instructions.");
+    let output = after(output, "No more reverse-execution history.");
+    let output = after(output, "function_calls.c: 6\n");
+    let output = after(
+        output,
+        "Process record is stopped and all execution logs are deleted.",
+    );
+    after(output, "No recording is currently active.");
+}
+
+/// `reverse-step` goes back a line at a time, undoing what the lines wrote to memory, and the
+/// program can run forward again from there.
+#[test]
+fn test_reverse_step() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func2",
+            "run",
+            "record",
+            "next",
+            "next",
+            "print sum",
+            "reverse-step",
+            "print sum",
+            "reverse-step",
+            "reverse-step",
+            "step",
+            "step",
+            "print sum",
+            "step",
+            "step",
+            "reverse-step",
+            "reverse-step",
+            "bt",
+        ],
+    );
+    let output = after(&output, "function_calls.c: 12\n");
+    let output = after(output, "sum = 47\n");
+    let output = after(output, "function_calls.c: 11\n");
+    let output = after(output, "sum = ");
+    assert!(!output.starts_with("47\n"));
+    let output = after(output, "function_calls.c: 10\n");
+    let output = after(output, "No more reverse-execution history.");
+    let output = after(output, "function_calls.c: 12\n");
+    let output = after(output, "sum = 47\n");
+    let output = after(output, "function_calls.c: 6\n");
+    // Back out of func3 into the line that called it, then into the end of the printf line
+    let output = after(output, "function_calls.c: 13\n");
+    let output = after(output, "function_calls.c: 12\n");
+    after(output, "#0  func2 (a=42, b=5) at ");
+}
+
+/// Going back over a breakpoint doesn't count as hitting it, or use up its ignore count.
+#[test]
+fn test_reverse_continue_keeps_counts() {
+    let program = compile_sample("function_calls");
+    let output = run_deet(
+        &program,
+        &[
+            "break func1",
+            "break func3",
+            "run",
+            "record",
+            "continue",
+            "ignore 0 1",
+            "reverse-continue",
+            "info breakpoints",
+        ],
+    );
+    let output = after(&output, "Will ignore next 1 crossings of breakpoint 0.\n");
+    let output = after(output, "function_calls.c: 17\n");
+    let output = after(output, "in func1 at ");
+    let output = after(output, "\tbreakpoint already hit 1 time(s)\n");
+    let output = after(output, "\twill ignore next 1 crossings of breakpoint\n");
+    let output = after(output, "in func3 at ");
+    after(output, "\tbreakpoint already hit 1 time(s)\n");
+}
@@ -0,0 +1,282 @@
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+fn target_dir() -> PathBuf {
+    let mut path = std::env::current_exe().expect("Could not get current test executable path");
+    path.pop();
+    path.pop();
+    path
+}
+
+/// Compiles `samples/<name>.c` into the target directory and returns the path of the binary.
+/// The source is passed to gcc by its absolute path, since deet matches line numbers against
+/// the compilation unit name.
+#[allow(dead_code)]
+pub fn compile_sample(name: &str) -> PathBuf {
+    compile(&format!("{}.c", name), name, &[])
+}
+
+/// Compiles `samples/<name>.c` like `compile_sample`, but letting gcc leave out the frame
+/// pointer, into a binary of its own, and returns its path.
+#[allow(dead_code)]
+pub fn compile_sample_without_frame_pointer(name: &str) -> PathBuf {
+    compile(
+        &format!("{}.c", name),
+        &format!("{}-nofp", name),
+        &["-fomit-frame-pointer"],
+    )
+}
+
+/// Compiles `samples/lib<library>.c` into a shared library in the target directory, then
+/// `samples/<name>.c` linked against it, and returns the path of the binary. The binary finds
+/// the library through its rpath.
+#[allow(dead_code)]
+pub fn compile_sample_with_library(name: &str, library: &str) -> PathBuf {
+    let lib_name = format!("lib{}", library);
+    compile(
+        &format!("{}.c", lib_name),
+        &format!("{}.so", lib_name),
+        &["-shared", "-fPIC"],
+    );
+    let dir = target_dir().join("samples");
+    let lib_dir = format!("-L{}", dir.display());
+    let lib = format!("-l{}", library);
+    let rpath = format!("-Wl,-rpath,{}", dir.display());
+    compile(&format!("{}.c", name), name, &[&lib_dir, &lib, &rpath])
+}
+
+/// Compiles `samples/<source>` into `<output>` in the target directory, passing `extra_args` to
+/// gcc after the source.
+fn compile(source: &str, output: &str, extra_args: &[&str]) -> PathBuf {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("samples")
+        .join(source);
+    let dir = target_dir().join("samples");
+    std::fs::create_dir_all(&dir).expect("Could not create samples directory");
+    let binary = dir.join(output);
+    // Several tests may compile the same sample at once, so build into a private file and
+    // rename it into place
+    let scratch = dir.join(scratch_file(output).file_name().unwrap());
+    // deet reads DWARF 4, which newer versions of gcc no longer emit by default
+    let status = Command::new("gcc")
+        .args(&[
+            "-O0",
+            "-g",
+            "-gdwarf-4",
+            "-fno-omit-frame-pointer",
+            "-pthread",
+            "-o",
+        ])
+        .arg(&scratch)
+        .arg(&source)
+        .args(extra_args)
+        .status()
+        .expect("Could not run gcc");
+    assert!(status.success(), "Could not compile {}", source.display());
+    std::fs::rename(&scratch, &binary).expect("Could not move compiled sample into place");
+    binary
+}
+
+/// Returns a file in the target directory that no other test is using.
+#[allow(dead_code)]
+pub fn scratch_file(name: &str) -> PathBuf {
+    target_dir().join(format!(
+        "{}.{}.{}",
+        name,
+        std::process::id(),
+        NEXT_SCRATCH.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+/// Returns a command that runs deet, with the command history kept out of the user's home
+/// directory.
+fn deet_command() -> Command {
+    let home = target_dir().join("deet-home");
+    std::fs::create_dir_all(&home).expect("Could not create home directory");
+    let mut command = Command::new(target_dir().join("deet"));
+    command
+        .env("HOME", &home)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    command
+}
+
+/// Runs deet on `program`, feeding it `commands` one per line, and returns everything it
+/// printed to stdout.
+#[allow(dead_code)]
+pub fn run_deet(program: &Path, commands: &[&str]) -> String {
+    run_deet_with_args(&[program.as_os_str()], commands)
+}
+
+/// Runs deet with `args`, feeding it `commands` one per line, and returns everything it printed
+/// to stdout.
+#[allow(dead_code)]
+pub fn run_deet_with_args(args: &[&OsStr], commands: &[&str]) -> String {
+    let mut child = deet_command()
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("Could not execute deet binary");
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        for command in commands {
+            writeln!(stdin, "{}", command).unwrap();
+        }
+    }
+    let output = child.wait_with_output().expect("Could not wait for deet");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Runs deet on `program` with `--interpreter=json`, feeding it `requests` one per line, and
+/// returns the events it printed, one per line.
+#[allow(dead_code)]
+pub fn run_deet_json(program: &Path, requests: &[&str]) -> Vec<String> {
+    let mut child = deet_command()
+        .arg("--interpreter=json")
+        .arg(program)
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("Could not execute deet binary");
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        for request in requests {
+            writeln!(stdin, "{}", request).unwrap();
+        }
+    }
+    let output = child.wait_with_output().expect("Could not wait for deet");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+/// Runs `deet --dap`, sending it each of `requests` as a Debug Adapter Protocol message, and
+/// returns the bodies of the messages it sent back.
+#[allow(dead_code)]
+pub fn run_deet_dap(requests: &[String]) -> Vec<String> {
+    let mut child = deet_command()
+        .arg("--dap")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("Could not execute deet binary");
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        for request in requests {
+            write!(
+                stdin,
+                "Content-Length: {}\r\n\r\n{}",
+                request.len(),
+                request
+            )
+            .unwrap();
+        }
+    }
+    let output = child.wait_with_output().expect("Could not wait for deet");
+    let mut rest = &output.stdout[..];
+    let mut messages = Vec::new();
+    while !rest.is_empty() {
+        let header_end = rest
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .expect("Message without a header");
+        let length: usize = String::from_utf8_lossy(&rest[..header_end])
+            .strip_prefix("Content-Length: ")
+            .and_then(|length| length.parse().ok())
+            .expect("Message without a Content-Length header");
+        let body = &rest[header_end + 4..header_end + 4 + length];
+        messages.push(String::from_utf8_lossy(body).into_owned());
+        rest = &rest[header_end + 4 + length..];
+    }
+    messages
+}
+
+/// Runs `deet --dap`, feeding it `input` as is, and returns how it exited and what it printed.
+#[allow(dead_code)]
+pub fn run_deet_dap_raw(input: &str) -> Output {
+    let mut child = deet_command()
+        .arg("--dap")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Could not execute deet binary");
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("Could not wait for deet")
+}
+
+/// Writes `lines` to a script and returns its path.
+#[allow(dead_code)]
+pub fn write_script(lines: &[&str]) -> PathBuf {
+    let path = scratch_file("script");
+    std::fs::write(&path, lines.join("\n")).expect("Could not write script");
+    path
+}
+
+/// Runs deet on `program` in batch mode with `-x script`, and returns everything it printed to
+/// stdout.
+#[allow(dead_code)]
+pub fn run_deet_script(program: &Path, script: &[&str]) -> String {
+    let path = write_script(script);
+    let output = deet_command()
+        .arg("-x")
+        .arg(&path)
+        .arg("--batch")
+        .arg(program)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Could not execute deet binary");
+    std::fs::remove_file(&path).ok();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// A `deet --gdbserver` process waiting for a client.
+#[allow(dead_code)]
+pub struct GdbServer {
+    pub child: Child,
+    pub port: u16,
+    /// Process id of the inferior.
+    pub pid: i32,
+    /// Kept open so that deet can go on printing.
+    _stdout: BufReader<ChildStdout>,
+}
+
+/// Starts `deet --gdbserver` on `program`, listening on a free port on localhost.
+#[allow(dead_code)]
+pub fn start_gdbserver(program: &Path) -> GdbServer {
+    let mut child = deet_command()
+        .arg("--gdbserver")
+        .arg("localhost:0")
+        .arg(program)
+        .stdin(Stdio::null())
+        .spawn()
+        .expect("Could not execute deet binary");
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut pid = None;
+    loop {
+        let mut line = String::new();
+        if stdout.read_line(&mut line).unwrap() == 0 {
+            child.wait().unwrap();
+            panic!("deet exited before listening");
+        }
+        if let Some(rest) = line.trim().split("; pid = ").nth(1) {
+            pid = rest.parse().ok();
+        }
+        if let Some(port) = line.trim().strip_prefix("Listening on port ") {
+            return GdbServer {
+                child,
+                port: port.parse().unwrap(),
+                pid: pid.expect("deet did not say which process it started"),
+                _stdout: stdout,
+            };
+        }
+    }
+}